@@ -0,0 +1,122 @@
+use anyhow::{bail, Error};
+use syn::{Attribute, Path};
+
+use crate::field::{flag_attr, noproto_attrs, path_attr, set_option};
+
+/// Options set on the message struct itself, e.g. `#[noproto(debug)]`.
+#[derive(Clone, Default)]
+pub struct Container {
+    pub debug: bool,
+    /// Generate a `defmt::Format` impl, for `#[noproto(defmt)]`.
+    pub defmt: bool,
+    pub tests: bool,
+    pub wire_eq: bool,
+    /// Number fields without a tag attribute sequentially, for `#[noproto(auto_tags)]`.
+    pub auto_tags: bool,
+    /// Generate a module with tag constants, for `#[noproto(tags)]`.
+    pub tags: bool,
+    /// Generate typed field path selectors, for `#[noproto(fields)]`.
+    pub fields: bool,
+    /// Pack all repeated scalar fields, as proto3 does, for `#[noproto(packed)]`.
+    pub packed: bool,
+    pub before_write: Option<Path>,
+    pub after_read: Option<Path>,
+    /// Wire message decoded then converted to `Self`, for `#[noproto(from = "Wire")]`.
+    pub from: Option<Path>,
+    /// Wire message `Self` is converted to for encoding, for `#[noproto(into = "Wire")]`.
+    pub into: Option<Path>,
+}
+
+impl Container {
+    pub fn new(attrs: Vec<Attribute>) -> Result<Self, Error> {
+        let attrs = noproto_attrs(attrs);
+
+        let mut debug = None;
+        let mut defmt = None;
+        let mut no_fmt = None;
+        let mut tests = None;
+        let mut wire_eq = None;
+        let mut auto_tags = None;
+        let mut tags = None;
+        let mut fields = None;
+        let mut packed = None;
+        let mut before_write = None;
+        let mut after_read = None;
+        let mut from = None;
+        let mut into = None;
+        let mut unknown_attrs = Vec::new();
+
+        for attr in &attrs {
+            if flag_attr(attr, "debug") {
+                set_option(&mut debug, true, "duplicate debug attribute")?;
+            } else if flag_attr(attr, "defmt") {
+                set_option(&mut defmt, true, "duplicate defmt attribute")?;
+            } else if flag_attr(attr, "no_fmt") {
+                set_option(&mut no_fmt, true, "duplicate no_fmt attribute")?;
+            } else if flag_attr(attr, "tests") {
+                set_option(&mut tests, true, "duplicate tests attribute")?;
+            } else if flag_attr(attr, "wire_eq") {
+                set_option(&mut wire_eq, true, "duplicate wire_eq attribute")?;
+            } else if flag_attr(attr, "auto_tags") {
+                set_option(&mut auto_tags, true, "duplicate auto_tags attribute")?;
+            } else if flag_attr(attr, "tags") {
+                set_option(&mut tags, true, "duplicate tags attribute")?;
+            } else if flag_attr(attr, "fields") {
+                set_option(&mut fields, true, "duplicate fields attribute")?;
+            } else if flag_attr(attr, "packed") {
+                set_option(&mut packed, true, "duplicate packed attribute")?;
+            } else if let Some(x) = path_attr(attr, "before_write")? {
+                set_option(&mut before_write, x, "duplicate before_write attribute")?;
+            } else if let Some(x) = path_attr(attr, "after_read")? {
+                set_option(&mut after_read, x, "duplicate after_read attribute")?;
+            } else if let Some(x) = path_attr(attr, "from")? {
+                set_option(&mut from, x, "duplicate from attribute")?;
+            } else if let Some(x) = path_attr(attr, "into")? {
+                set_option(&mut into, x, "duplicate into attribute")?;
+            } else {
+                unknown_attrs.push(attr);
+            }
+        }
+
+        match unknown_attrs.len() {
+            0 => (),
+            1 => bail!("unknown attribute: {:?}", unknown_attrs[0]),
+            _ => bail!("unknown attributes: {:?}", unknown_attrs),
+        }
+
+        let debug = debug.unwrap_or(false);
+        // Generated code only uses `core::fmt` when explicitly requested, `no_fmt` guarantees it
+        // by rejecting the options that do.
+        let no_fmt = no_fmt.unwrap_or(false);
+        if debug && no_fmt {
+            bail!("debug can't be combined with no_fmt, the Debug impl uses core::fmt");
+        }
+        let tests = tests.unwrap_or(false);
+        if tests && no_fmt {
+            bail!("tests can't be combined with no_fmt, the generated tests use core::fmt");
+        }
+
+        // The wire message does all the encoding, options about the fields wouldn't apply.
+        match (&from, &into) {
+            (None, None) => (),
+            (Some(_), Some(_)) if attrs.len() == 2 => (),
+            (Some(_), Some(_)) => bail!("from and into can't be combined with other attributes"),
+            _ => bail!("from and into attributes must be used together"),
+        }
+
+        Ok(Self {
+            debug,
+            defmt: defmt.unwrap_or(false),
+            tests,
+            wire_eq: wire_eq.unwrap_or(false),
+            auto_tags: auto_tags.unwrap_or(false),
+            tags: tags.unwrap_or(false),
+            fields: fields.unwrap_or(false),
+            packed: packed.unwrap_or(false),
+            before_write,
+            after_read,
+            from,
+            into,
+        })
+    }
+}
@@ -1,7 +1,11 @@
 use std::fmt;
 
 use anyhow::{bail, Error};
-use syn::{Attribute, Lit, Meta, MetaList, MetaNameValue, NestedMeta};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    Attribute, Expr, GenericArgument, Lit, Meta, MetaList, MetaNameValue, NestedMeta, Path, PathArguments, Type, TypePath,
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Kind {
@@ -9,21 +13,86 @@ pub enum Kind {
     Repeated,
     Optional,
     Oneof,
+    /// Fields of another message encoded inline, for `#[noproto(extend)]`.
+    Extend,
+}
+
+/// Integer encoding selected by a field attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Int32,
+    Int64,
+    Fixed,
+    SFixed,
+    RawLe,
+}
+
+/// Conversion between the wire type and the field type, for `#[noproto(convert(...))]`.
+#[derive(Clone)]
+pub struct Convert {
+    /// Type encoded on the wire.
+    pub wire: Type,
+    /// Type of the field, if given.
+    pub value: Option<Type>,
+    /// Module with the `to_wire` and `from_wire` functions.
+    pub with: Path,
+}
+
+impl fmt::Debug for Convert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let with = &self.with;
+        write!(f, "convert(with = {})", quote!(#with))
+    }
 }
 
 #[derive(Clone)]
 pub struct Field {
+    pub ty: Type,
     pub kind: Kind,
     pub tags: Vec<u32>,
+    pub redact: bool,
+    pub enumeration: Option<Path>,
+    /// Index in the message's presence bitmap, for `#[noproto(has_bit)]` fields.
+    /// Assigned by the message, in declaration order.
+    pub has_bit: Option<usize>,
+    /// Codec for `#[noproto(compress)]` fields.
+    pub compress: Option<Path>,
+    /// Integer encoding, e.g. `#[noproto(int32)]`.
+    pub encoding: Option<Encoding>,
+    /// Encode the message with the group (delimited) wire format, for `#[noproto(group)]`.
+    pub group: bool,
+    /// Unit or type conversion applied when writing and reading.
+    pub convert: Option<Convert>,
+    /// Value of the field when absent from the wire, for `#[noproto(default = "expr")]`.
+    pub default: Option<Expr>,
+    /// Fail decoding if the field is absent, for `#[noproto(required)]`.
+    pub required: bool,
+    /// Module with the `write` and `read` functions, for `#[noproto(with = "module")]`.
+    pub with: Option<Path>,
+    /// Pack scalar elements of a repeated field, for `#[noproto(packed)]`.
+    pub packed: bool,
 }
 
 impl Field {
-    pub fn new(attrs: Vec<Attribute>) -> Result<Self, Error> {
+    /// Parse a field. With `auto_tag`, the tag attribute is optional, `tags` is left empty
+    /// without it for the message to assign one.
+    pub fn new(ty: Type, attrs: Vec<Attribute>, auto_tag: bool) -> Result<Self, Error> {
         let attrs = noproto_attrs(attrs);
 
         let mut tag = None;
         let mut tags = None;
         let mut kind = None;
+        let mut redact = None;
+        let mut enumeration = None;
+        let mut has_bit = None;
+        let mut compress = None;
+        let mut encoding = None;
+        let mut group = None;
+        let mut convert = None;
+        let mut default = None;
+        let mut required = None;
+        let mut with = None;
+        let mut packed = None;
         let mut unknown_attrs = Vec::new();
 
         for attr in &attrs {
@@ -33,6 +102,30 @@ impl Field {
                 set_option(&mut tags, x, "duplicate tags attributes")?;
             } else if let Some(x) = kind_attr(attr) {
                 set_option(&mut kind, x, "duplicate kind attribute")?;
+            } else if flag_attr(attr, "redact") {
+                set_option(&mut redact, true, "duplicate redact attribute")?;
+            } else if let Some(x) = path_attr(attr, "enumeration")? {
+                set_option(&mut enumeration, x, "duplicate enumeration attribute")?;
+            } else if flag_attr(attr, "has_bit") {
+                set_option(&mut has_bit, 0, "duplicate has_bit attribute")?;
+            } else if flag_attr(attr, "compress") {
+                set_option(&mut compress, syn::parse_quote!(::noproto::compress::Lz), "duplicate compress attribute")?;
+            } else if let Some(x) = path_attr(attr, "compress")? {
+                set_option(&mut compress, x, "duplicate compress attribute")?;
+            } else if let Some(x) = encoding_attr(attr) {
+                set_option(&mut encoding, x, "duplicate encoding attribute")?;
+            } else if flag_attr(attr, "group") {
+                set_option(&mut group, true, "duplicate group attribute")?;
+            } else if let Some(x) = convert_attr(attr)? {
+                set_option(&mut convert, x, "duplicate convert attribute")?;
+            } else if let Some(x) = expr_attr(attr, "default")? {
+                set_option(&mut default, x, "duplicate default attribute")?;
+            } else if flag_attr(attr, "required") {
+                set_option(&mut required, true, "duplicate required attribute")?;
+            } else if let Some(x) = path_attr(attr, "with")? {
+                set_option(&mut with, x, "duplicate with attribute")?;
+            } else if flag_attr(attr, "packed") {
+                set_option(&mut packed, true, "duplicate packed attribute")?;
             } else {
                 unknown_attrs.push(attr);
             }
@@ -44,8 +137,13 @@ impl Field {
             _ => bail!("unknown attributes: {:?}", unknown_attrs),
         }
 
-        let kind = kind.unwrap_or(Kind::Single);
+        let kind = kind.unwrap_or_else(|| infer_kind(&ty, tags.is_some()));
+        if kind == Kind::Extend && attrs.len() > 1 {
+            bail!("extend attribute can't be combined with other attributes");
+        }
         let tags = match kind {
+            // The part's fields have their own tags.
+            Kind::Extend => Vec::new(),
             Kind::Oneof => {
                 if tag.is_some() {
                     bail!("tag attribute must not be set in oneof.")
@@ -57,21 +155,397 @@ impl Field {
             }
             _ => match tag {
                 Some(tag) => vec![tag],
+                None if auto_tag => Vec::new(),
                 None => bail!("missing tag attribute"),
             },
         };
 
-        Ok(Self { tags, kind })
+        if enumeration.is_some() && kind != Kind::Single {
+            bail!("enumeration attribute is only supported on single fields");
+        }
+        if has_bit.is_some() && (kind != Kind::Single || enumeration.is_some()) {
+            bail!("has_bit attribute is only supported on single fields without enumeration");
+        }
+        if compress.is_some() && (kind != Kind::Single || enumeration.is_some()) {
+            bail!("compress attribute is only supported on single string or bytes fields");
+        }
+        if encoding.is_some() && (kind != Kind::Single || enumeration.is_some() || compress.is_some()) {
+            bail!("encoding attributes are only supported on single numeric fields");
+        }
+        let group = group.unwrap_or(false);
+        if group && (kind == Kind::Oneof || enumeration.is_some() || compress.is_some() || encoding.is_some()) {
+            bail!("group attribute is only supported on message fields");
+        }
+        let other_encoding = enumeration.is_some() || compress.is_some() || encoding.is_some() || group;
+        if convert.is_some() && (kind != Kind::Single || other_encoding) {
+            bail!("convert attribute is only supported on single fields without other encoding attributes");
+        }
+        if with.is_some() && (kind != Kind::Single || other_encoding || convert.is_some()) {
+            bail!("with attribute is only supported on single fields without other encoding attributes");
+        }
+        let packed = packed.unwrap_or(false);
+        if packed && (kind != Kind::Repeated || group) {
+            bail!("packed attribute is only supported on repeated fields");
+        }
+
+        let required = required.unwrap_or(false);
+        if required && (kind != Kind::Single || has_bit.is_some()) {
+            bail!("required attribute is only supported on single fields without has_bit");
+        }
+
+        Ok(Self {
+            ty,
+            tags,
+            kind,
+            redact: redact.unwrap_or(false),
+            enumeration,
+            has_bit,
+            compress,
+            encoding,
+            group,
+            convert,
+            default,
+            required,
+            with,
+            packed,
+        })
+    }
+
+    /// Type the field is encoded as, if different from the Rust field type.
+    pub fn wire_ty(&self) -> Option<TokenStream> {
+        let ty = &self.ty;
+        if let Some(convert) = &self.convert {
+            let wire = &convert.wire;
+            return Some(quote!(#wire));
+        }
+        match self.encoding {
+            Some(Encoding::Int32) => return Some(quote!(::noproto::types::Int32)),
+            Some(Encoding::Int64) => return Some(quote!(::noproto::types::Int64)),
+            Some(Encoding::Fixed) => return Some(quote!(<#ty as ::noproto::types::AsFixed>::Wire)),
+            Some(Encoding::SFixed) => return Some(quote!(<#ty as ::noproto::types::AsSFixed>::Wire)),
+            Some(Encoding::RawLe) => return Some(quote!(::noproto::types::RawLe<#ty>)),
+            None => {}
+        }
+        self.enumeration.as_ref().map(|_| quote!(::noproto::types::Int32))
+    }
+
+    /// Statement encoding the field `ident` into `w`.
+    pub fn write(&self, ident: &TokenStream) -> TokenStream {
+        if self.kind == Kind::Extend {
+            // The part's fields, without a header of their own.
+            return quote!(::noproto::Message::write_raw(&#ident, w)?;);
+        }
+        let tag = self.tags[0];
+        if let Some(with) = &self.with {
+            return quote!(#with::write(w, #tag, &#ident)?;);
+        }
+        if let Some(convert) = &self.convert {
+            let (wire, with) = (&convert.wire, &convert.with);
+            return quote!(w.write_field::<#wire>(#tag, &#with::to_wire(&#ident))?;);
+        }
+        if let Some(wire_ty) = self.wire_ty() {
+            return quote!(w.write_field(#tag, &<#wire_ty>::from(#ident))?;);
+        }
+        if let Some(codec) = &self.compress {
+            return quote!(::noproto::compress::write::<#codec, _>(w, #tag, &#ident)?;);
+        }
+        if self.group {
+            return match self.kind {
+                Kind::Repeated => quote!(w.write_repeated_group(#tag, &#ident)?;),
+                Kind::Optional => quote!(w.write_optional_group(#tag, &#ident)?;),
+                _ => quote!(w.write_group(#tag, &#ident)?;),
+            };
+        }
+        match self.kind {
+            Kind::Single => quote!(w.write_field(#tag, &#ident)?;),
+            Kind::Repeated if self.packed => quote!(w.write_packed(#tag, &#ident)?;),
+            Kind::Repeated => quote!(w.write_repeated(#tag, &#ident)?;),
+            Kind::Optional => quote!(w.write_optional(#tag, &#ident)?;),
+            Kind::Oneof => quote!(w.write_oneof(&#ident)?;),
+            Kind::Extend => unreachable!(),
+        }
+    }
+
+    /// Statement encoding the field `ident` back to front into `w`, a `ReverseWriter`.
+    pub fn write_reverse(&self, ident: &TokenStream) -> TokenStream {
+        if self.kind == Kind::Extend {
+            return quote!(::noproto::Message::write_reverse(&#ident, w)?;);
+        }
+        if self.compress.is_some() || self.with.is_some() {
+            let write = self.write(ident);
+            return quote!(w.write_forward(|w| { #write Ok(()) })?;);
+        }
+        // Same methods as the forward `ByteWriter`.
+        self.write(ident)
+    }
+
+    /// Statement decoding field reader `r` into the field `ident`.
+    pub fn read(&self, ident: &TokenStream) -> TokenStream {
+        if let Some(with) = &self.with {
+            return quote!(#with::read(r, &mut #ident)?;);
+        }
+        if let Some(convert) = &self.convert {
+            let (wire, with) = (&convert.wire, &convert.with);
+            let value_ty = convert.value.as_ref().unwrap_or(&self.ty);
+            return quote! {
+                let mut value = <#wire>::default();
+                r.read(&mut value)?;
+                #ident = {
+                    let value: #value_ty = #with::from_wire(value);
+                    value
+                };
+            };
+        }
+        if let Some(wire_ty) = self.wire_ty() {
+            return quote! {
+                let mut value = <#wire_ty>::default();
+                r.read(&mut value)?;
+                #ident = value.into();
+            };
+        }
+        if let Some(codec) = &self.compress {
+            return quote!(::noproto::compress::read::<#codec, _>(r, &mut #ident)?;);
+        }
+        if self.group {
+            return match self.kind {
+                Kind::Repeated => quote!(r.read_repeated_group(&mut #ident)?;),
+                Kind::Optional => quote!(r.read_optional_group(&mut #ident)?;),
+                _ => quote!(r.read_group(&mut #ident)?;),
+            };
+        }
+        match self.kind {
+            Kind::Single => quote!(r.read(&mut #ident)?;),
+            Kind::Repeated => quote!(r.read_repeated(&mut #ident)?;),
+            Kind::Optional => quote!(r.read_optional(&mut #ident)?;),
+            Kind::Oneof => quote!(r.read_oneof(&mut #ident)?;),
+            Kind::Extend => quote!(::noproto::Part::read_field(&mut #ident, r)?;),
+        }
+    }
+
+    /// Statement checking the invariants of the field `ident`.
+    pub fn validate(&self, ident: &TokenStream) -> TokenStream {
+        if let Some(enumeration) = &self.enumeration {
+            return quote! {
+                <#enumeration as ::core::convert::TryFrom<i32>>::try_from(#ident)
+                    .map_err(|_| ::noproto::ValidationError::Enum)?;
+            };
+        }
+        if let Some(convert) = &self.convert {
+            let (wire, with) = (&convert.wire, &convert.with);
+            return quote!(<#wire as ::noproto::Message>::validate(&#with::to_wire(&#ident))?;);
+        }
+        if self.wire_ty().is_some() || self.with.is_some() {
+            // Plain numbers, any value is valid. The `with` module checks its values when reading.
+            return quote!();
+        }
+        match self.kind {
+            Kind::Single => quote!(::noproto::Message::validate(&#ident)?;),
+            Kind::Repeated => quote!(::noproto::RepeatedMessage::validate(&#ident)?;),
+            Kind::Optional => quote!(::noproto::OptionalMessage::validate(&#ident)?;),
+            Kind::Oneof => quote!(::noproto::Oneof::validate(&#ident)?;),
+            Kind::Extend => quote!(::noproto::Message::validate(&#ident)?;),
+        }
+    }
+
+    /// Expression for the upper bound on the encoded length of this field.
+    pub fn max_len(&self) -> TokenStream {
+        let ty = &self.ty;
+        if self.kind == Kind::Extend {
+            return quote!(<#ty as ::noproto::Message>::MAX_ENCODED_LEN);
+        }
+        let tag = self.tags[0];
+        if self.with.is_some() {
+            // Up to the `with` module, unbounded.
+            return quote!(usize::MAX);
+        }
+        if let Some(wire_ty) = self.wire_ty() {
+            return quote!(::noproto::encoding::max_field_len::<#wire_ty>(#tag));
+        }
+        if let Some(codec) = &self.compress {
+            return quote!(::noproto::compress::max_field_len::<#codec, #ty>(#tag));
+        }
+        if self.group {
+            return match self.kind {
+                Kind::Repeated => quote!(::noproto::encoding::max_repeated_group_len::<#ty>(#tag)),
+                Kind::Optional => {
+                    quote!(::noproto::encoding::max_group_len::<<#ty as ::noproto::OptionalMessage>::Message>(#tag))
+                }
+                _ => quote!(::noproto::encoding::max_group_len::<#ty>(#tag)),
+            };
+        }
+        match self.kind {
+            Kind::Single => quote!(::noproto::encoding::max_field_len::<#ty>(#tag)),
+            Kind::Repeated if self.packed => quote!(::noproto::encoding::max_packed_len::<#ty>(#tag)),
+            Kind::Repeated => quote!(::noproto::encoding::max_repeated_len::<#ty>(#tag)),
+            Kind::Optional => {
+                quote!(::noproto::encoding::max_field_len::<<#ty as ::noproto::OptionalMessage>::Message>(#tag))
+            }
+            Kind::Oneof => quote!(<#ty as ::noproto::Oneof>::MAX_ENCODED_LEN),
+            Kind::Extend => unreachable!(),
+        }
+    }
+
+    /// Expression for whether encoding this field can fail with a large enough buffer.
+    pub fn fallible_write(&self) -> TokenStream {
+        let ty = &self.ty;
+        if self.with.is_some() {
+            // Up to the `with` module.
+            return quote!(true);
+        }
+        if let Some(wire_ty) = self.wire_ty() {
+            return quote!(<#wire_ty as ::noproto::Message>::FALLIBLE_WRITE);
+        }
+        if self.compress.is_some() {
+            return quote!(false);
+        }
+        match self.kind {
+            Kind::Single | Kind::Extend => quote!(<#ty as ::noproto::Message>::FALLIBLE_WRITE),
+            Kind::Repeated => {
+                quote!(<<#ty as ::noproto::RepeatedMessage>::Message as ::noproto::Message>::FALLIBLE_WRITE)
+            }
+            Kind::Optional => {
+                quote!(<<#ty as ::noproto::OptionalMessage>::Message as ::noproto::Message>::FALLIBLE_WRITE)
+            }
+            Kind::Oneof => quote!(<#ty as ::noproto::Oneof>::FALLIBLE_WRITE),
+        }
+    }
+
+    /// Expression for the `FieldDescriptor` of this field. Not valid for oneofs and parts.
+    pub fn descriptor(&self, name: &str) -> TokenStream {
+        let ty = &self.ty;
+        let tag = self.tags[0];
+        let (label, message, max_count) = match self.kind {
+            Kind::Single if self.has_bit.is_some() => (quote!(Optional), quote!(#ty), quote!(1)),
+            Kind::Single => (quote!(Single), quote!(#ty), quote!(1)),
+            Kind::Repeated => (
+                quote!(Repeated),
+                quote!(<#ty as ::noproto::RepeatedMessage>::Message),
+                quote!(<#ty as ::noproto::RepeatedMessage>::MAX_LEN),
+            ),
+            Kind::Optional => (
+                quote!(Optional),
+                quote!(<#ty as ::noproto::OptionalMessage>::Message),
+                quote!(1),
+            ),
+            Kind::Oneof | Kind::Extend => unreachable!(),
+        };
+        let redact = self.redact;
+        let required = self.required;
+        let group = self.group;
+        if self.with.is_some() {
+            return quote! {
+                ::noproto::descriptor::FieldDescriptor {
+                    name: #name,
+                    tag: #tag,
+                    label: ::noproto::descriptor::Label::#label,
+                    ty: ::noproto::descriptor::Type::Unknown,
+                    max_len: usize::MAX,
+                    max_count: 1,
+                    redacted: #redact,
+                    required: #required,
+                    compressed: false,
+                    group: false,
+                }
+            };
+        }
+        if let Some(codec) = &self.compress {
+            // Described as the compressed payload, as it is on the wire.
+            return quote! {
+                ::noproto::descriptor::FieldDescriptor {
+                    name: #name,
+                    tag: #tag,
+                    label: ::noproto::descriptor::Label::#label,
+                    ty: ::noproto::descriptor::Type::Bytes,
+                    max_len: ::noproto::compress::max_len::<#codec, #ty>(),
+                    max_count: 1,
+                    redacted: #redact,
+                    required: #required,
+                    compressed: true,
+                    group: false,
+                }
+            };
+        }
+        let (message, descriptor_ty) = match (&self.enumeration, self.wire_ty()) {
+            (Some(enumeration), Some(wire_ty)) => (wire_ty, quote!(#enumeration)),
+            (None, Some(wire_ty)) => (wire_ty.clone(), wire_ty),
+            _ => (message.clone(), message),
+        };
+        quote! {
+            ::noproto::descriptor::FieldDescriptor {
+                name: #name,
+                tag: #tag,
+                label: ::noproto::descriptor::Label::#label,
+                ty: <#descriptor_ty as ::noproto::Message>::TYPE,
+                max_len: <#message as ::noproto::Message>::MAX_ENCODED_LEN,
+                max_count: #max_count,
+                redacted: #redact,
+                required: #required,
+                compressed: false,
+                group: #group,
+            }
+        }
+    }
+
+    /// Return type and body of the selector method of this field, for `#[noproto(fields)]`,
+    /// extending the path `self.0` from messages of type `__Root`. `None` for oneofs and parts.
+    pub fn selector(&self) -> Option<(TokenStream, TokenStream)> {
+        let ty = &self.ty;
+        if self.kind == Kind::Extend {
+            return None;
+        }
+        let tag = self.tags[0];
+        // Fields encoded other than by their type are selected as a whole.
+        if self.wire_ty().is_some() || self.compress.is_some() || self.with.is_some() {
+            return Some((quote!(::noproto::path::FieldPath<__Root, #ty>), quote!(self.0.__push(#tag))));
+        }
+        let elem = match self.kind {
+            Kind::Single => quote!(#ty),
+            Kind::Repeated => quote!(<#ty as ::noproto::RepeatedMessage>::Message),
+            Kind::Optional => quote!(<#ty as ::noproto::OptionalMessage>::Message),
+            Kind::Oneof => return None,
+            Kind::Extend => unreachable!(),
+        };
+        Some((
+            quote!(<#elem as ::noproto::path::Select>::Fields<__Root>),
+            quote!(::core::convert::From::from(self.0.__push::<#elem>(#tag))),
+        ))
+    }
+
+    /// Trait bound the field type must satisfy.
+    pub fn bound(&self) -> TokenStream {
+        let ty = &self.ty;
+        if self.with.is_some() {
+            // Any type the `with` module accepts.
+            return quote!(#ty: ::core::marker::Sized);
+        }
+        if let Some(convert) = &self.convert {
+            let wire = &convert.wire;
+            return quote!(#wire: ::noproto::Message + ::core::default::Default);
+        }
+        if let Some(wire_ty) = self.wire_ty() {
+            return quote!(#wire_ty: ::core::convert::From<#ty> + ::core::convert::Into<#ty>);
+        }
+        if self.compress.is_some() {
+            return quote!(#ty: ::noproto::compress::Payload);
+        }
+        match self.kind {
+            Kind::Single => quote!(#ty: ::noproto::Message),
+            Kind::Repeated => quote!(#ty: ::noproto::RepeatedMessage),
+            Kind::Optional => quote!(#ty: ::noproto::OptionalMessage),
+            Kind::Oneof => quote!(#ty: ::noproto::Oneof),
+            Kind::Extend => quote!(#ty: ::noproto::Part),
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct OneofVariant {
+    pub ty: Type,
     pub tag: u32,
 }
 
 impl OneofVariant {
-    pub fn new(attrs: Vec<Attribute>) -> Result<Self, Error> {
+    pub fn new(ty: Type, attrs: Vec<Attribute>) -> Result<Self, Error> {
         let attrs = noproto_attrs(attrs);
 
         let mut tag = None;
@@ -96,7 +570,7 @@ impl OneofVariant {
             None => bail!("missing tag attribute"),
         };
 
-        Ok(Self { tag })
+        Ok(Self { ty, tag })
     }
 }
 
@@ -154,17 +628,149 @@ fn tags_attr(attr: &Meta) -> Result<Option<Vec<u32>>, Error> {
 fn kind_attr(attr: &Meta) -> Option<Kind> {
     let Meta::Path(ref path) = *attr else { return None };
 
-    if path.is_ident("repeated") {
+    if path.is_ident("single") {
+        Some(Kind::Single)
+    } else if path.is_ident("repeated") {
         Some(Kind::Repeated)
     } else if path.is_ident("optional") {
         Some(Kind::Optional)
     } else if path.is_ident("oneof") {
         Some(Kind::Oneof)
+    } else if path.is_ident("extend") {
+        Some(Kind::Extend)
     } else {
         None
     }
 }
 
+/// Infer the kind of a field without kind attribute from its type.
+///
+/// Fields with a `tags` attribute are oneofs, `Option<T>` is optional and `Vec<T, N>` repeated,
+/// except `Vec<u8, N>` which is `bytes`. Anything else, including type aliases, is single.
+fn infer_kind(ty: &Type, has_tags: bool) -> Kind {
+    if has_tags {
+        return Kind::Oneof;
+    }
+    let Type::Path(TypePath { qself: None, path }) = ty else {
+        return Kind::Single;
+    };
+    let Some(segment) = path.segments.last() else {
+        return Kind::Single;
+    };
+    let first_arg = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args.args.first(),
+        _ => None,
+    };
+    match first_arg {
+        Some(GenericArgument::Type(_)) if segment.ident == "Option" => Kind::Optional,
+        Some(GenericArgument::Type(Type::Path(elem))) if segment.ident == "Vec" && elem.path.is_ident("u8") => {
+            Kind::Single
+        }
+        Some(GenericArgument::Type(_)) if segment.ident == "Vec" => Kind::Repeated,
+        _ => Kind::Single,
+    }
+}
+
+/// Parse an attribute selecting the wire encoding of an integer field, e.g. `#[noproto(int32)]`.
+fn encoding_attr(attr: &Meta) -> Option<Encoding> {
+    let Meta::Path(ref path) = *attr else { return None };
+
+    if path.is_ident("int32") {
+        Some(Encoding::Int32)
+    } else if path.is_ident("int64") {
+        Some(Encoding::Int64)
+    } else if path.is_ident("fixed") {
+        Some(Encoding::Fixed)
+    } else if path.is_ident("sfixed") {
+        Some(Encoding::SFixed)
+    } else if path.is_ident("raw_le_bytes") {
+        Some(Encoding::RawLe)
+    } else {
+        None
+    }
+}
+
+/// Parse a conversion attribute, e.g.
+/// `#[noproto(convert(from = "i32", to = "Celsius", with = "millidegrees"))]`.
+fn convert_attr(attr: &Meta) -> Result<Option<Convert>, Error> {
+    if !attr.path().is_ident("convert") {
+        return Ok(None);
+    }
+    let Meta::List(ref meta_list) = *attr else {
+        bail!("invalid convert attribute: {:?}", attr);
+    };
+
+    let mut wire = None;
+    let mut value = None;
+    let mut with = None;
+    for item in &meta_list.nested {
+        let NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+            ref path,
+            lit: Lit::Str(ref lit),
+            ..
+        })) = *item
+        else {
+            bail!("invalid convert attribute: {:?}", attr);
+        };
+        if path.is_ident("from") {
+            set_option(&mut wire, lit.value(), "duplicate convert from")?;
+        } else if path.is_ident("to") {
+            set_option(&mut value, lit.value(), "duplicate convert to")?;
+        } else if path.is_ident("with") {
+            set_option(&mut with, lit.value(), "duplicate convert with")?;
+        } else {
+            bail!("unknown convert attribute: {:?}", path);
+        }
+    }
+
+    let (Some(wire), Some(with)) = (wire, with) else {
+        bail!("convert attribute needs `from` and `with`");
+    };
+    Ok(Some(Convert {
+        wire: syn::parse_str(&wire)?,
+        value: value.map(|v| syn::parse_str(&v)).transpose()?,
+        with: syn::parse_str(&with)?,
+    }))
+}
+
+/// Parse a path given as a string attribute, e.g. `#[noproto(enumeration = "MyEnum")]`.
+pub(crate) fn path_attr(attr: &Meta, name: &str) -> Result<Option<Path>, Error> {
+    if !attr.path().is_ident(name) {
+        return Ok(None);
+    }
+    match *attr {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(ref lit), ..
+        }) => Ok(Some(lit.parse()?)),
+        _ => bail!("invalid {} attribute: {:?}", name, attr),
+    }
+}
+
+/// Parse an expression given as a string attribute, e.g. `#[noproto(default = "5")]`.
+fn expr_attr(attr: &Meta, name: &str) -> Result<Option<Expr>, Error> {
+    if !attr.path().is_ident(name) {
+        return Ok(None);
+    }
+    match *attr {
+        Meta::NameValue(MetaNameValue {
+            lit: Lit::Str(ref lit), ..
+        }) => Ok(Some(lit.parse()?)),
+        _ => bail!("invalid {} attribute: {:?}", name, attr),
+    }
+}
+
+/// Check if a field is the presence bitmap, marked `#[noproto(presence)]`.
+pub fn is_presence(attrs: &[Attribute]) -> bool {
+    noproto_attrs(attrs.to_vec())
+        .iter()
+        .any(|attr| flag_attr(attr, "presence"))
+}
+
+/// Check for a bare flag attribute, e.g. `#[noproto(redact)]`.
+pub(crate) fn flag_attr(attr: &Meta, name: &str) -> bool {
+    matches!(*attr, Meta::Path(ref path) if path.is_ident(name))
+}
+
 pub fn set_option<T: fmt::Debug>(option: &mut Option<T>, value: T, message: &str) -> Result<(), Error> {
     if let Some(ref existing) = *option {
         bail!("{}: {:?} and {:?}", message, existing, value);
@@ -174,7 +780,7 @@ pub fn set_option<T: fmt::Debug>(option: &mut Option<T>, value: T, message: &str
 }
 
 /// Get the items belonging to the 'noproto' list attribute, e.g. `#[noproto(foo, bar="baz")]`.
-fn noproto_attrs(attrs: Vec<Attribute>) -> Vec<Meta> {
+pub(crate) fn noproto_attrs(attrs: Vec<Attribute>) -> Vec<Meta> {
     attrs
         .iter()
         .flat_map(Attribute::parse_meta)
@@ -13,13 +13,63 @@ use quote::quote;
 use syn::punctuated::Punctuated;
 use syn::{Data, DataEnum, DataStruct, DeriveInput, Expr, Fields, FieldsNamed, FieldsUnnamed, Ident, Index, Variant};
 
+mod container;
 mod field;
+use crate::container::Container;
 use crate::field::Field;
 
+/// `Default` impl using the `default` attribute of the fields that have one.
+fn default_impl(
+    ident: &Ident,
+    generics: &syn::Generics,
+    presence: &Option<(proc_macro2::TokenStream, syn::Type)>,
+    fields: &[(proc_macro2::TokenStream, Field)],
+) -> proc_macro2::TokenStream {
+    let mut generics = generics.clone();
+    let mut values = Vec::new();
+    for (field_ident, field) in fields {
+        let value = match &field.default {
+            Some(default) => quote!(#default),
+            None => {
+                if generics.type_params().next().is_some() {
+                    let ty = &field.ty;
+                    generics
+                        .make_where_clause()
+                        .predicates
+                        .push(syn::parse_quote!(#ty: ::core::default::Default));
+                }
+                quote!(::core::default::Default::default())
+            }
+        };
+        values.push(quote!(#field_ident: #value,));
+    }
+    if let Some((presence, _)) = presence {
+        values.push(quote!(#presence: ::core::default::Default::default(),));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::core::default::Default for #ident #ty_generics #where_clause {
+            fn default() -> Self {
+                Self { #(#values)* }
+            }
+        }
+    }
+}
+
 fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
     let input: DeriveInput = syn::parse(input)?;
 
     let ident = input.ident;
+    let vis = input.vis;
+
+    let container = match Container::new(input.attrs) {
+        Ok(container) => container,
+        Err(err) => return Err(err.context(format!("invalid message {}", ident))),
+    };
+
+    if let (Some(from), Some(into)) = (&container.from, &container.into) {
+        return Ok(convert_impl(&ident, input.generics, from, into).into());
+    }
 
     let variant_data = match input.data {
         Data::Struct(variant_data) => variant_data,
@@ -27,10 +77,9 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         Data::Union(..) => bail!("Message can not be derived for a union"),
     };
 
-    let generics = &input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let mut generics = input.generics;
 
-    let (_is_struct, fields) = match variant_data {
+    let (is_struct, fields) = match variant_data {
         DataStruct {
             fields: Fields::Named(FieldsNamed { named: fields, .. }),
             ..
@@ -44,9 +93,25 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         } => (false, Vec::new()),
     };
 
+    // The presence bitmap is not a protobuf field, set it apart.
+    let mut presence = None;
     let mut fields = fields
         .into_iter()
         .enumerate()
+        .filter(|(i, field)| {
+            if !field::is_presence(&field.attrs) {
+                return true;
+            }
+            let field_ident = field.ident.as_ref().map(|x| quote!(#x)).unwrap_or_else(|| {
+                let index = Index {
+                    index: *i as u32,
+                    span: Span::call_site(),
+                };
+                quote!(#index)
+            });
+            presence.get_or_insert((field_ident, field.ty.clone()));
+            false
+        })
         .map(|(i, field)| {
             let field_ident = field.ident.map(|x| quote!(#x)).unwrap_or_else(|| {
                 let index = Index {
@@ -55,18 +120,88 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
                 };
                 quote!(#index)
             });
-            match Field::new(field.attrs) {
+            match Field::new(field.ty, field.attrs, container.auto_tags) {
                 Ok(field) => Ok((field_ident, field)),
                 Err(err) => Err(err.context(format!("invalid message field {}.{}", ident, field_ident))),
             }
         })
         .collect::<Result<Vec<_>, _>>()?;
 
+    if container.packed {
+        for (_, field) in &mut fields {
+            field.packed |= field.kind == Kind::Repeated && !field.group;
+        }
+    }
+
+    // Number fields without tag after the highest tag so far, in declaration order.
+    let mut next_tag = 1;
+    for (_, field) in fields.iter_mut().filter(|(_, field)| field.kind != Kind::Extend) {
+        if field.tags.is_empty() {
+            field.tags.push(next_tag);
+        }
+        next_tag = next_tag.max(field.tags.iter().max().unwrap() + 1);
+    }
+
+    // Assign presence bits in declaration order.
+    let mut num_bits = 0;
+    for (_, field) in &mut fields {
+        if let Some(bit) = &mut field.has_bit {
+            *bit = num_bits;
+            num_bits += 1;
+        }
+    }
+    let presence_check = match (&presence, num_bits) {
+        (_, 0) => None,
+        (None, _) => bail!("message {} has has_bit fields but no presence field", ident),
+        (Some((_, presence_ty)), _) => Some(quote! {
+            const { assert!(#num_bits <= <#presence_ty>::BITS, "too many has_bit fields for presence bitmap") };
+        }),
+    };
+
+    // Field types may depend on type parameters, so require them to implement the right traits.
+    if generics.type_params().next().is_some() {
+        let where_clause = generics.make_where_clause();
+        for (_, field) in &fields {
+            where_clause.predicates.push(syn::parse2(field.bound())?);
+        }
+    }
+    let generics = &generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let tests = if container.tests {
+        if !generics.params.is_empty() {
+            bail!("tests attribute is not supported on generic message {}", ident);
+        }
+        Some(tests_module(&ident))
+    } else {
+        None
+    };
+
+    let wire_eq = if container.wire_eq {
+        if !generics.params.is_empty() {
+            bail!("wire_eq attribute is not supported on generic message {}", ident);
+        }
+        Some(wire_eq_impl(&ident))
+    } else {
+        None
+    };
+
+    // Fields with a `default` attribute need a `Default` impl using it, replacing the derived one.
+    let default = fields
+        .iter()
+        .any(|(_, field)| field.default.is_some())
+        .then(|| default_impl(&ident, generics, &presence, &fields));
+
+    // Debug output lists the fields in declaration order, so build it before sorting.
+    let debug = container.debug.then(|| debug_impl(&ident, generics, is_struct, &fields));
+    let defmt = container.defmt.then(|| defmt_impl(&ident, generics, is_struct, &fields));
+
     // Sort the fields by tag number so that fields will be encoded in tag order.
     // TODO: This encodes oneof fields in the position of their lowest tag,
     // regardless of the currently occupied variant, is that consequential?
     // See: https://developers.google.com/protocol-buffers/docs/encoding#order
-    fields.sort_by_key(|&(_, ref field)| field.tags.iter().copied().min().unwrap());
+    // Parts have no tags of their own, their fields are encoded after the others.
+    fields.sort_by_key(|&(_, ref field)| field.tags.iter().copied().min().unwrap_or(u32::MAX));
     let fields = fields;
 
     let mut tags = fields.iter().flat_map(|(_, field)| &field.tags).collect::<Vec<_>>();
@@ -77,57 +212,671 @@ fn try_message(input: TokenStream) -> Result<TokenStream, Error> {
         bail!("message {} has fields with duplicate tags", ident);
     }
 
-    let write = fields.iter().map(|&(ref field_ident, ref field)| {
-        let tag = field.tags[0];
-        let ident = quote!(self.#field_ident);
-        match field.kind {
-            Kind::Single => quote!(w.write_field(#tag, &#ident)?;),
-            Kind::Repeated => quote!(w.write_repeated(#tag, &#ident)?;),
-            Kind::Optional => quote!(w.write_optional(#tag, &#ident)?;),
-            Kind::Oneof => quote!(w.write_oneof(&#ident)?;),
-        }
-    });
+    // Tuple struct fields have no name to use for their tag constants.
+    let tag_consts = fields
+        .iter()
+        .filter(|_| is_struct)
+        .filter(|(_, field)| !matches!(field.kind, Kind::Oneof | Kind::Extend))
+        .map(|(field_ident, field)| (field_ident.to_string(), field.tags[0]));
+    let tags_module = container.tags.then(|| tags_module(&ident, &vis, tag_consts));
 
-    let read = fields.iter().map(|&(ref field_ident, ref field)| {
-        let ident = quote!(self.#field_ident);
-        let read = match field.kind {
-            Kind::Single => quote!(r.read(&mut #ident)?;),
-            Kind::Repeated => quote!(r.read_repeated(&mut #ident)?;),
-            Kind::Optional => quote!(r.read_optional(&mut #ident)?;),
-            Kind::Oneof => quote!(r.read_oneof(&mut #ident)?;),
-        };
+    let max_lens = fields.iter().map(|(_, field)| field.max_len());
+    let fallible_writes = fields.iter().map(|(_, field)| field.fallible_write());
+    let has_before_write = container.before_write.is_some();
+
+    let field_descriptors = fields
+        .iter()
+        .filter(|(_, field)| !matches!(field.kind, Kind::Oneof | Kind::Extend))
+        .map(|(field_ident, field)| field.descriptor(&field_ident.to_string()));
+    let oneof_descriptors = fields
+        .iter()
+        .filter(|(_, field)| field.kind == Kind::Oneof)
+        .map(|(field_ident, field)| {
+            let name = field_ident.to_string();
+            let ty = &field.ty;
+            quote! {
+                ::noproto::descriptor::OneofDescriptor {
+                    name: #name,
+                    fields: <#ty as ::noproto::Oneof>::FIELDS,
+                }
+            }
+        });
+
+    let write = fields
+        .iter()
+        .map(|&(ref field_ident, ref field)| {
+            let write = field.write(&quote!(self.#field_ident));
+            match (field.has_bit, &presence) {
+                (Some(bit), Some((presence, _))) => quote!(if self.#presence.get(#bit) { #write }),
+                _ => write,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let write_reverse = fields
+        .iter()
+        .rev()
+        .map(|&(ref field_ident, ref field)| {
+            let write = field.write_reverse(&quote!(self.#field_ident));
+            match (field.has_bit, &presence) {
+                (Some(bit), Some((presence, _))) => quote!(if self.#presence.get(#bit) { #write }),
+                _ => write,
+            }
+        })
+        .collect::<Vec<_>>();
 
+    let write_nth = write.iter().enumerate().map(|(i, write)| quote!(#i => { #write }));
+
+    // Required fields are tracked by their index among the required fields.
+    let num_required = fields.iter().filter(|(_, field)| field.required).count();
+    if num_required > 64 {
+        bail!("message {} has more than 64 required fields", ident);
+    }
+    let mut required_index = 0usize..;
+    let mut read_arm = |field_ident: &proc_macro2::TokenStream, field: &Field, track_required: bool| {
+        let mut read = field.read(&quote!(self.#field_ident));
+        if let (Some(bit), Some((presence, _))) = (field.has_bit, &presence) {
+            read = quote!(#read self.#presence.set(#bit););
+        }
+        if field.required && track_required {
+            let i = required_index.next().unwrap();
+            read = quote!(#read required_seen[#i] = true;);
+        }
+
+        if field.kind == Kind::Extend {
+            let ty = &field.ty;
+            return quote!(tag if <#ty as ::noproto::Part>::has_tag(tag) => { #read });
+        }
         let tags = field.tags.iter().map(|&tag| quote!(#tag));
         let tags = Itertools::intersperse(tags, quote!(|));
 
         quote!(#(#tags)* => { #read })
+    };
+    let read = fields
+        .iter()
+        .map(|(field_ident, field)| read_arm(field_ident, field, true))
+        .collect::<Vec<_>>();
+    // Required fields are checked by the message the part is read into.
+    let read_part = fields
+        .iter()
+        .map(|(field_ident, field)| read_arm(field_ident, field, false))
+        .collect::<Vec<_>>();
+
+    let own_tags = fields.iter().flat_map(|(_, field)| &field.tags);
+    let part_tys = fields
+        .iter()
+        .filter(|(_, field)| field.kind == Kind::Extend)
+        .map(|(_, field)| &field.ty)
+        .collect::<Vec<_>>();
+
+    // The fields of parts are listed with the message's own, and their tags checked against them.
+    let (type_descriptor, part_tags_check) = if part_tys.is_empty() {
+        let type_descriptor = quote! {
+            ::noproto::descriptor::Type::Message(&::noproto::descriptor::MessageDescriptor {
+                name: stringify!(#ident),
+                fields: &[#(#field_descriptors),*],
+                oneofs: &[#(#oneof_descriptors),*],
+                max_encoded_len: <Self as ::noproto::Message>::MAX_ENCODED_LEN,
+            })
+        };
+        (type_descriptor, None)
+    } else {
+        // Array lengths can't depend on generic parameters.
+        if !generics.params.is_empty() {
+            bail!("extend fields are not supported on generic message {}", ident);
+        }
+        let type_descriptor = quote! {{
+            use ::noproto::descriptor::{FieldDescriptor, MessageDescriptor, OneofDescriptor};
+            const OWN_FIELDS: &[FieldDescriptor] = &[#(#field_descriptors),*];
+            const OWN_ONEOFS: &[OneofDescriptor] = &[#(#oneof_descriptors),*];
+            const PARTS: &[&MessageDescriptor] = &[
+                #(::noproto::descriptor::__part(<#part_tys as ::noproto::Message>::TYPE)),*
+            ];
+            const FIELDS: [FieldDescriptor; ::noproto::descriptor::__fields_len(OWN_FIELDS, PARTS)] =
+                ::noproto::descriptor::__concat_fields(OWN_FIELDS, PARTS);
+            const ONEOFS: [OneofDescriptor; ::noproto::descriptor::__oneofs_len(OWN_ONEOFS, PARTS)] =
+                ::noproto::descriptor::__concat_oneofs(OWN_ONEOFS, PARTS);
+            ::noproto::descriptor::Type::Message(&MessageDescriptor {
+                name: stringify!(#ident),
+                fields: &FIELDS,
+                oneofs: &ONEOFS,
+                max_encoded_len: <#ident as ::noproto::Message>::MAX_ENCODED_LEN,
+            })
+        }};
+        let check = quote! {
+            const _: () = ::noproto::descriptor::__assert_part_tags(::noproto::descriptor::descriptor::<#ident>());
+        };
+        (type_descriptor, Some(check))
+    };
+
+    let validate = fields
+        .iter()
+        .map(|(field_ident, field)| field.validate(&quote!(self.#field_ident)));
+
+    // Typed accessors for `enumeration` fields. Tuple struct fields have no name to use for them.
+    let accessors = fields.iter().filter(|_| is_struct).filter_map(|(field_ident, field)| {
+        let enumeration = field.enumeration.as_ref()?;
+        let setter = Ident::new(&format!("set_{}", field_ident), Span::call_site());
+        let doc = format!("Get `{}` as `{}`, or the raw value if unknown.", field_ident, quote!(#enumeration));
+        Some(quote! {
+            #[doc = #doc]
+            pub fn #field_ident(&self) -> ::core::result::Result<#enumeration, i32> {
+                #enumeration::try_from(self.#field_ident)
+            }
+
+            #[doc = concat!("Set `", stringify!(#field_ident), "` from an enumeration value.")]
+            pub fn #setter(&mut self, value: #enumeration) {
+                self.#field_ident = value as i32;
+            }
+        })
+    });
+    let has_bit_accessors = fields.iter().filter(|_| is_struct).filter_map(|(field_ident, field)| {
+        let bit = field.has_bit?;
+        let (presence, _) = presence.as_ref()?;
+        let ty = &field.ty;
+        let setter = Ident::new(&format!("set_{}", field_ident), Span::call_site());
+        let clearer = Ident::new(&format!("clear_{}", field_ident), Span::call_site());
+        Some(quote! {
+            #[doc = concat!("Get `", stringify!(#field_ident), "`, if present.")]
+            pub fn #field_ident(&self) -> ::core::option::Option<&#ty> {
+                self.#presence.get(#bit).then_some(&self.#field_ident)
+            }
+
+            #[doc = concat!("Set `", stringify!(#field_ident), "` and mark it present.")]
+            pub fn #setter(&mut self, value: #ty) {
+                self.#field_ident = value;
+                self.#presence.set(#bit);
+            }
+
+            #[doc = concat!("Mark `", stringify!(#field_ident), "` as not present.")]
+            pub fn #clearer(&mut self) {
+                self.#presence.clear(#bit);
+            }
+        })
+    });
+    // Array lengths can't depend on generic parameters, so only non-generic messages get it.
+    let descriptor_proto = generics.params.is_empty().then(|| {
+        quote! {
+            impl #ident {
+                /// Serialized `google.protobuf.DescriptorProto` of this message, see
+                /// [`descriptor_proto`](::noproto::descriptor::descriptor_proto).
+                pub const DESCRIPTOR_PROTO: &'static [u8] = &::noproto::descriptor::descriptor_proto::<
+                    #ident,
+                    { ::noproto::descriptor::descriptor_proto_len::<#ident>() },
+                >();
+            }
+        }
+    });
+
+    let selectors = match container.fields {
+        false => None,
+        true if !generics.params.is_empty() => bail!("fields attribute is not supported on generic messages"),
+        true if !is_struct => bail!("fields attribute is only supported on structs with named fields"),
+        true => Some(selectors(&ident, &vis, &fields)),
+    };
+
+    let accessors = accessors.chain(has_bit_accessors).collect::<Vec<_>>();
+    let accessors = (!accessors.is_empty()).then(|| {
+        quote! {
+            impl #impl_generics #ident #ty_generics #where_clause {
+                #(#accessors)*
+            }
+        }
     });
 
+    let (required_init, required_check) = match num_required {
+        0 => (None, None),
+        _ => (
+            Some(quote!(let mut required_seen = [false; #num_required];)),
+            Some(quote! {
+                if !r.is_partial() && required_seen.contains(&false) {
+                    return Err(::noproto::ReadError::MissingField);
+                }
+            }),
+        ),
+    };
+
+    let before_write = container.before_write.as_ref().map(|f| quote!(#f(self)?;));
+    let after_read = container.after_read.as_ref().map(|f| quote!(#f(self)?;));
+
     let expanded = quote! {
+        #accessors
+
+        #descriptor_proto
+
+        #selectors
+
+        #default
+
         impl #impl_generics ::noproto::Message for #ident #ty_generics #where_clause {
             const WIRE_TYPE: ::noproto::WireType = ::noproto::WireType::LengthDelimited;
+            const MAX_ENCODED_LEN: usize = 0usize #(.saturating_add(#max_lens))*;
+            const FALLIBLE_WRITE: bool = #has_before_write #(|| #fallible_writes)*;
+            const TYPE: ::noproto::descriptor::Type = #type_descriptor;
 
             fn write_raw(&self, w: &mut ::noproto::encoding::ByteWriter) -> Result<(), ::noproto::WriteError> {
+                #presence_check
+                #before_write
                 #(#write)*
                 Ok(())
             }
 
+            fn write_reverse(&self, w: &mut ::noproto::encoding::ReverseWriter) -> Result<(), ::noproto::WriteError> {
+                #before_write
+                #(#write_reverse)*
+                Ok(())
+            }
+
+            fn write_nth_field(&self, index: usize, w: &mut ::noproto::encoding::ByteWriter) -> Result<bool, ::noproto::WriteError> {
+                if index == 0 {
+                    #before_write
+                }
+                match index {
+                    #(#write_nth)*
+                    _ => return Ok(false),
+                }
+                Ok(true)
+            }
+
             fn read_raw(&mut self, r: &mut ::noproto::encoding::ByteReader) -> Result<(), ::noproto::ReadError> {
+                #required_init
                 for r in r.read_fields() {
-                    let r = r?;
-                    match r.tag() {
-                        #(#read)*
-                        _ => {}
-                    }
+                    r?.traced(|r| {
+                        match r.tag() {
+                            #(#read)*
+                            _ => {}
+                        }
+                        Ok(())
+                    })?;
+                }
+                #required_check
+                #after_read
+                Ok(())
+            }
+
+            fn validate(&self) -> Result<(), ::noproto::ValidationError> {
+                #(#validate)*
+                Ok(())
+            }
+        }
+
+        impl #impl_generics ::noproto::Part for #ident #ty_generics #where_clause {
+            fn has_tag(tag: u32) -> bool {
+                <[u32]>::contains(&[#(#own_tags),*], &tag) #(|| <#part_tys as ::noproto::Part>::has_tag(tag))*
+            }
+
+            fn read_field(&mut self, r: ::noproto::encoding::FieldReader) -> Result<(), ::noproto::ReadError> {
+                match r.tag() {
+                    #(#read_part)*
+                    _ => {}
                 }
                 Ok(())
             }
         }
+
+        #part_tags_check
+
+        #tags_module
+
+        #debug
+        #defmt
+
+        #wire_eq
+
+        #tests
     };
 
     Ok(expanded.into())
 }
 
+/// `Message` impl encoding `Self` as the wire message `into` and decoding it from `from`, for
+/// `#[noproto(from = "..", into = "..")]`. The fields of `Self` are ignored.
+fn convert_impl(
+    ident: &Ident,
+    mut generics: syn::Generics,
+    from: &syn::Path,
+    into: &syn::Path,
+) -> proc_macro2::TokenStream {
+    if generics.type_params().next().is_some() {
+        let where_clause = generics.make_where_clause();
+        where_clause.predicates.push(syn::parse_quote! {
+            #from: ::noproto::Message + ::core::default::Default + ::core::convert::Into<Self>
+        });
+        where_clause.predicates.push(syn::parse_quote! {
+            Self: ::core::clone::Clone + ::core::convert::Into<#into>
+        });
+        where_clause.predicates.push(syn::parse_quote!(#into: ::noproto::Message));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let wire = quote!(::core::convert::Into::<#into>::into(::core::clone::Clone::clone(self)));
+
+    quote! {
+        impl #impl_generics ::noproto::Message for #ident #ty_generics #where_clause {
+            const WIRE_TYPE: ::noproto::WireType = <#into as ::noproto::Message>::WIRE_TYPE;
+            const MAX_ENCODED_LEN: usize = <#into as ::noproto::Message>::MAX_ENCODED_LEN;
+            const FALLIBLE_WRITE: bool = <#into as ::noproto::Message>::FALLIBLE_WRITE;
+            const TYPE: ::noproto::descriptor::Type = <#into as ::noproto::Message>::TYPE;
+
+            fn write_raw(&self, w: &mut ::noproto::encoding::ByteWriter) -> Result<(), ::noproto::WriteError> {
+                #wire.write_raw(w)
+            }
+
+            fn write_reverse(&self, w: &mut ::noproto::encoding::ReverseWriter) -> Result<(), ::noproto::WriteError> {
+                #wire.write_reverse(w)
+            }
+
+            fn encoded_len(&self) -> Result<usize, ::noproto::WriteError> {
+                #wire.encoded_len()
+            }
+
+            fn write_nth_field(&self, index: usize, w: &mut ::noproto::encoding::ByteWriter) -> Result<bool, ::noproto::WriteError> {
+                #wire.write_nth_field(index, w)
+            }
+
+            // Decoding replaces the value instead of merging into it, the message read may not be
+            // the one written.
+            fn read_raw(&mut self, r: &mut ::noproto::encoding::ByteReader) -> Result<(), ::noproto::ReadError> {
+                let mut wire = <#from as ::core::default::Default>::default();
+                wire.read_raw(r)?;
+                *self = ::core::convert::Into::into(wire);
+                Ok(())
+            }
+
+            fn validate(&self) -> Result<(), ::noproto::ValidationError> {
+                #wire.validate()
+            }
+        }
+    }
+}
+
+/// Generate the typed field path selector of a message, for `#[noproto(fields)]`.
+fn selectors(ident: &Ident, vis: &syn::Visibility, fields: &[(proc_macro2::TokenStream, Field)]) -> proc_macro2::TokenStream {
+    let selector = Ident::new(&format!("{}Fields", ident), Span::call_site());
+    let doc = format!("Typed paths to the fields of [`{}`], see [`noproto::path`](::noproto::path).", ident);
+    let methods = fields.iter().filter_map(|(field_ident, field)| {
+        let (ty, body) = field.selector()?;
+        Some(quote! {
+            #[doc = concat!("Select `", stringify!(#field_ident), "`.")]
+            pub fn #field_ident(self) -> #ty {
+                #body
+            }
+        })
+    });
+    quote! {
+        #[doc = #doc]
+        #vis struct #selector<__Root>(::noproto::path::FieldPath<__Root, #ident>);
+
+        impl<__Root> ::core::clone::Clone for #selector<__Root> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<__Root> ::core::marker::Copy for #selector<__Root> {}
+
+        impl<__Root> ::core::convert::From<::noproto::path::FieldPath<__Root, #ident>> for #selector<__Root> {
+            fn from(path: ::noproto::path::FieldPath<__Root, #ident>) -> Self {
+                Self(path)
+            }
+        }
+
+        impl<__Root> ::core::ops::Deref for #selector<__Root> {
+            type Target = ::noproto::path::FieldPath<__Root, #ident>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl<__Root> #selector<__Root> {
+            #(#methods)*
+        }
+
+        impl ::noproto::path::Select for #ident {
+            type Fields<__Root> = #selector<__Root>;
+        }
+
+        impl #ident {
+            /// Start a typed path to a field of this message.
+            pub fn fields() -> #selector<#ident> {
+                #selector(::noproto::path::FieldPath::root())
+            }
+        }
+    }
+}
+
+/// Generate a module named after `ident` holding a `tags` module with a constant for each tag.
+fn tags_module(
+    ident: &Ident,
+    vis: &syn::Visibility,
+    tags: impl Iterator<Item = (String, u32)>,
+) -> proc_macro2::TokenStream {
+    let module = module_ident(&snake_case(&ident.to_string()));
+    let doc = format!("Items generated for [`{}`].", ident);
+    let tags_doc = format!("Field tags of [`{}`].", ident);
+    let consts = tags.map(|(name, tag)| {
+        let name = name.trim_start_matches("r#");
+        let doc = format!("Tag of `{}`.", name);
+        let name = Ident::new(&snake_case(name).to_uppercase(), Span::call_site());
+        quote! {
+            #[doc = #doc]
+            pub const #name: u32 = #tag;
+        }
+    });
+    quote! {
+        #[doc = #doc]
+        #vis mod #module {
+            #[doc = #tags_doc]
+            pub mod tags {
+                #(#consts)*
+            }
+        }
+    }
+}
+
+/// Make an identifier for a module named `name`, even if `name` is a keyword.
+fn module_ident(name: &str) -> Ident {
+    match name {
+        // These can't be raw identifiers.
+        "crate" | "self" | "super" => Ident::new(&format!("{}_", name), Span::call_site()),
+        _ => match syn::parse_str::<Ident>(name) {
+            Ok(ident) => ident,
+            Err(_) => Ident::new_raw(name, Span::call_site()),
+        },
+    }
+}
+
+/// Convert a `CamelCase` or `snake_case` name to `snake_case`.
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            out.push(c);
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        }
+    }
+    out
+}
+
+/// Generate `PartialEq`, `Eq` and `Hash` impls comparing the encoded form of a message.
+///
+/// Encoding is deterministic (fields in tag order), so equal encodings mean equal messages on
+/// the wire. Both messages are streamed, without buffers for the whole encoding.
+fn wire_eq_impl(ident: &Ident) -> proc_macro2::TokenStream {
+    quote! {
+        impl ::core::cmp::PartialEq for #ident {
+            fn eq(&self, other: &Self) -> bool {
+                ::noproto::__wire_eq(self, other)
+            }
+        }
+
+        impl ::core::cmp::Eq for #ident {}
+
+        impl ::core::hash::Hash for #ident {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                ::noproto::__wire_hash(self, state)
+            }
+        }
+    }
+}
+
+/// Generate a `#[cfg(test)]` module with baseline tests for a message.
+fn tests_module(ident: &Ident) -> proc_macro2::TokenStream {
+    let module = Ident::new(&format!("noproto_tests_{}", ident), Span::call_site());
+    quote! {
+        #[cfg(test)]
+        #[allow(non_snake_case)]
+        mod #module {
+            use super::*;
+
+            const BUF_LEN: usize = {
+                let max = <#ident as ::noproto::Message>::MAX_ENCODED_LEN;
+                if max < 4096 {
+                    max
+                } else {
+                    4096
+                }
+            };
+
+            #[test]
+            fn default_round_trip() {
+                let mut buf = [0u8; BUF_LEN];
+                let msg = <#ident as ::core::default::Default>::default();
+                let n = ::noproto::write(&msg, &mut buf).unwrap();
+                let msg: #ident = ::noproto::read(&buf[..n]).unwrap();
+                let mut buf2 = [0u8; BUF_LEN];
+                let n2 = ::noproto::write(&msg, &mut buf2).unwrap();
+                assert_eq!(&buf[..n], &buf2[..n2]);
+            }
+
+            #[test]
+            fn empty_decodes_to_default() {
+                let mut buf = [0u8; BUF_LEN];
+                let msg: #ident = ::noproto::read(&[]).unwrap();
+                let n = ::noproto::write(&msg, &mut buf).unwrap();
+                let mut buf2 = [0u8; BUF_LEN];
+                let n2 = ::noproto::write(&<#ident as ::core::default::Default>::default(), &mut buf2).unwrap();
+                assert_eq!(&buf[..n], &buf2[..n2]);
+            }
+
+            #[test]
+            fn default_within_max_encoded_len() {
+                let mut buf = [0u8; BUF_LEN];
+                let n = ::noproto::write(&<#ident as ::core::default::Default>::default(), &mut buf).unwrap();
+                assert!(n <= <#ident as ::noproto::Message>::MAX_ENCODED_LEN);
+            }
+
+            #[test]
+            fn random_round_trip() {
+                let mut rng = ::noproto::testing::Rng::new(0);
+                let mut scratch = [0u8; BUF_LEN];
+                for _ in 0..16 {
+                    let msg: #ident = match ::noproto::testing::random_message(&mut rng, &mut scratch) {
+                        Ok(msg) => msg,
+                        // Larger than the buffer, or with fields the generator can't produce.
+                        Err(_) => continue,
+                    };
+                    let mut buf = [0u8; BUF_LEN];
+                    let n = ::noproto::write(&msg, &mut buf).unwrap();
+                    assert!(n <= <#ident as ::noproto::Message>::MAX_ENCODED_LEN);
+                    let msg: #ident = ::noproto::read(&buf[..n]).unwrap();
+                    let mut buf2 = [0u8; BUF_LEN];
+                    let n2 = ::noproto::write(&msg, &mut buf2).unwrap();
+                    assert_eq!(&buf[..n], &buf2[..n2]);
+                }
+            }
+        }
+    }
+}
+
+/// Generate a `Debug` impl for a message, masking fields marked `#[noproto(redact)]`.
+fn debug_impl(
+    ident: &Ident,
+    generics: &syn::Generics,
+    is_struct: bool,
+    fields: &[(proc_macro2::TokenStream, Field)],
+) -> proc_macro2::TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let values = fields.iter().map(|(field_ident, field)| {
+        if field.redact {
+            quote!(&::core::format_args!("<redacted>"))
+        } else if is_struct && field.has_bit.is_some() {
+            quote!(&self.#field_ident())
+        } else {
+            quote!(&self.#field_ident)
+        }
+    });
+
+    let body = if is_struct {
+        let names = fields.iter().map(|(field_ident, _)| field_ident.to_string());
+        quote!(f.debug_struct(stringify!(#ident)) #(.field(#names, #values))* .finish())
+    } else {
+        quote!(f.debug_tuple(stringify!(#ident)) #(.field(#values))* .finish())
+    };
+
+    quote! {
+        impl #impl_generics ::core::fmt::Debug for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                #body
+            }
+        }
+    }
+}
+
+/// Generate a `defmt::Format` impl for a message, masking fields marked `#[noproto(redact)]`.
+///
+/// Refers to `defmt` by name, like `#[derive(defmt::Format)]`, so the user crate depends on it.
+fn defmt_impl(
+    ident: &Ident,
+    generics: &syn::Generics,
+    is_struct: bool,
+    fields: &[(proc_macro2::TokenStream, Field)],
+) -> proc_macro2::TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    // The redacted values are part of the format string, so they are never sent.
+    let mut format = ident.to_string();
+    let mut values = Vec::new();
+    for (i, (field_ident, field)) in fields.iter().enumerate() {
+        format += match (i, is_struct) {
+            (0, true) => " {{ ",
+            (0, false) => "(",
+            _ => ", ",
+        };
+        if is_struct {
+            format += &format!("{}: ", field_ident);
+        }
+        if field.redact {
+            format += "<redacted>";
+        } else {
+            format += "{}";
+            values.push(match is_struct && field.has_bit.is_some() {
+                true => quote!(self.#field_ident()),
+                false => quote!(self.#field_ident),
+            });
+        }
+    }
+    if !fields.is_empty() {
+        format += if is_struct { " }}" } else { ")" };
+    }
+
+    quote! {
+        impl #impl_generics defmt::Format for #ident #ty_generics #where_clause {
+            fn format(&self, f: defmt::Formatter<'_>) {
+                defmt::write!(f, #format #(, #values)*)
+            }
+        }
+    }
+}
+
 #[proc_macro_derive(Message, attributes(noproto))]
 pub fn message(input: TokenStream) -> TokenStream {
     try_message(input).unwrap()
@@ -174,6 +923,16 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
 
     let _default = variants[0].0.clone();
 
+    let try_from = variants
+        .iter()
+        .map(|(variant, value)| quote!(#value => Ok(#ident::#variant)));
+
+    let values = variants
+        .iter()
+        .map(|(variant, value)| quote!((stringify!(#variant), (#value) as i32)));
+
+    let discriminants = variants.iter().map(|(_, value)| value);
+
     let _is_valid = variants.iter().map(|&(_, ref value)| quote!(#value => true));
 
     let write = variants
@@ -188,22 +947,48 @@ fn try_enumeration(input: TokenStream) -> Result<TokenStream, Error> {
         impl #impl_generics  ::noproto::Message for #ident #ty_generics #where_clause {
 
             const WIRE_TYPE: ::noproto::WireType = ::noproto::WireType::Varint;
+            // Negative values are sign-extended to 64 bits, as for `int32`.
+            const MAX_ENCODED_LEN: usize = {
+                let mut len = 5;
+                #(
+                    if (#discriminants as i32) < 0 {
+                        len = 10;
+                    }
+                )*
+                len
+            };
+            const TYPE: ::noproto::descriptor::Type = ::noproto::descriptor::Type::Enum(&::noproto::descriptor::EnumDescriptor {
+                name: stringify!(#ident),
+                values: &[#(#values),*],
+            });
 
             fn write_raw(&self, w: &mut ::noproto::encoding::ByteWriter) -> Result<(), ::noproto::WriteError> {
-                let val = match self {
+                let val: i32 = match self {
                     #(#write,)*
                 };
-                w.write_varuint32(*self as _)
+                w.write_varuint64(val as i64 as u64)
             }
 
             fn read_raw(&mut self, r: &mut ::noproto::encoding::ByteReader) -> Result<(), ::noproto::ReadError> {
-                *self = match r.read_varuint32()? {
+                // Truncated to 32 bits, as for `int32`.
+                *self = match r.read_varuint64()? as i32 {
                     #(#read,)*
-                    _ => return Err(::noproto::ReadError),
+                    _ => return Err(::noproto::ReadError::InvalidEnumValue),
                 };
                 Ok(())
             }
         }
+
+        impl #impl_generics ::core::convert::TryFrom<i32> for #ident #ty_generics #where_clause {
+            type Error = i32;
+
+            fn try_from(value: i32) -> Result<Self, i32> {
+                match value {
+                    #(#try_from,)*
+                    _ => Err(value),
+                }
+            }
+        }
     };
 
     Ok(expanded.into())
@@ -218,6 +1003,7 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
     let input: DeriveInput = syn::parse(input)?;
 
     let ident = input.ident;
+    let vis = input.vis;
 
     let variants = match input.data {
         Data::Enum(DataEnum { variants, .. }) => variants,
@@ -225,6 +1011,15 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         Data::Union(..) => bail!("Oneof can not be derived for a union"),
     };
 
+    // `tags` is the only option of oneofs.
+    let mut tags_attr = None;
+    for attr in field::noproto_attrs(input.attrs) {
+        if !field::flag_attr(&attr, "tags") {
+            bail!("invalid oneof {}: unknown attribute: {:?}", ident, attr);
+        }
+        field::set_option(&mut tags_attr, true, "duplicate tags attribute")?;
+    }
+
     let generics = &input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
@@ -246,7 +1041,8 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             bail!("Oneof enum variants must have a single field");
         }
 
-        match OneofVariant::new(attrs) {
+        let ty = variant_fields.into_iter().next().unwrap().ty;
+        match OneofVariant::new(ty, attrs) {
             Ok(variant) => oneof_variants.push((variant_ident, variant)),
             Err(err) => bail!("invalid oneof variant {}.{}: {}", ident, variant_ident, err),
         }
@@ -259,27 +1055,91 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
         panic!("invalid oneof {}: variants have duplicate tags", ident);
     }
 
+    let max_lens = oneof_variants.iter().map(|(_, variant)| {
+        let ty = &variant.ty;
+        let tag = variant.tag;
+        quote!(::noproto::encoding::max_field_len::<#ty>(#tag))
+    });
+
+    let field_descriptors = oneof_variants.iter().map(|(variant_ident, variant)| {
+        let name = variant_ident.to_string();
+        let ty = &variant.ty;
+        let tag = variant.tag;
+        quote! {
+            ::noproto::descriptor::FieldDescriptor {
+                name: #name,
+                tag: #tag,
+                label: ::noproto::descriptor::Label::Optional,
+                ty: <#ty as ::noproto::Message>::TYPE,
+                max_len: <#ty as ::noproto::Message>::MAX_ENCODED_LEN,
+                max_count: 1,
+                redacted: false,
+                required: false,
+                compressed: false,
+                group: false,
+            }
+        }
+    });
+
+    let tags_module = tags_attr.is_some().then(|| {
+        tags_module(
+            &ident,
+            &vis,
+            oneof_variants.iter().map(|(variant_ident, variant)| (variant_ident.to_string(), variant.tag)),
+        )
+    });
+
     let write = oneof_variants.iter().map(|(variant_ident, variant)| {
         let tag = variant.tag;
         quote!(#ident::#variant_ident(value) => { w.write_field(#tag, value)?; })
     });
+    // `ReverseWriter` has the same methods, so the same code writes back to front.
+    let write = write.collect::<Vec<_>>();
 
+    // If the variant is already set, merge into it like for any other field.
     let read = oneof_variants.iter().map(|(variant_ident, variant)| {
         let tag = variant.tag;
         quote!(#tag => {
-            *self = #ident::#variant_ident(r.read_oneof_variant()?);
+            if let #ident::#variant_ident(value) = self {
+                r.read(value)?;
+            } else if let Some(value) = r.try_read_oneof_variant()? {
+                *self = #ident::#variant_ident(value);
+            }
         })
     });
 
     let read_option = oneof_variants.iter().map(|(variant_ident, variant)| {
         let tag = variant.tag;
         quote!(#tag => {
-            *this = Some(#ident::#variant_ident(r.read_oneof_variant()?));
+            if let Some(#ident::#variant_ident(value)) = this {
+                r.read(value)?;
+            } else if let Some(value) = r.try_read_oneof_variant()? {
+                *this = Some(#ident::#variant_ident(value));
+            }
         })
     });
 
+    let variant_tys = oneof_variants.iter().map(|(_, variant)| &variant.ty);
+
+    let validate = oneof_variants.iter().map(|(variant_ident, _)| {
+        quote!(#ident::#variant_ident(value) => ::noproto::Message::validate(value),)
+    });
+
     let expanded = quote! {
         impl #impl_generics ::noproto::Oneof for #ident #ty_generics #where_clause {
+            const MAX_ENCODED_LEN: usize = {
+                let mut max = 0;
+                #(
+                    let len = #max_lens;
+                    if len > max {
+                        max = len;
+                    }
+                )*
+                max
+            };
+            const FIELDS: &'static [::noproto::descriptor::FieldDescriptor] = &[#(#field_descriptors),*];
+            const FALLIBLE_WRITE: bool = false #(|| <#variant_tys as ::noproto::Message>::FALLIBLE_WRITE)*;
+
             fn write_raw(&self, w: &mut ::noproto::encoding::ByteWriter) -> Result<(), ::noproto::WriteError> {
                 match self {
                     #(#write)*
@@ -290,7 +1150,7 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             fn read_raw(&mut self, r: ::noproto::encoding::FieldReader) -> Result<(), ::noproto::ReadError> {
                 match r.tag() {
                     #(#read)*
-                    _ => return Err(::noproto::ReadError),
+                    _ => return Err(::noproto::ReadError::InvalidData),
                 }
                 Ok(())
             }
@@ -298,11 +1158,26 @@ fn try_oneof(input: TokenStream) -> Result<TokenStream, Error> {
             fn read_raw_option(this: &mut Option<Self>, r: ::noproto::encoding::FieldReader) -> Result<(), ::noproto::ReadError> {
                 match r.tag() {
                     #(#read_option)*
-                    _ => return Err(::noproto::ReadError),
+                    _ => return Err(::noproto::ReadError::InvalidData),
+                }
+                Ok(())
+            }
+
+            fn write_reverse(&self, w: &mut ::noproto::encoding::ReverseWriter) -> Result<(), ::noproto::WriteError> {
+                match self {
+                    #(#write)*
                 }
                 Ok(())
             }
+
+            fn validate(&self) -> Result<(), ::noproto::ValidationError> {
+                match self {
+                    #(#validate)*
+                }
+            }
         }
+
+        #tags_module
     };
 
     Ok(expanded.into())
@@ -0,0 +1,72 @@
+//! Container capacities chosen at build time.
+//!
+//! Derived messages can be generic over the capacities of their `heapless` containers, so the
+//! same definitions build with large buffers on gateway-class devices and small ones on sensors.
+//! The capacities can then be picked per build, with feature flags or with [`capacity!`]
+//! reading an environment variable set when building (e.g. from `.cargo/config.toml`):
+//!
+//! ```
+//! #[derive(noproto::Message, Default)]
+//! struct Status<const NAME: usize, const LOG: usize> {
+//!     #[noproto(tag = 1)]
+//!     name: heapless::String<NAME>,
+//!     #[noproto(tag = 2)]
+//!     log: heapless::Vec<heapless::String<NAME>, LOG>,
+//! }
+//!
+//! // 32 unless `APP_NAME_LEN` is set when building.
+//! const NAME_LEN: usize = noproto::capacity!("APP_NAME_LEN", 32);
+//!
+//! #[cfg(feature = "gateway")]
+//! const LOG_LEN: usize = 64;
+//! #[cfg(not(feature = "gateway"))]
+//! const LOG_LEN: usize = 4;
+//!
+//! type DeviceStatus = Status<NAME_LEN, LOG_LEN>;
+//!
+//! let status = DeviceStatus::default();
+//! # assert_eq!(status.name.capacity(), NAME_LEN);
+//! ```
+//!
+//! [`capacity!`]: crate::capacity!
+
+/// Parse a capacity given as a decimal string, or return `default` if it's `None`.
+///
+/// # Panics
+///
+/// Panics if `value` is not a valid decimal number, which fails the build when called in a
+/// constant.
+pub const fn parse(value: Option<&str>, default: usize) -> usize {
+    let Some(value) = value else {
+        return default;
+    };
+    let bytes = value.as_bytes();
+    assert!(!bytes.is_empty(), "empty capacity");
+    let mut n: usize = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let digit = bytes[i];
+        assert!(digit.is_ascii_digit(), "capacity is not a decimal number");
+        n = match n.checked_mul(10) {
+            Some(n) => n,
+            None => panic!("capacity too large"),
+        };
+        n = match n.checked_add((digit - b'0') as usize) {
+            Some(n) => n,
+            None => panic!("capacity too large"),
+        };
+        i += 1;
+    }
+    n
+}
+
+/// Get a capacity from the environment variable `$env` at build time, or `$default` if it's
+/// not set.
+///
+/// See the [`capacity`](crate::capacity) module.
+#[macro_export]
+macro_rules! capacity {
+    ($env:literal, $default:expr $(,)?) => {
+        $crate::capacity::parse(::core::option_env!($env), $default)
+    };
+}
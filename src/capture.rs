@@ -0,0 +1,190 @@
+//! Capture files of encoded messages, for debugging.
+//!
+//! A [`CaptureWriter`] appends encoded messages to a file with their timestamp and direction,
+//! e.g. on a gateway, and a [`CaptureReader`] reads them back, e.g. to replay them through
+//! decoders on a developer machine.
+//!
+//! The format is a magic header followed by records, all integers little endian:
+//!
+//! ```text
+//! header: b"NPCAP" version:u8
+//! record: timestamp_us:u64 direction:u8 len:u32 data:[u8; len]
+//! ```
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use noproto::capture::{CaptureReader, CaptureWriter, Direction};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//! }
+//!
+//! let mut w = CaptureWriter::new(Vec::new()).unwrap();
+//! w.write_message_at(Duration::from_secs(1), Direction::Tx, &Ping { seq: 1 }).unwrap();
+//! let file = w.into_inner();
+//!
+//! let mut r = CaptureReader::new(&file[..]).unwrap();
+//! let record = r.next().unwrap().unwrap();
+//! assert_eq!(record.timestamp, Duration::from_secs(1));
+//! assert_eq!(record.direction, Direction::Tx);
+//! assert_eq!(record.decode::<Ping>().unwrap(), Ping { seq: 1 });
+//! assert!(r.next().is_none());
+//! ```
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime};
+use std::vec::Vec;
+
+use crate::write::ByteWriter;
+use crate::{Message, ReadError};
+
+const MAGIC: &[u8; 5] = b"NPCAP";
+const VERSION: u8 = 1;
+
+/// Direction of a captured message.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Direction {
+    /// Received by the capturing device.
+    Rx,
+    /// Sent by the capturing device.
+    Tx,
+}
+
+/// A captured message.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Record {
+    /// Capture time, since the Unix epoch.
+    pub timestamp: Duration,
+    /// Direction of the message.
+    pub direction: Direction,
+    /// Encoded message.
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// Decode the captured message.
+    pub fn decode<M: Message + Default>(&self) -> Result<M, ReadError> {
+        crate::read(&self.data)
+    }
+}
+
+/// Writes messages to a capture file.
+pub struct CaptureWriter<W> {
+    w: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Create a new [`CaptureWriter`], writing the file header to `w`.
+    pub fn new(mut w: W) -> io::Result<Self> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        Ok(Self { w, buf: Vec::new() })
+    }
+
+    /// Get back the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    /// Encode and append a message, timestamped with the current time.
+    pub fn write_message<M: Message>(&mut self, direction: Direction, msg: &M) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        self.write_message_at(now, direction, msg)
+    }
+
+    /// Encode and append a message with the given timestamp.
+    pub fn write_message_at<M: Message>(&mut self, timestamp: Duration, direction: Direction, msg: &M) -> io::Result<()> {
+        let len = msg.encoded_len().map_err(io::Error::other)?;
+        self.buf.clear();
+        self.buf.resize(len, 0);
+        let mut w = ByteWriter::new(&mut self.buf);
+        msg.write_raw(&mut w).map_err(io::Error::other)?;
+
+        let data = core::mem::take(&mut self.buf);
+        let res = self.write_record(timestamp, direction, &data);
+        self.buf = data;
+        res
+    }
+
+    /// Append an already encoded message.
+    pub fn write_record(&mut self, timestamp: Duration, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let timestamp = u64::try_from(timestamp.as_micros()).map_err(io::Error::other)?;
+        let len = u32::try_from(data.len()).map_err(io::Error::other)?;
+        let direction = match direction {
+            Direction::Rx => 0u8,
+            Direction::Tx => 1u8,
+        };
+        self.w.write_all(&timestamp.to_le_bytes())?;
+        self.w.write_all(&[direction])?;
+        self.w.write_all(&len.to_le_bytes())?;
+        self.w.write_all(data)
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// Reads the records of a capture file.
+pub struct CaptureReader<R> {
+    r: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Create a new [`CaptureReader`], checking the file header.
+    pub fn new(mut r: R) -> io::Result<Self> {
+        let mut header = [0; 6];
+        r.read_exact(&mut header)?;
+        if header[..5] != MAGIC[..] || header[5] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a noproto capture file"));
+        }
+        Ok(Self { r })
+    }
+
+    /// Get back the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    /// Read the next record, returning `None` at the end of the file.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        let mut header = [0; 13];
+        // Only a clean end of file before a record is not an error.
+        let n = self.r.read(&mut header)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        self.r.read_exact(&mut header[n..])?;
+
+        let timestamp = Duration::from_micros(u64::from_le_bytes(header[..8].try_into().unwrap()));
+        let direction = match header[8] {
+            0 => Direction::Rx,
+            1 => Direction::Tx,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid direction")),
+        };
+        let len = u32::from_le_bytes(header[9..].try_into().unwrap()) as usize;
+        let mut data = Vec::new();
+        (&mut self.r).take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Some(Record {
+            timestamp,
+            direction,
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
@@ -0,0 +1,204 @@
+//! Catalogs of the messages of a protocol.
+//!
+//! [`catalog!`](crate::catalog) generates an enum with a variant per message type, each with
+//! its application-assigned type ID. Values are sent in an [`Envelope`] carrying the type ID,
+//! and decoded back to the right variant, so the table mapping IDs to types is written once:
+//!
+//! ```
+//! use noproto::catalog::{Catalog, Entry};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Heartbeat {
+//!     #[noproto(tag = 1)]
+//!     uptime: u32,
+//! }
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Config {
+//!     #[noproto(tag = 1)]
+//!     interval: u32,
+//! }
+//!
+//! noproto::catalog! {
+//!     /// Messages sent by the device.
+//!     #[derive(PartialEq, Debug)]
+//!     pub enum Packet {
+//!         1 => Heartbeat,
+//!         2 => Config,
+//!     }
+//! }
+//!
+//! assert_eq!(<Config as Entry<Packet>>::TYPE_ID, 2);
+//! assert_eq!(Packet::TYPE_IDS, [1, 2]);
+//!
+//! let mut buf = [0; Packet::MAX_ENCODED_LEN];
+//! let n = Packet::from(Config { interval: 10 }).write(&mut buf).unwrap();
+//!
+//! // Matches on the catalog are checked for exhaustiveness.
+//! match Packet::read(&buf[..n]).unwrap() {
+//!     Packet::Heartbeat(heartbeat) => panic!("unexpected {:?}", heartbeat),
+//!     Packet::Config(config) => assert_eq!(config.interval, 10),
+//! }
+//! ```
+//!
+//! Variants are named after the message types. Types given by path need a variant name, as in
+//! `3 => Status(status::Report)`. Each type can appear only once in a catalog, and duplicate
+//! type IDs fail the build:
+//!
+//! ```compile_fail
+//! # #[derive(noproto::Message, Default)]
+//! # struct Heartbeat {}
+//! # #[derive(noproto::Message, Default)]
+//! # struct Config {}
+//! noproto::catalog! {
+//!     enum Packet {
+//!         1 => Heartbeat,
+//!         1 => Config,
+//!     }
+//! }
+//! ```
+
+use crate::envelope::{DispatchError, Envelope, Header};
+use crate::write::ByteWriter;
+use crate::{Message, WriteError};
+
+/// Enum of the messages of a protocol, generated by [`catalog!`](crate::catalog).
+pub trait Catalog: Sized {
+    /// Type IDs of the messages, in declaration order.
+    const TYPE_IDS: &'static [u32];
+    /// Upper bound on the length of the envelope written by [`write`](Catalog::write), or
+    /// `usize::MAX` if unbounded.
+    const MAX_ENCODED_LEN: usize;
+
+    /// Get the type ID of the message.
+    fn type_id(&self) -> u32;
+    /// Write the message in an [`Envelope`] with its type ID, returning the written length.
+    fn write(&self, buf: &mut [u8]) -> Result<usize, WriteError>;
+    /// Decode a message from an encoded [`Envelope`], picking its type from the type ID.
+    fn read(buf: &[u8]) -> Result<Self, DispatchError>;
+}
+
+/// A message type of catalog `C`.
+pub trait Entry<C: Catalog>: Message {
+    /// Type ID of the message in `C`.
+    const TYPE_ID: u32;
+}
+
+/// Define an enum implementing [`Catalog`](crate::catalog::Catalog), from its type IDs and
+/// message types.
+///
+/// See the [`catalog`](crate::catalog) module for an example.
+#[macro_export]
+macro_rules! catalog {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($type_id:expr => $variant:ident $(($ty:ty))?),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($variant($crate::catalog!(@ty $variant $($ty)?)),)*
+        }
+
+        const _: () = $crate::catalog::__assert_unique(&[$($type_id),*]);
+
+        impl $crate::catalog::Catalog for $name {
+            const TYPE_IDS: &'static [u32] = &[$($type_id),*];
+            const MAX_ENCODED_LEN: usize = $crate::catalog::__max(&[$(
+                <$crate::envelope::Envelope<$crate::catalog!(@ty $variant $($ty)?)> as $crate::Message>::MAX_ENCODED_LEN
+            ),*]);
+
+            fn type_id(&self) -> u32 {
+                match self {
+                    $(Self::$variant(_) => $type_id,)*
+                }
+            }
+
+            fn write(&self, buf: &mut [u8]) -> Result<usize, $crate::WriteError> {
+                match self {
+                    $(Self::$variant(m) => $crate::catalog::__write($type_id, m, buf),)*
+                }
+            }
+
+            fn read(buf: &[u8]) -> Result<Self, $crate::envelope::DispatchError> {
+                match $crate::catalog::__type_id(buf)? {
+                    $(
+                        type_id if type_id == $type_id => $crate::catalog::__read(buf).map(Self::$variant),
+                    )*
+                    type_id => Err($crate::envelope::DispatchError::UnknownType(type_id)),
+                }
+            }
+        }
+
+        $(
+            impl $crate::catalog::Entry<$name> for $crate::catalog!(@ty $variant $($ty)?) {
+                const TYPE_ID: u32 = $type_id;
+            }
+
+            impl ::core::convert::From<$crate::catalog!(@ty $variant $($ty)?)> for $name {
+                fn from(m: $crate::catalog!(@ty $variant $($ty)?)) -> Self {
+                    Self::$variant(m)
+                }
+            }
+        )*
+    };
+    (@ty $variant:ident) => { $variant };
+    (@ty $variant:ident $ty:ty) => { $ty };
+}
+
+/// Check that `type_ids` has no duplicates, for [`catalog!`](crate::catalog).
+#[doc(hidden)]
+pub const fn __assert_unique(type_ids: &[u32]) {
+    let mut i = 0;
+    while i < type_ids.len() {
+        let mut j = i + 1;
+        while j < type_ids.len() {
+            if type_ids[i] == type_ids[j] {
+                panic!("catalog!: duplicate type ID");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// Largest of `lens`, for [`catalog!`](crate::catalog).
+#[doc(hidden)]
+pub const fn __max(lens: &[usize]) -> usize {
+    let mut max = 0;
+    let mut i = 0;
+    while i < lens.len() {
+        if lens[i] > max {
+            max = lens[i];
+        }
+        i += 1;
+    }
+    max
+}
+
+/// Write `payload` in an envelope, for [`catalog!`](crate::catalog).
+#[doc(hidden)]
+pub fn __write<M: Message>(type_id: u32, payload: &M, buf: &mut [u8]) -> Result<usize, WriteError> {
+    let mut w = ByteWriter::new(buf);
+    // Same encoding as `Envelope`, without moving the payload into one.
+    Header {
+        type_id,
+        ..Default::default()
+    }
+    .write_raw(&mut w)?;
+    w.write_field(4, payload)?;
+    Ok(w.pos())
+}
+
+/// Get the type ID of an encoded envelope, for [`catalog!`](crate::catalog).
+#[doc(hidden)]
+pub fn __type_id(buf: &[u8]) -> Result<u32, DispatchError> {
+    Ok(Header::peek(buf)?.type_id)
+}
+
+/// Decode the payload of an envelope, for [`catalog!`](crate::catalog).
+#[doc(hidden)]
+pub fn __read<M: Message + Default>(buf: &[u8]) -> Result<M, DispatchError> {
+    Ok(Envelope::<M>::read(buf)?.payload)
+}
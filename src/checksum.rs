@@ -0,0 +1,235 @@
+//! Messages framed with a trailing checksum, for links and storage without integrity checks.
+//!
+//! The framing helpers are generic over [`Checksum`], so the software implementations here can
+//! be swapped for the MCU's hardware CRC unit:
+//!
+//! ```
+//! use noproto::checksum::{read_checked, write_checked, Checksum, Crc32};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Reading {
+//!     #[noproto(tag = 1)]
+//!     value: i32,
+//! }
+//!
+//! let mut buf = [0; 16];
+//! let n = write_checked(&Reading { value: -3 }, &mut Crc32::new(), &mut buf).unwrap();
+//! assert_eq!(read_checked(&buf[..n], &mut Crc32::new()), Ok(Reading { value: -3 }));
+//!
+//! // Wrapping a hardware CRC peripheral.
+//! struct HwCrc(u32);
+//!
+//! impl Checksum for HwCrc {
+//!     const LEN: usize = 4;
+//!
+//!     fn reset(&mut self) {
+//!         // crc.reset()
+//!         self.0 = 0;
+//!     }
+//!
+//!     fn update(&mut self, data: &[u8]) {
+//!         // crc.feed(data)
+//!         self.0 = data.iter().fold(self.0, |acc, &b| acc.rotate_left(5) ^ b as u32);
+//!     }
+//!
+//!     fn finish(&mut self) -> u32 {
+//!         // crc.read()
+//!         self.0
+//!     }
+//! }
+//!
+//! let n = write_checked(&Reading { value: 7 }, &mut HwCrc(0), &mut buf).unwrap();
+//! buf[0] ^= 1;
+//! assert!(read_checked::<Reading, _>(&buf[..n], &mut HwCrc(0)).is_err());
+//! ```
+
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WriteError};
+
+/// A checksum computed over a byte stream.
+pub trait Checksum {
+    /// Length of the checksum in bytes, at most 4.
+    const LEN: usize;
+
+    /// Start a new computation.
+    fn reset(&mut self);
+
+    /// Feed `data` to the computation.
+    fn update(&mut self, data: &[u8]);
+
+    /// Get the checksum of the data fed since the last reset, in its low [`LEN`](Self::LEN) bytes.
+    fn finish(&mut self) -> u32;
+
+    /// Compute the checksum of `data`.
+    fn checksum(&mut self, data: &[u8]) -> u32 {
+        self.reset();
+        self.update(data);
+        self.finish()
+    }
+}
+
+impl<C: Checksum + ?Sized> Checksum for &mut C {
+    const LEN: usize = C::LEN;
+
+    fn reset(&mut self) {
+        C::reset(self)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        C::update(self, data)
+    }
+
+    fn finish(&mut self) -> u32 {
+        C::finish(self)
+    }
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF, not reflected.
+///
+/// Computed bit by bit, trading speed for not having a lookup table in flash.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc16(u16);
+
+impl Crc16 {
+    /// Create a new [`Crc16`].
+    pub const fn new() -> Self {
+        Self(0xFFFF)
+    }
+}
+
+impl Default for Crc16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Checksum for Crc16 {
+    const LEN: usize = 2;
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= (b as u16) << 8;
+            for _ in 0..8 {
+                self.0 = if self.0 & 0x8000 != 0 {
+                    (self.0 << 1) ^ 0x1021
+                } else {
+                    self.0 << 1
+                };
+            }
+        }
+    }
+
+    fn finish(&mut self) -> u32 {
+        self.0 as u32
+    }
+}
+
+/// CRC-32 as used by Ethernet and zlib: polynomial 0x04C11DB7, reflected.
+///
+/// Computed bit by bit, trading speed for not having a lookup table in flash.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Create a new [`Crc32`].
+    pub const fn new() -> Self {
+        Self(!0)
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Checksum for Crc32 {
+    const LEN: usize = 4;
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= b as u32;
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 != 0 {
+                    (self.0 >> 1) ^ 0xEDB8_8320
+                } else {
+                    self.0 >> 1
+                };
+            }
+        }
+    }
+
+    fn finish(&mut self) -> u32 {
+        !self.0
+    }
+}
+
+/// Fletcher-16, cheaper than a CRC on cores without a hardware CRC unit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fletcher16 {
+    sum1: u16,
+    sum2: u16,
+}
+
+impl Fletcher16 {
+    /// Create a new [`Fletcher16`].
+    pub const fn new() -> Self {
+        Self { sum1: 0, sum2: 0 }
+    }
+}
+
+impl Checksum for Fletcher16 {
+    const LEN: usize = 2;
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.sum1 = (self.sum1 + b as u16) % 255;
+            self.sum2 = (self.sum2 + self.sum1) % 255;
+        }
+    }
+
+    fn finish(&mut self) -> u32 {
+        ((self.sum2 as u32) << 8) | self.sum1 as u32
+    }
+}
+
+/// Serialize `msg` followed by its little-endian checksum, returning the total length.
+pub fn write_checked<M: Message, C: Checksum>(msg: &M, checksum: &mut C, buf: &mut [u8]) -> Result<usize, WriteError> {
+    const { assert!(C::LEN <= 4, "checksums are at most 4 bytes") };
+    let mut w = ByteWriter::new(buf);
+    msg.write_raw(&mut w)?;
+    let sum = checksum.checksum(w.bytes());
+    w.write(&sum.to_le_bytes()[..C::LEN])?;
+    Ok(w.pos())
+}
+
+/// Check the trailing checksum of `buf`, returning the data before it.
+///
+/// Fails with [`ReadError::InvalidData`] if the checksum doesn't match.
+pub fn verify<'a, C: Checksum>(buf: &'a [u8], checksum: &mut C) -> Result<&'a [u8], ReadError> {
+    const { assert!(C::LEN <= 4, "checksums are at most 4 bytes") };
+    let split = buf.len().checked_sub(C::LEN).ok_or(ReadError::UnexpectedEof)?;
+    let (data, trailer) = buf.split_at(split);
+    let sum = checksum.checksum(data).to_le_bytes();
+    if trailer != &sum[..C::LEN] {
+        return Err(ReadError::InvalidData);
+    }
+    Ok(data)
+}
+
+/// Deserialize a message written by [`write_checked`], checking its checksum first.
+pub fn read_checked<M: Message + Default, C: Checksum>(buf: &[u8], checksum: &mut C) -> Result<M, ReadError> {
+    crate::read(verify(buf, checksum)?)
+}
@@ -0,0 +1,65 @@
+//! Encoding messages piece by piece, for transports with a small MTU.
+//!
+//! A [`ChunkedEncoder`] fills one packet-sized chunk at a time, e.g. a BLE notification, LoRa
+//! frame or CAN frame, without a staging buffer for the whole message. Each chunk is produced
+//! by encoding the message again and keeping only the bytes falling in the chunk, so the
+//! message must not change between chunks, and encoding takes time proportional to the message
+//! length times the number of chunks.
+//!
+//! ```
+//! use noproto::chunked::ChunkedEncoder;
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Log {
+//!     #[noproto(tag = 1)]
+//!     line: heapless::String<64>,
+//! }
+//!
+//! let log = Log { line: "a line too long for one packet".try_into().unwrap() };
+//!
+//! let mut received = heapless::Vec::<u8, 64>::new();
+//! let mut encoder = ChunkedEncoder::new(&log);
+//! let mut packet = [0; 8];
+//! while let Some(n) = encoder.encode_next(&mut packet).unwrap() {
+//!     received.extend_from_slice(&packet[..n]).unwrap();
+//! }
+//! assert_eq!(noproto::read::<Log>(&received).unwrap(), log);
+//! ```
+
+use crate::write::ByteWriter;
+use crate::{Message, WriteError};
+
+/// Resumable encoder producing a message across several output chunks.
+pub struct ChunkedEncoder<'a, M> {
+    msg: &'a M,
+    /// Number of bytes already produced.
+    pos: usize,
+}
+
+impl<'a, M: Message> ChunkedEncoder<'a, M> {
+    /// Create a new [`ChunkedEncoder`] for `msg`.
+    pub fn new(msg: &'a M) -> Self {
+        Self { msg, pos: 0 }
+    }
+
+    /// Get the number of bytes already produced.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Encode the next part of the message into `buf`, returning the number of bytes written,
+    /// or `None` once the whole message was produced.
+    ///
+    /// Only the last chunk is shorter than `buf`.
+    pub fn encode_next(&mut self, buf: &mut [u8]) -> Result<Option<usize>, WriteError> {
+        let mut w = ByteWriter::new_window(buf, self.pos);
+        self.msg.write_raw(&mut w)?;
+        let total = w.pos();
+        if self.pos >= total {
+            return Ok(None);
+        }
+        let n = (total - self.pos).min(buf.len());
+        self.pos += n;
+        Ok(Some(n))
+    }
+}
@@ -0,0 +1,225 @@
+//! Per-field compression, for fields marked `#[noproto(compress)]`.
+//!
+//! The payload of a compressed `string` or `bytes` field is replaced by its compressed form
+//! on the wire. `#[noproto(compress)]` uses the built-in [`Lz`] codec, and
+//! `#[noproto(compress = "MyCodec")]` any other [`Codec`].
+//!
+//! ```
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Log {
+//!     #[noproto(tag = 1, compress)]
+//!     text: heapless::String<128>,
+//! }
+//!
+//! let mut log = Log::default();
+//! log.text.push_str("error error error error error error").unwrap();
+//!
+//! let mut buf = [0; 64];
+//! let n = noproto::write(&log, &mut buf).unwrap();
+//! assert!(n < log.text.len());
+//! assert_eq!(noproto::read::<Log>(&buf[..n]).unwrap(), log);
+//! ```
+
+use crate::read::FieldReader;
+use crate::write::{varuint_len, ByteWriter, MAX_LENGTH_DELIMITED};
+use crate::{Message, ReadError, WireType, WriteError};
+
+/// A compression algorithm for field payloads.
+pub trait Codec {
+    /// Bound on the expansion of incompressible data: compressing `len` bytes produces at
+    /// most `len + len.div_ceil(OVERHEAD_BLOCK)` bytes. 0 if unbounded.
+    const OVERHEAD_BLOCK: usize;
+
+    /// Compress `data` into `w`.
+    fn compress(data: &[u8], w: &mut ByteWriter) -> Result<(), WriteError>;
+
+    /// Decompress `data` into `out`, returning the decompressed length.
+    fn decompress(data: &[u8], out: &mut [u8]) -> Result<usize, ReadError>;
+}
+
+/// Field types that can be compressed.
+pub trait Payload: Message {
+    /// The uncompressed payload.
+    fn bytes(&self) -> &[u8];
+
+    /// Replace the payload with the bytes written by `f` into the given buffer, returning
+    /// their length.
+    fn fill(&mut self, f: impl FnOnce(&mut [u8]) -> Result<usize, ReadError>) -> Result<(), ReadError>;
+}
+
+impl<const N: usize> Payload for heapless::Vec<u8, N> {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn fill(&mut self, f: impl FnOnce(&mut [u8]) -> Result<usize, ReadError>) -> Result<(), ReadError> {
+        self.clear();
+        // Can't fail, `N` is the capacity. Not unwrapped to keep `core::fmt` out of decoding.
+        let _ = self.resize(N, 0);
+        match f(self) {
+            Ok(n) => {
+                self.truncate(n);
+                Ok(())
+            }
+            Err(e) => {
+                self.clear();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<const N: usize> Payload for heapless::String<N> {
+    fn bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+
+    fn fill(&mut self, f: impl FnOnce(&mut [u8]) -> Result<usize, ReadError>) -> Result<(), ReadError> {
+        // SAFETY: the contents are checked to be valid UTF-8 before returning, and cleared if not.
+        let vec = unsafe { self.as_mut_vec() };
+        vec.fill(f)?;
+        if core::str::from_utf8(vec).is_err() {
+            vec.clear();
+            return Err(ReadError::InvalidUtf8);
+        }
+        Ok(())
+    }
+}
+
+/// Upper bound on the length of the compressed payload of `P`, or `usize::MAX` if unbounded.
+pub const fn max_len<C: Codec, P: Message>() -> usize {
+    let len = P::MAX_ENCODED_LEN;
+    match C::OVERHEAD_BLOCK {
+        0 => usize::MAX,
+        block => len.saturating_add(len.div_ceil(block)),
+    }
+}
+
+/// Upper bound on the encoded length of a compressed field, including its header.
+pub const fn max_field_len<C: Codec, P: Message>(tag: u32) -> usize {
+    let body = max_len::<C, P>();
+    let header = varuint_len((tag as u64) << 3);
+    body.saturating_add(varuint_len(body as u64)).saturating_add(header)
+}
+
+/// Write `payload` compressed with `C` as field `tag`.
+pub fn write<C: Codec, P: Payload>(w: &mut ByteWriter, tag: u32, payload: &P) -> Result<(), WriteError> {
+    w.write_varuint32((tag << 3) | WireType::LengthDelimited as u32)?;
+    if w.can_move() {
+        return w.write_length_delimited(|w| C::compress(payload.bytes(), w));
+    }
+
+    // A window or stream can't move the data to fit the header, so size it first.
+    let mut counter = ByteWriter::counting();
+    C::compress(payload.bytes(), &mut counter)?;
+    let len = counter.pos();
+    if len > MAX_LENGTH_DELIMITED {
+        return Err(WriteError);
+    }
+    w.write_varuint32(len as u32)?;
+    let start = w.pos();
+    C::compress(payload.bytes(), w)?;
+    if w.pos() - start != len {
+        return Err(WriteError);
+    }
+    Ok(())
+}
+
+/// Read a field compressed with `C` into `payload`.
+pub fn read<C: Codec, P: Payload>(r: FieldReader, payload: &mut P) -> Result<(), ReadError> {
+    if r.wire_type() != WireType::LengthDelimited {
+        return Err(ReadError::InvalidWireType);
+    }
+    payload.fill(|out| C::decompress(r.data(), out))
+}
+
+/// Minimal LZ77 codec, suited to short repetitive text such as log messages.
+///
+/// Each chunk starts with a control byte: `0..=127` is followed by that many plus one
+/// literal bytes, `128..=255` is a match of `(control & 127) + 3` bytes, followed by one byte
+/// holding the match distance minus one (at most 256 bytes back).
+pub struct Lz;
+
+const LZ_MAX_LITERALS: usize = 128;
+const LZ_MIN_MATCH: usize = 3;
+const LZ_MAX_MATCH: usize = 127 + LZ_MIN_MATCH;
+const LZ_WINDOW: usize = 256;
+
+impl Codec for Lz {
+    const OVERHEAD_BLOCK: usize = LZ_MAX_LITERALS;
+
+    fn compress(data: &[u8], w: &mut ByteWriter) -> Result<(), WriteError> {
+        let mut literals = 0;
+        let mut i = 0;
+        while i < data.len() {
+            let (len, dist) = lz_longest_match(data, i);
+            if len < LZ_MIN_MATCH {
+                i += 1;
+                literals += 1;
+                if literals == LZ_MAX_LITERALS {
+                    lz_flush_literals(w, &data[i - literals..i])?;
+                    literals = 0;
+                }
+                continue;
+            }
+            lz_flush_literals(w, &data[i - literals..i])?;
+            literals = 0;
+            w.write_u8(0x80 | (len - LZ_MIN_MATCH) as u8)?;
+            w.write_u8((dist - 1) as u8)?;
+            i += len;
+        }
+        lz_flush_literals(w, &data[i - literals..i])
+    }
+
+    fn decompress(data: &[u8], out: &mut [u8]) -> Result<usize, ReadError> {
+        let mut i = 0;
+        let mut n = 0;
+        while i < data.len() {
+            let control = data[i] as usize;
+            i += 1;
+            if control < 0x80 {
+                let len = control + 1;
+                let literals = data.get(i..i + len).ok_or(ReadError::InvalidData)?;
+                out.get_mut(n..n + len)
+                    .ok_or(ReadError::CapacityExceeded)?
+                    .copy_from_slice(literals);
+                i += len;
+                n += len;
+            } else {
+                let len = (control & 0x7f) + LZ_MIN_MATCH;
+                let dist = *data.get(i).ok_or(ReadError::InvalidData)? as usize + 1;
+                i += 1;
+                if dist > n || out.len() - n < len {
+                    return Err(ReadError::InvalidData);
+                }
+                // Byte by byte, matches may overlap the bytes they produce.
+                for j in n..n + len {
+                    out[j] = out[j - dist];
+                }
+                n += len;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Find the longest match for `data[i..]` in the window before it, as `(len, distance)`.
+fn lz_longest_match(data: &[u8], i: usize) -> (usize, usize) {
+    let max = (data.len() - i).min(LZ_MAX_MATCH);
+    let mut best = (0, 0);
+    for start in i.saturating_sub(LZ_WINDOW)..i {
+        let len = (0..max).take_while(|&k| data[start + k] == data[i + k]).count();
+        if len > best.0 {
+            best = (len, i - start);
+        }
+    }
+    best
+}
+
+fn lz_flush_literals(w: &mut ByteWriter, literals: &[u8]) -> Result<(), WriteError> {
+    if literals.is_empty() {
+        return Ok(());
+    }
+    w.write_u8((literals.len() - 1) as u8)?;
+    w.write(literals)
+}
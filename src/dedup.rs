@@ -0,0 +1,89 @@
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use crate::{ReadError, RepeatedMessage, ValidationError, WriteError};
+
+/// Repeated field adapter that drops consecutive duplicates while decoding.
+///
+/// An appended element is skipped if it is equal to the last element kept, or if the
+/// predicate given to [`with_predicate`](Dedup::with_predicate) says so. Useful for
+/// compacting bursty repeated telemetry when decoding or forwarding it.
+#[derive(Clone)]
+pub struct Dedup<R: RepeatedMessage> {
+    inner: R,
+    same: fn(&R::Message, &R::Message) -> bool,
+}
+
+impl<R: RepeatedMessage> Dedup<R> {
+    /// Wrap `inner`, skipping elements equal to the last one kept.
+    pub fn new(inner: R) -> Self
+    where
+        R::Message: PartialEq,
+    {
+        Self::with_predicate(inner, |last, new| last == new)
+    }
+
+    /// Wrap `inner`, skipping elements for which `same(last, new)` returns true.
+    pub fn with_predicate(inner: R, same: fn(&R::Message, &R::Message) -> bool) -> Self {
+        Self { inner, same }
+    }
+
+    /// Unwrap the inner repeated field.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: RepeatedMessage + Default> Default for Dedup<R>
+where
+    R::Message: PartialEq,
+{
+    fn default() -> Self {
+        Self::new(R::default())
+    }
+}
+
+impl<R: RepeatedMessage> Deref for Dedup<R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: RepeatedMessage> DerefMut for Dedup<R> {
+    fn deref_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: RepeatedMessage + fmt::Debug> fmt::Debug for Dedup<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<R: RepeatedMessage> RepeatedMessage for Dedup<R> {
+    type Message = R::Message;
+
+    const MAX_LEN: usize = R::MAX_LEN;
+
+    type Iter<'a> = R::Iter<'a> where Self: 'a;
+
+    fn iter(&self) -> Result<Self::Iter<'_>, WriteError> {
+        self.inner.iter()
+    }
+
+    fn append(&mut self, m: Self::Message) -> Result<(), ReadError> {
+        if let Ok(Some(last)) = self.inner.iter().map(|i| i.last()) {
+            if (self.same)(last, &m) {
+                return Ok(());
+            }
+        }
+        self.inner.append(m)
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.inner.validate()
+    }
+}
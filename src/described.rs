@@ -0,0 +1,143 @@
+//! Self-describing messages, for blobs stored long-term in flash or on external media.
+//!
+//! A described message is prefixed with a compact header identifying its format, so it can be
+//! safely interpreted long after it was written, by firmware whose schema may have changed:
+//!
+//! ```text
+//! u8      format version, currently 1
+//! u32 LE  schema fingerprint of the message type
+//! varint  application-assigned type ID
+//! varint  payload length
+//! ...     payload
+//! ```
+//!
+//! The fingerprint is the one of [`SchemaInfo::of`], so it changes with any wire-relevant change
+//! to the schema, and [`read_described`] rejects blobs written with another schema instead of
+//! misinterpreting them.
+//!
+//! ```
+//! use noproto::described::{read_described, write_described, DescribedError};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Calibration {
+//!     #[noproto(tag = 1)]
+//!     offset: i32,
+//!     #[noproto(tag = 2)]
+//!     gain: f32,
+//! }
+//!
+//! const CALIBRATION: u32 = 3;
+//!
+//! let cal = Calibration { offset: -12, gain: 1.5 };
+//! let mut flash = [0; 32];
+//! let n = write_described(&cal, CALIBRATION, &mut flash).unwrap();
+//!
+//! assert_eq!(read_described::<Calibration>(&flash[..n], CALIBRATION), Ok(cal));
+//! assert_eq!(
+//!     read_described::<Calibration>(&flash[..n], 4),
+//!     Err(DescribedError::TypeMismatch(CALIBRATION))
+//! );
+//! ```
+//!
+//! [`SchemaInfo::of`]: crate::negotiate::SchemaInfo::of
+
+use crate::negotiate::SchemaInfo;
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WriteError};
+
+/// Format version written in the header.
+pub const FORMAT: u8 = 1;
+
+/// Header of a described message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Description {
+    /// Schema fingerprint of the message type, 0 if it has no descriptor.
+    pub schema_hash: u32,
+    /// Application-assigned type ID.
+    pub type_id: u32,
+    /// Length of the payload.
+    pub len: u32,
+}
+
+impl Description {
+    /// Decode the header of a described message, returning it with the payload.
+    pub fn peek(buf: &[u8]) -> Result<(Description, &[u8]), DescribedError> {
+        let mut r = ByteReader::new(buf);
+        let format = r.read_u8()?;
+        if format != FORMAT {
+            return Err(DescribedError::Format(format));
+        }
+        let schema_hash = r.read_u32()?;
+        let type_id = r.read_varuint32()?;
+        let len = r.read_varuint32()?;
+        let payload = r.read_slice(len as usize)?;
+        if !r.eof() {
+            return Err(ReadError::InvalidLength.into());
+        }
+        let desc = Description {
+            schema_hash,
+            type_id,
+            len,
+        };
+        Ok((desc, payload))
+    }
+}
+
+/// Error returned by [`read_described`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DescribedError {
+    /// The header or the payload could not be decoded.
+    Read(ReadError),
+    /// The header has an unknown format version.
+    Format(u8),
+    /// The message has another type ID, given here.
+    TypeMismatch(u32),
+    /// The message was written with another schema, whose fingerprint is given here.
+    SchemaMismatch(u32),
+}
+
+impl core::fmt::Display for DescribedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DescribedError::Read(e) => e.fmt(f),
+            DescribedError::Format(format) => write!(f, "unknown format version {}", format),
+            DescribedError::TypeMismatch(type_id) => write!(f, "unexpected message type {}", type_id),
+            DescribedError::SchemaMismatch(hash) => write!(f, "unexpected schema {:08x}", hash),
+        }
+    }
+}
+
+impl core::error::Error for DescribedError {}
+
+impl From<ReadError> for DescribedError {
+    fn from(e: ReadError) -> Self {
+        DescribedError::Read(e)
+    }
+}
+
+/// Serialize `msg` with a self-describing header for type ID `type_id`.
+///
+/// Returns the total number of bytes written, header included.
+pub fn write_described<M: Message>(msg: &M, type_id: u32, buf: &mut [u8]) -> Result<usize, WriteError> {
+    let info = SchemaInfo::of::<M>(type_id);
+    let mut w = ByteWriter::new(buf);
+    w.write_u8(FORMAT)?;
+    w.write_u32(info.hash)?;
+    w.write_varuint32(type_id)?;
+    w.write_length_delimited(|w| msg.write_raw(w))?;
+    Ok(w.pos())
+}
+
+/// Deserialize a message written by [`write_described`], checking that its header matches
+/// type ID `type_id` and the schema of `M`.
+pub fn read_described<M: Message + Default>(buf: &[u8], type_id: u32) -> Result<M, DescribedError> {
+    let (desc, payload) = Description::peek(buf)?;
+    if desc.type_id != type_id {
+        return Err(DescribedError::TypeMismatch(desc.type_id));
+    }
+    if desc.schema_hash != SchemaInfo::of::<M>(type_id).hash {
+        return Err(DescribedError::SchemaMismatch(desc.schema_hash));
+    }
+    Ok(crate::read(payload)?)
+}
@@ -0,0 +1,878 @@
+//! Runtime descriptions of message schemas, generated by the derives.
+
+use crate::{Message, WireType};
+
+/// Get the descriptor of a derived message, or `None` if `M` is not a described message.
+pub const fn descriptor<M: Message>() -> Option<&'static MessageDescriptor> {
+    match M::TYPE {
+        Type::Message(d) => Some(d),
+        _ => None,
+    }
+}
+
+/// Fail the build if two sources of fields of the same message claim the same tag.
+///
+/// Useful when a message is assembled from generated and hand-written parts, e.g. a derived
+/// message whose fields are decoded alongside manually handled ones. The sources are described
+/// messages, whose tags are taken from their descriptors, then after a `;` the tags of
+/// hand-written fields.
+///
+/// ```
+/// #[derive(noproto::Message, Default)]
+/// struct Generated {
+///     #[noproto(tag = 1)]
+///     a: u32,
+///     #[noproto(tag = 2)]
+///     b: u32,
+/// }
+///
+/// noproto::static_assert_tags!(Generated; 10, 11);
+/// ```
+///
+/// ```compile_fail
+/// # #[derive(noproto::Message, Default)]
+/// # struct Generated {
+/// #     #[noproto(tag = 1)]
+/// #     a: u32,
+/// #     #[noproto(tag = 2)]
+/// #     b: u32,
+/// # }
+/// noproto::static_assert_tags!(Generated; 2, 11);
+/// ```
+#[macro_export]
+macro_rules! static_assert_tags {
+    ($($ty:ty),* $(,)? $(; $($tag:expr),* $(,)?)?) => {
+        const _: () = $crate::descriptor::__assert_tags(
+            &[$($crate::descriptor::descriptor::<$ty>()),*],
+            &[$($($tag),*)?],
+        );
+    };
+}
+
+/// Check that `descs` and `tags` have no tag in common, for [`static_assert_tags!`].
+#[doc(hidden)]
+pub const fn __assert_tags(descs: &[Option<&MessageDescriptor>], tags: &[u32]) {
+    let mut i = 0;
+    while i < descs.len() {
+        if descs[i].is_none() {
+            panic!("static_assert_tags!: type without descriptor, list its tags after `;`");
+        }
+        i += 1;
+    }
+    let total = tag_count(descs) + tags.len();
+    let mut i = 0;
+    while i < total {
+        let mut j = i + 1;
+        while j < total {
+            if tag_at(descs, tags, i) == tag_at(descs, tags, j) {
+                panic!("static_assert_tags!: duplicate tag");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// Descriptor of the type of an `#[noproto(extend)]` field, for the derive.
+#[doc(hidden)]
+pub const fn __part(ty: Type) -> &'static MessageDescriptor {
+    match ty {
+        Type::Message(desc) => desc,
+        _ => panic!("extend field type without message descriptor"),
+    }
+}
+
+/// Number of fields in `own` and in the fields of `parts`, for the derive.
+#[doc(hidden)]
+pub const fn __fields_len(own: &[FieldDescriptor], parts: &[&MessageDescriptor]) -> usize {
+    let mut n = own.len();
+    let mut i = 0;
+    while i < parts.len() {
+        n += parts[i].fields.len();
+        i += 1;
+    }
+    n
+}
+
+/// The fields of `own`, then those of `parts`, for the derive.
+#[doc(hidden)]
+pub const fn __concat_fields<const N: usize>(
+    own: &[FieldDescriptor],
+    parts: &[&MessageDescriptor],
+) -> [FieldDescriptor; N] {
+    const EMPTY: FieldDescriptor = FieldDescriptor {
+        name: "",
+        tag: 0,
+        label: Label::Single,
+        ty: Type::Unknown,
+        max_len: 0,
+        max_count: 0,
+        redacted: false,
+        required: false,
+        compressed: false,
+        group: false,
+    };
+    let mut out = [EMPTY; N];
+    let mut n = 0;
+    while n < own.len() {
+        out[n] = own[n];
+        n += 1;
+    }
+    let mut i = 0;
+    while i < parts.len() {
+        let mut j = 0;
+        while j < parts[i].fields.len() {
+            out[n] = parts[i].fields[j];
+            n += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Number of oneofs in `own` and in the oneofs of `parts`, for the derive.
+#[doc(hidden)]
+pub const fn __oneofs_len(own: &[OneofDescriptor], parts: &[&MessageDescriptor]) -> usize {
+    let mut n = own.len();
+    let mut i = 0;
+    while i < parts.len() {
+        n += parts[i].oneofs.len();
+        i += 1;
+    }
+    n
+}
+
+/// The oneofs of `own`, then those of `parts`, for the derive.
+#[doc(hidden)]
+pub const fn __concat_oneofs<const N: usize>(
+    own: &[OneofDescriptor],
+    parts: &[&MessageDescriptor],
+) -> [OneofDescriptor; N] {
+    const EMPTY: OneofDescriptor = OneofDescriptor { name: "", fields: &[] };
+    let mut out = [EMPTY; N];
+    let mut n = 0;
+    while n < own.len() {
+        out[n] = own[n];
+        n += 1;
+    }
+    let mut i = 0;
+    while i < parts.len() {
+        let mut j = 0;
+        while j < parts[i].oneofs.len() {
+            out[n] = parts[i].oneofs[j];
+            n += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Check that a message and the parts embedded in it with `#[noproto(extend)]` use distinct
+/// tags, for the derive.
+#[doc(hidden)]
+pub const fn __assert_part_tags(desc: Option<&MessageDescriptor>) {
+    let descs = &[desc];
+    let total = tag_count(descs);
+    let mut i = 0;
+    while i < total {
+        let mut j = i + 1;
+        while j < total {
+            if tag_at(descs, &[], i) == tag_at(descs, &[], j) {
+                panic!("extend field with a tag already used by the message or another part");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// Number of tags of all fields of `descs`, including those in oneofs.
+const fn tag_count(descs: &[Option<&MessageDescriptor>]) -> usize {
+    let mut n = 0;
+    let mut i = 0;
+    while i < descs.len() {
+        if let Some(desc) = descs[i] {
+            n += desc.fields.len();
+            let mut o = 0;
+            while o < desc.oneofs.len() {
+                n += desc.oneofs[o].fields.len();
+                o += 1;
+            }
+        }
+        i += 1;
+    }
+    n
+}
+
+/// The `index`-th tag of the fields of `descs`, then of `tags`.
+const fn tag_at(descs: &[Option<&MessageDescriptor>], tags: &[u32], mut index: usize) -> u32 {
+    let mut i = 0;
+    while i < descs.len() {
+        if let Some(desc) = descs[i] {
+            if index < desc.fields.len() {
+                return desc.fields[index].tag;
+            }
+            index -= desc.fields.len();
+            let mut o = 0;
+            while o < desc.oneofs.len() {
+                let fields = desc.oneofs[o].fields;
+                if index < fields.len() {
+                    return fields[index].tag;
+                }
+                index -= fields.len();
+                o += 1;
+            }
+        }
+        i += 1;
+    }
+    tags[index]
+}
+
+/// Protobuf type of a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// `bool`
+    Bool,
+    /// `uint32`
+    Uint32,
+    /// `uint64`
+    Uint64,
+    /// `int32`
+    Int32,
+    /// `int64`
+    Int64,
+    /// `sint32`
+    Sint32,
+    /// `sint64`
+    Sint64,
+    /// `fixed32`
+    Fixed32,
+    /// `fixed64`
+    Fixed64,
+    /// `sfixed32`
+    Sfixed32,
+    /// `sfixed64`
+    Sfixed64,
+    /// `float`
+    Float,
+    /// `double`
+    Double,
+    /// `string`
+    String,
+    /// `bytes`
+    Bytes,
+    /// `bytes` of exactly this length, e.g. [`RawLe`](crate::types::RawLe).
+    FixedBytes(usize),
+    /// An enumeration.
+    Enum(&'static EnumDescriptor),
+    /// A nested message.
+    Message(&'static MessageDescriptor),
+    /// A type without a description, e.g. a manual `Message` impl.
+    Unknown,
+}
+
+impl Type {
+    /// Wire type of a single value of this type, or `None` for [`Type::Unknown`].
+    pub fn wire_type(&self) -> Option<WireType> {
+        match self {
+            Type::Bool
+            | Type::Uint32
+            | Type::Uint64
+            | Type::Int32
+            | Type::Int64
+            | Type::Sint32
+            | Type::Sint64
+            | Type::Enum(_) => Some(WireType::Varint),
+            Type::Fixed32 | Type::Sfixed32 | Type::Float => Some(WireType::ThirtyTwoBit),
+            Type::Fixed64 | Type::Sfixed64 | Type::Double => Some(WireType::SixtyFourBit),
+            Type::String | Type::Bytes | Type::FixedBytes(_) | Type::Message(_) => Some(WireType::LengthDelimited),
+            Type::Unknown => None,
+        }
+    }
+
+    /// Name of the type in `.proto` syntax.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Bool => "bool",
+            Type::Uint32 => "uint32",
+            Type::Uint64 => "uint64",
+            Type::Int32 => "int32",
+            Type::Int64 => "int64",
+            Type::Sint32 => "sint32",
+            Type::Sint64 => "sint64",
+            Type::Fixed32 => "fixed32",
+            Type::Fixed64 => "fixed64",
+            Type::Sfixed32 => "sfixed32",
+            Type::Sfixed64 => "sfixed64",
+            Type::Float => "float",
+            Type::Double => "double",
+            Type::String => "string",
+            Type::Bytes | Type::FixedBytes(_) => "bytes",
+            Type::Enum(e) => e.name,
+            Type::Message(m) => m.name,
+            Type::Unknown => "unknown",
+        }
+    }
+}
+
+/// Cardinality of a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Label {
+    /// Always present.
+    Single,
+    /// `optional`
+    Optional,
+    /// `repeated`
+    Repeated,
+}
+
+/// Description of a message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MessageDescriptor {
+    /// Name of the Rust type.
+    pub name: &'static str,
+    /// Fields not part of a oneof.
+    pub fields: &'static [FieldDescriptor],
+    /// Oneofs.
+    pub oneofs: &'static [OneofDescriptor],
+    /// Upper bound on the encoded length, or `usize::MAX` if unbounded.
+    pub max_encoded_len: usize,
+}
+
+impl MessageDescriptor {
+    /// Iterate over all fields, including those in oneofs.
+    pub fn all_fields(&self) -> impl Iterator<Item = &'static FieldDescriptor> {
+        let fields: &'static [FieldDescriptor] = self.fields;
+        let oneofs: &'static [OneofDescriptor] = self.oneofs;
+        fields.iter().chain(oneofs.iter().flat_map(|o| o.fields.iter()))
+    }
+
+    /// Find a field by tag, including those in oneofs.
+    pub fn field(&self, tag: u32) -> Option<&'static FieldDescriptor> {
+        self.all_fields().find(|f| f.tag == tag)
+    }
+
+    /// Hash of the wire-relevant parts of the schema: tags, labels and types of all fields,
+    /// including nested messages and enum values.
+    ///
+    /// Names and capacities are not included, so renaming a field or resizing a buffer doesn't
+    /// change the fingerprint.
+    pub const fn fingerprint(&self) -> u32 {
+        let mut h = hash_fields(FNV_OFFSET, self.fields);
+        let mut i = 0;
+        while i < self.oneofs.len() {
+            h = hash_u32(h, u32::MAX);
+            h = hash_fields(h, self.oneofs[i].fields);
+            i += 1;
+        }
+        h
+    }
+}
+
+const FNV_OFFSET: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// FNV-1a over the little-endian bytes of `val`.
+const fn hash_u32(mut h: u32, val: u32) -> u32 {
+    let bytes = val.to_le_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        h ^= bytes[i] as u32;
+        h = h.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    h
+}
+
+const fn hash_fields(mut h: u32, fields: &[FieldDescriptor]) -> u32 {
+    let mut i = 0;
+    while i < fields.len() {
+        let f = &fields[i];
+        h = hash_u32(h, f.tag);
+        h = hash_u32(h, f.label as u32);
+        h = match f.ty {
+            Type::Bool => hash_u32(h, 0),
+            Type::Uint32 => hash_u32(h, 1),
+            Type::Uint64 => hash_u32(h, 2),
+            Type::Int32 => hash_u32(h, 3),
+            Type::Int64 => hash_u32(h, 4),
+            Type::Sint32 => hash_u32(h, 5),
+            Type::Sint64 => hash_u32(h, 6),
+            Type::Fixed32 => hash_u32(h, 7),
+            Type::Fixed64 => hash_u32(h, 8),
+            Type::Sfixed32 => hash_u32(h, 9),
+            Type::Sfixed64 => hash_u32(h, 10),
+            Type::String => hash_u32(h, 11),
+            Type::Bytes => hash_u32(h, 12),
+            Type::Enum(e) => {
+                h = hash_u32(h, 13);
+                let mut j = 0;
+                while j < e.values.len() {
+                    h = hash_u32(h, e.values[j].1 as u32);
+                    j += 1;
+                }
+                h
+            }
+            Type::Message(m) => hash_u32(hash_u32(h, 14), m.fingerprint()),
+            Type::Unknown => hash_u32(h, 15),
+            Type::Float => hash_u32(h, 16),
+            Type::Double => hash_u32(h, 17),
+            Type::FixedBytes(len) => hash_u32(hash_u32(h, 20), len as u32),
+        };
+        if f.compressed {
+            h = hash_u32(h, 18);
+        }
+        if f.group {
+            h = hash_u32(h, 19);
+        }
+        i += 1;
+    }
+    h
+}
+
+/// Description of a message field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldDescriptor {
+    /// Name of the Rust field, or variant for oneof fields.
+    pub name: &'static str,
+    /// Field tag.
+    pub tag: u32,
+    /// Cardinality.
+    pub label: Label,
+    /// Type of a single value.
+    pub ty: Type,
+    /// Upper bound on the encoded length of a single value, or `usize::MAX` if unbounded.
+    pub max_len: usize,
+    /// Maximum number of values: 1, or the capacity of a repeated field.
+    pub max_count: usize,
+    /// Whether the field is marked `#[noproto(redact)]`.
+    pub redacted: bool,
+    /// Whether the field is marked `#[noproto(required)]`.
+    pub required: bool,
+    /// Whether the field is marked `#[noproto(compress)]`. It is then described as [`Type::Bytes`]
+    /// holding the compressed payload, which can't be generated or decoded without the codec.
+    pub compressed: bool,
+    /// Whether the field is marked `#[noproto(group)]`, its messages then being encoded with the
+    /// group wire type instead of length-delimited.
+    pub group: bool,
+}
+
+impl FieldDescriptor {
+    /// Wire type of a single value of this field, or `None` for [`Type::Unknown`].
+    pub fn wire_type(&self) -> Option<WireType> {
+        match self.group {
+            true => Some(WireType::StartGroup),
+            false => self.ty.wire_type(),
+        }
+    }
+}
+
+/// Description of a oneof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OneofDescriptor {
+    /// Name of the Rust field.
+    pub name: &'static str,
+    /// Variants.
+    pub fields: &'static [FieldDescriptor],
+}
+
+/// Description of an enumeration.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnumDescriptor {
+    /// Name of the Rust type.
+    pub name: &'static str,
+    /// Variant names and their values.
+    pub values: &'static [(&'static str, i32)],
+}
+
+/// Length of the serialized `google.protobuf.DescriptorProto` of `M`, see [`descriptor_proto`].
+///
+/// # Panics
+///
+/// Panics if `M` has no descriptor.
+pub const fn descriptor_proto_len<M: Message>() -> usize {
+    let mut w = ProtoWriter::counting();
+    message_proto(&mut w, descriptor_of::<M>());
+    w.pos
+}
+
+/// Serialize the descriptor of `M` as a `google.protobuf.DescriptorProto`, at compile time.
+///
+/// `N` must be [`descriptor_proto_len::<M>()`](descriptor_proto_len). The messages and enums
+/// used by the fields are included as nested types, so the description is self-contained.
+/// Derived messages without generic parameters provide it as their `DESCRIPTOR_PROTO` constant,
+/// so devices can describe themselves without any work at runtime.
+///
+/// Names are those of the Rust types and fields. Groups are described as `TYPE_GROUP` fields,
+/// and `optional` fields as `LABEL_OPTIONAL` like singular ones.
+///
+/// ```
+/// #[derive(noproto::Message, Default)]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// // name: "Ping", field { name: "seq", number: 1, label: LABEL_OPTIONAL, type: TYPE_UINT32 }
+/// let expected = b"\x0a\x04Ping\x12\x0b\x0a\x03seq\x18\x01\x20\x01\x28\x0d";
+/// assert_eq!(Ping::DESCRIPTOR_PROTO, expected);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `M` has no descriptor or `N` is wrong.
+pub const fn descriptor_proto<M: Message, const N: usize>() -> [u8; N] {
+    let mut buf = [0; N];
+    let mut w = ProtoWriter {
+        buf: &mut buf,
+        pos: 0,
+        counting: false,
+    };
+    message_proto(&mut w, descriptor_of::<M>());
+    assert!(w.pos == N, "wrong DescriptorProto length");
+    buf
+}
+
+const fn descriptor_of<M: Message>() -> &'static MessageDescriptor {
+    match descriptor::<M>() {
+        Some(desc) => desc,
+        None => panic!("message without descriptor"),
+    }
+}
+
+/// Minimal protobuf encoder usable in const fns. Submessages are written twice, first
+/// counting to get their length prefix.
+struct ProtoWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    counting: bool,
+}
+
+impl ProtoWriter<'_> {
+    const fn counting() -> ProtoWriter<'static> {
+        ProtoWriter {
+            buf: &mut [],
+            pos: 0,
+            counting: true,
+        }
+    }
+
+    const fn byte(&mut self, b: u8) {
+        if !self.counting {
+            self.buf[self.pos] = b;
+        }
+        self.pos += 1;
+    }
+
+    const fn varint(&mut self, mut val: u64) {
+        while val >= 0x80 {
+            self.byte(val as u8 | 0x80);
+            val >>= 7;
+        }
+        self.byte(val as u8);
+    }
+
+    const fn header(&mut self, tag: u32, wire_type: WireType) {
+        self.varint(((tag << 3) | wire_type as u32) as u64);
+    }
+
+    const fn uint(&mut self, tag: u32, val: u64) {
+        self.header(tag, WireType::Varint);
+        self.varint(val);
+    }
+
+    const fn string(&mut self, tag: u32, s: &str) {
+        let bytes = s.as_bytes();
+        self.header(tag, WireType::LengthDelimited);
+        self.varint(bytes.len() as u64);
+        let mut i = 0;
+        while i < bytes.len() {
+            self.byte(bytes[i]);
+            i += 1;
+        }
+    }
+}
+
+// Field numbers of `DescriptorProto`.
+const DESCRIPTOR_NAME: u32 = 1;
+const DESCRIPTOR_FIELD: u32 = 2;
+const DESCRIPTOR_NESTED_TYPE: u32 = 3;
+const DESCRIPTOR_ENUM_TYPE: u32 = 4;
+const DESCRIPTOR_ONEOF_DECL: u32 = 8;
+
+/// Write the fields of a `DescriptorProto`.
+const fn message_proto(w: &mut ProtoWriter, m: &MessageDescriptor) {
+    w.string(DESCRIPTOR_NAME, m.name);
+    let mut i = 0;
+    while i < m.fields.len() {
+        field_submessage(w, &m.fields[i], None);
+        i += 1;
+    }
+    let mut o = 0;
+    while o < m.oneofs.len() {
+        let mut i = 0;
+        while i < m.oneofs[o].fields.len() {
+            field_submessage(w, &m.oneofs[o].fields[i], Some(o));
+            i += 1;
+        }
+        o += 1;
+    }
+
+    // Each nested type once, at its first use.
+    let total = field_count(m);
+    let mut i = 0;
+    while i < total {
+        let ty = field_at(m, i).ty;
+        if first_use(m, i) {
+            match ty {
+                Type::Message(nested) => {
+                    let mut c = ProtoWriter::counting();
+                    message_proto(&mut c, nested);
+                    w.header(DESCRIPTOR_NESTED_TYPE, WireType::LengthDelimited);
+                    w.varint(c.pos as u64);
+                    message_proto(w, nested);
+                }
+                Type::Enum(e) => {
+                    let mut c = ProtoWriter::counting();
+                    enum_proto(&mut c, e);
+                    w.header(DESCRIPTOR_ENUM_TYPE, WireType::LengthDelimited);
+                    w.varint(c.pos as u64);
+                    enum_proto(w, e);
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+
+    let mut o = 0;
+    while o < m.oneofs.len() {
+        let name = m.oneofs[o].name;
+        let mut c = ProtoWriter::counting();
+        c.string(1, name);
+        w.header(DESCRIPTOR_ONEOF_DECL, WireType::LengthDelimited);
+        w.varint(c.pos as u64);
+        w.string(1, name);
+        o += 1;
+    }
+}
+
+const fn field_submessage(w: &mut ProtoWriter, f: &FieldDescriptor, oneof: Option<usize>) {
+    let mut c = ProtoWriter::counting();
+    field_proto(&mut c, f, oneof);
+    w.header(DESCRIPTOR_FIELD, WireType::LengthDelimited);
+    w.varint(c.pos as u64);
+    field_proto(w, f, oneof);
+}
+
+/// Write the fields of a `FieldDescriptorProto`.
+const fn field_proto(w: &mut ProtoWriter, f: &FieldDescriptor, oneof: Option<usize>) {
+    const LABEL_OPTIONAL: u64 = 1;
+    const LABEL_REQUIRED: u64 = 2;
+    const LABEL_REPEATED: u64 = 3;
+
+    w.string(1, f.name);
+    w.uint(3, f.tag as u64);
+    w.uint(
+        4,
+        match f.label {
+            Label::Repeated => LABEL_REPEATED,
+            _ if f.required => LABEL_REQUIRED,
+            Label::Single | Label::Optional => LABEL_OPTIONAL,
+        },
+    );
+    let ty = match f.ty {
+        Type::Double => 1,
+        Type::Float => 2,
+        Type::Int64 => 3,
+        Type::Uint64 => 4,
+        Type::Int32 => 5,
+        Type::Fixed64 => 6,
+        Type::Fixed32 => 7,
+        Type::Bool => 8,
+        Type::String => 9,
+        Type::Message(_) if f.group => 10,
+        Type::Message(_) => 11,
+        Type::Bytes | Type::FixedBytes(_) => 12,
+        Type::Uint32 => 13,
+        Type::Enum(_) => 14,
+        Type::Sfixed32 => 15,
+        Type::Sfixed64 => 16,
+        Type::Sint32 => 17,
+        Type::Sint64 => 18,
+        Type::Unknown => 0,
+    };
+    if ty != 0 {
+        w.uint(5, ty);
+    }
+    match f.ty {
+        Type::Message(m) => w.string(6, m.name),
+        Type::Enum(e) => w.string(6, e.name),
+        _ => {}
+    }
+    if let Some(oneof) = oneof {
+        w.uint(9, oneof as u64);
+    }
+}
+
+/// Write the fields of an `EnumDescriptorProto`.
+const fn enum_proto(w: &mut ProtoWriter, e: &EnumDescriptor) {
+    w.string(1, e.name);
+    let mut i = 0;
+    while i < e.values.len() {
+        let (name, number) = e.values[i];
+        let mut c = ProtoWriter::counting();
+        c.string(1, name);
+        c.uint(2, number as i64 as u64);
+        w.header(2, WireType::LengthDelimited);
+        w.varint(c.pos as u64);
+        w.string(1, name);
+        w.uint(2, number as i64 as u64);
+        i += 1;
+    }
+}
+
+/// Number of fields of `m`, including those in oneofs.
+const fn field_count(m: &MessageDescriptor) -> usize {
+    tag_count(&[Some(m)])
+}
+
+/// The `index`-th field of `m`, then of its oneofs.
+const fn field_at(m: &MessageDescriptor, mut index: usize) -> &FieldDescriptor {
+    if index < m.fields.len() {
+        return &m.fields[index];
+    }
+    index -= m.fields.len();
+    let mut o = 0;
+    loop {
+        let fields = m.oneofs[o].fields;
+        if index < fields.len() {
+            return &fields[index];
+        }
+        index -= fields.len();
+        o += 1;
+    }
+}
+
+/// Check if the type of the `index`-th field of `m` is a message or enum not used by an earlier
+/// field.
+const fn first_use(m: &MessageDescriptor, index: usize) -> bool {
+    let name = match field_at(m, index).ty {
+        Type::Message(nested) => nested.name,
+        Type::Enum(e) => e.name,
+        _ => return false,
+    };
+    let mut i = 0;
+    while i < index {
+        match field_at(m, i).ty {
+            Type::Message(nested) if str_eq(nested.name, name) => return false,
+            Type::Enum(e) if str_eq(e.name, name) => return false,
+            _ => {}
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(feature = "std")]
+mod json {
+    use core::fmt::{self, Write};
+
+    use super::*;
+
+    impl MessageDescriptor {
+        /// Machine-readable JSON description of the message, including nested messages and enums.
+        ///
+        /// Unbounded lengths (`usize::MAX`) are written as `null`.
+        pub fn to_json(&self) -> std::string::String {
+            let mut s = std::string::String::new();
+            write_message(&mut s, self).unwrap();
+            s
+        }
+    }
+
+    fn write_message(w: &mut impl Write, m: &MessageDescriptor) -> fmt::Result {
+        write!(w, "{{\"name\":\"{}\",\"max_encoded_len\":", m.name)?;
+        write_len(w, m.max_encoded_len)?;
+        w.write_str(",\"fields\":")?;
+        write_fields(w, m.fields)?;
+        w.write_str(",\"oneofs\":[")?;
+        for (i, o) in m.oneofs.iter().enumerate() {
+            if i != 0 {
+                w.write_char(',')?;
+            }
+            write!(w, "{{\"name\":\"{}\",\"fields\":", o.name)?;
+            write_fields(w, o.fields)?;
+            w.write_char('}')?;
+        }
+        w.write_str("]}")
+    }
+
+    fn write_fields(w: &mut impl Write, fields: &[FieldDescriptor]) -> fmt::Result {
+        w.write_char('[')?;
+        for (i, f) in fields.iter().enumerate() {
+            if i != 0 {
+                w.write_char(',')?;
+            }
+            let label = match f.label {
+                Label::Single => "single",
+                Label::Optional => "optional",
+                Label::Repeated => "repeated",
+            };
+            write!(
+                w,
+                "{{\"name\":\"{}\",\"tag\":{},\"label\":\"{}\",\"type\":\"{}\",\"max_len\":",
+                f.name,
+                f.tag,
+                label,
+                f.ty.name()
+            )?;
+            write_len(w, f.max_len)?;
+            w.write_str(",\"max_count\":")?;
+            write_len(w, f.max_count)?;
+            match f.ty {
+                Type::Message(m) => {
+                    w.write_str(",\"message\":")?;
+                    write_message(w, m)?;
+                }
+                Type::Enum(e) => {
+                    w.write_str(",\"enum\":{")?;
+                    for (i, (name, value)) in e.values.iter().enumerate() {
+                        if i != 0 {
+                            w.write_char(',')?;
+                        }
+                        write!(w, "\"{}\":{}", name, value)?;
+                    }
+                    w.write_char('}')?;
+                }
+                _ => {}
+            }
+            w.write_char('}')?;
+        }
+        w.write_char(']')
+    }
+
+    fn write_len(w: &mut impl Write, len: usize) -> fmt::Result {
+        match len {
+            usize::MAX => w.write_str("null"),
+            len => write!(w, "{}", len),
+        }
+    }
+}
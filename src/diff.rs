@@ -0,0 +1,150 @@
+//! Differential encoding against a baseline, for state sync.
+//!
+//! [`write_diff`] encodes only the top-level fields of a message that differ from a baseline,
+//! e.g. the state last acknowledged by the peer, and lists the fields that went back to their
+//! default. [`apply_diff`] turns the baseline into the new message on the other side.
+//!
+//! Fields are compared by their encoding, one top-level field at a time as split by
+//! [`Message::write_nth_field`]. A changed repeated field is sent whole.
+//!
+//! The diff is itself a protobuf message:
+//!
+//! ```text
+//! message Diff {
+//!   repeated bytes changed = 1; // a changed top-level field, with its header
+//!   repeated uint32 cleared = 2; // tag of a field now empty
+//! }
+//! ```
+//!
+//! ```
+//! use noproto::diff::{apply_diff, write_diff};
+//!
+//! #[derive(noproto::Message, Default, Clone, PartialEq, Debug)]
+//! struct State {
+//!     #[noproto(tag = 1)]
+//!     name: heapless::String<16>,
+//!     #[noproto(tag = 2)]
+//!     level: u32,
+//!     #[noproto(tag = 3, optional)]
+//!     fault: Option<u32>,
+//! }
+//!
+//! let old = State { name: "pump".try_into().unwrap(), level: 10, fault: Some(3) };
+//! let new = State { level: 11, fault: None, ..old.clone() };
+//!
+//! let mut buf = [0; 64];
+//! let n = write_diff(&new, &old, &mut buf).unwrap();
+//! assert_eq!(&buf[..n], [0x0a, 0x02, 0x10, 0x0b, 0x10, 0x03]);
+//!
+//! let mut state = old.clone();
+//! apply_diff(&mut state, &buf[..n], &mut [0; 64]).unwrap();
+//! assert_eq!(state, new);
+//! ```
+
+use crate::read::ByteReader;
+use crate::write::{ByteWriter, MAX_VARUINT32_LEN};
+use crate::{Message, ReadError, WriteError};
+
+const CHANGED: u32 = 1;
+const CLEARED: u32 = 2;
+
+/// Room for the header of a `changed` entry.
+const HEADER_LEN: usize = 1 + MAX_VARUINT32_LEN;
+
+/// Encode the top-level fields of `msg` that differ from `baseline`.
+///
+/// `buf` also holds both versions of each field while comparing them, so it needs room for
+/// them after the diff written so far. Returns the number of bytes written to `buf`.
+pub fn write_diff<M: Message>(msg: &M, baseline: &M, buf: &mut [u8]) -> Result<usize, WriteError> {
+    let mut pos = 0;
+    for index in 0.. {
+        // Encode both versions of the field, leaving room for the header in front.
+        let start = pos + HEADER_LEN;
+        let scratch = buf.get_mut(start..).ok_or(WriteError)?;
+        let mut w = ByteWriter::new(scratch);
+        if !msg.write_nth_field(index, &mut w)? {
+            break;
+        }
+        let len = w.pos();
+        let (new, rest) = scratch.split_at_mut(len);
+        let mut w = ByteWriter::new(rest);
+        baseline.write_nth_field(index, &mut w)?;
+        let old = w.bytes();
+        if new == old {
+            continue;
+        }
+
+        let mut header = [0; HEADER_LEN];
+        let mut h = ByteWriter::new(&mut header);
+        if len == 0 {
+            let tag = ByteReader::new(old).read_varuint32().map_err(|_| WriteError)? >> 3;
+            h.write_varuint32(CLEARED << 3)?;
+            h.write_varuint32(tag)?;
+            let h = h.bytes();
+            buf[pos..][..h.len()].copy_from_slice(h);
+            pos += h.len();
+        } else {
+            h.write_varuint32((CHANGED << 3) | 2)?;
+            h.write_varuint32(len as u32)?;
+            let h = h.bytes();
+            buf.copy_within(start..start + len, pos + h.len());
+            buf[pos..][..h.len()].copy_from_slice(h);
+            pos += h.len() + len;
+        }
+    }
+    Ok(pos)
+}
+
+/// Update `msg`, holding the baseline of a diff written by [`write_diff`], to the new message.
+///
+/// The fields of the baseline not touched by the diff are encoded into `buf` along with the
+/// changed ones, and decoded into a new message, so `buf` must fit the whole new message.
+/// `msg` is left unchanged on error.
+pub fn apply_diff<M: Message + Default>(msg: &mut M, diff: &[u8], buf: &mut [u8]) -> Result<(), ReadError> {
+    let mut pos = 0;
+    for index in 0.. {
+        let mut w = ByteWriter::new(buf.get_mut(pos..).ok_or(ReadError::CapacityExceeded)?);
+        if !msg
+            .write_nth_field(index, &mut w)
+            .map_err(|_| ReadError::CapacityExceeded)?
+        {
+            break;
+        }
+        let field = w.bytes();
+        if field.is_empty() {
+            continue;
+        }
+        let tag = ByteReader::new(field).read_varuint32()? >> 3;
+        if !touches(diff, tag)? {
+            pos += field.len();
+        }
+    }
+    for field in ByteReader::new(diff).fields() {
+        let field = field?;
+        if field.tag() == CHANGED {
+            let data = field.data();
+            buf.get_mut(pos..pos + data.len())
+                .ok_or(ReadError::CapacityExceeded)?
+                .copy_from_slice(data);
+            pos += data.len();
+        }
+    }
+    *msg = crate::read(&buf[..pos])?;
+    Ok(())
+}
+
+/// Check if `diff` changes or clears the field with the given tag.
+fn touches(diff: &[u8], tag: u32) -> Result<bool, ReadError> {
+    for field in ByteReader::new(diff).fields() {
+        let field = field?;
+        let touched = match field.tag() {
+            CHANGED => ByteReader::new(field.data()).read_varuint32()? >> 3,
+            CLEARED => ByteReader::new(field.data()).read_varuint32()?,
+            _ => return Err(ReadError::InvalidData),
+        };
+        if touched == tag {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,235 @@
+//! Standard envelope around message payloads.
+//!
+//! An [`Envelope`] carries a version, an application-assigned type ID and flags next to the
+//! payload, so receivers can check the header with [`Header::peek`] before picking the payload
+//! type to decode. [`dispatch!`](crate::dispatch) does both, calling a handler per type ID.
+//!
+//! On the wire it's a regular message, with the header fields before the payload:
+//!
+//! ```text
+//! message Envelope {
+//!     uint32 version = 1;
+//!     uint32 type_id = 2;
+//!     uint32 flags = 3;
+//!     Payload payload = 4;
+//! }
+//! ```
+//!
+//! ```
+//! use noproto::envelope::{Envelope, Header};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//! }
+//!
+//! let mut buf = [0; 32];
+//! let n = noproto::write(&Envelope::new(7, Ping { seq: 1 }), &mut buf).unwrap();
+//!
+//! let header = Header::peek(&buf[..n]).unwrap();
+//! assert_eq!(header.type_id, 7);
+//! let ping = Envelope::<Ping>::read(&buf[..n]).unwrap().payload;
+//! assert_eq!(ping, Ping { seq: 1 });
+//! ```
+
+use crate::encoding::max_field_len;
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, ValidationError, WireType, WriteError};
+
+/// Envelope header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Header {
+    /// Format version of the payload.
+    pub version: u8,
+    /// Application-assigned type ID of the payload.
+    pub type_id: u32,
+    /// Application-defined flags.
+    pub flags: u32,
+}
+
+impl Header {
+    /// Decode only the header of an encoded envelope, without parsing the payload.
+    pub fn peek(buf: &[u8]) -> Result<Header, ReadError> {
+        crate::read_until_tags(buf, &[1, 2, 3])
+    }
+}
+
+impl Message for Header {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = max_field_len::<u8>(1) + max_field_len::<u32>(2) + max_field_len::<u32>(3);
+
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_field(1, &self.version)?;
+        w.write_field(2, &self.type_id)?;
+        w.write_field(3, &self.flags)?;
+        Ok(())
+    }
+
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        for r in r.read_fields() {
+            let r = r?;
+            match r.tag() {
+                1 => r.read(&mut self.version)?,
+                2 => r.read(&mut self.type_id)?,
+                3 => r.read(&mut self.flags)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned by [`dispatch!`](crate::dispatch).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DispatchError {
+    /// The envelope or its payload could not be decoded.
+    Read(ReadError),
+    /// No handler matches the type ID of the envelope.
+    UnknownType(u32),
+}
+
+impl core::fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DispatchError::Read(e) => e.fmt(f),
+            DispatchError::UnknownType(type_id) => write!(f, "unknown envelope type {}", type_id),
+        }
+    }
+}
+
+impl core::error::Error for DispatchError {}
+
+impl From<ReadError> for DispatchError {
+    fn from(e: ReadError) -> Self {
+        DispatchError::Read(e)
+    }
+}
+
+/// Decode an encoded [`Envelope`] and call the handler matching its type ID with the typed
+/// payload.
+///
+/// Each arm maps a type ID pattern to a payload type and a handler (a function or closure),
+/// and all handlers must return the same type. Evaluates to a `Result` with the handler's
+/// return value, or a [`DispatchError`].
+///
+/// ```
+/// use noproto::envelope::{DispatchError, Envelope};
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// #[derive(noproto::Message, Default)]
+/// struct SetLed {
+///     #[noproto(tag = 1)]
+///     on: bool,
+/// }
+///
+/// const PING: u32 = 1;
+/// const SET_LED: u32 = 2;
+///
+/// fn handle_ping(ping: Ping) -> u32 {
+///     ping.seq
+/// }
+///
+/// let mut led = false;
+/// let mut buf = [0; 32];
+/// let n = noproto::write(&Envelope::new(SET_LED, SetLed { on: true }), &mut buf).unwrap();
+///
+/// let res = noproto::dispatch!(&buf[..n], {
+///     PING => Ping => handle_ping,
+///     SET_LED => SetLed => |cmd: SetLed| {
+///         led = cmd.on;
+///         0
+///     },
+/// });
+/// assert_eq!(res, Ok(0));
+/// assert!(led);
+///
+/// let n = noproto::write(&Envelope::new(3, Ping { seq: 1 }), &mut buf).unwrap();
+/// let res = noproto::dispatch!(&buf[..n], { PING => Ping => handle_ping });
+/// assert_eq!(res, Err(DispatchError::UnknownType(3)));
+/// ```
+#[macro_export]
+macro_rules! dispatch {
+    ($buf:expr, { $($type_id:pat => $ty:ty => $handler:expr),* $(,)? }) => {{
+        let buf: &[u8] = $buf;
+        match $crate::envelope::Header::peek(buf) {
+            Err(e) => Err($crate::envelope::DispatchError::Read(e)),
+            Ok(header) => match header.type_id {
+                $(
+                    $type_id => match $crate::envelope::Envelope::<$ty>::read(buf) {
+                        Ok(envelope) => Ok(($handler)(envelope.payload)),
+                        Err(e) => Err($crate::envelope::DispatchError::Read(e)),
+                    },
+                )*
+                #[allow(unreachable_patterns)]
+                type_id => Err($crate::envelope::DispatchError::UnknownType(type_id)),
+            },
+        }
+    }};
+}
+
+/// A payload with a [`Header`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Envelope<M> {
+    /// Header.
+    pub header: Header,
+    /// Payload.
+    pub payload: M,
+}
+
+impl<M: Message> Envelope<M> {
+    /// Wrap `payload` with the given type ID, version 0 and no flags.
+    pub fn new(type_id: u32, payload: M) -> Self {
+        Self {
+            header: Header {
+                type_id,
+                ..Default::default()
+            },
+            payload,
+        }
+    }
+
+    /// Decode an envelope from a buffer.
+    pub fn read(buf: &[u8]) -> Result<Self, ReadError>
+    where
+        M: Default,
+    {
+        crate::read(buf)
+    }
+}
+
+impl<M: Message> Message for Envelope<M> {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = Header::MAX_ENCODED_LEN.saturating_add(max_field_len::<M>(4));
+    const FALLIBLE_WRITE: bool = M::FALLIBLE_WRITE;
+
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        // The header fields are inlined, not nested.
+        self.header.write_raw(w)?;
+        w.write_field(4, &self.payload)
+    }
+
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        for r in r.read_fields() {
+            let r = r?;
+            match r.tag() {
+                1 => r.read(&mut self.header.version)?,
+                2 => r.read(&mut self.header.type_id)?,
+                3 => r.read(&mut self.header.flags)?,
+                4 => r.read(&mut self.payload)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.payload.validate()
+    }
+}
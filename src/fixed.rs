@@ -0,0 +1,58 @@
+/// Fixed-width primitive, stored on the wire as its little-endian bytes.
+///
+/// Implemented for `u32`, `i32`, `f32`, `u64`, `i64` and `f64`, and also `u8`, `i8`, `u16` and
+/// `i16` for [`RawLe`](crate::types::RawLe). Any bit pattern is a valid value of these types, so slices of them can be copied to and from the wire as raw bytes
+/// on little-endian targets.
+pub trait FixedLe: Copy + sealed::Sealed {
+    /// Encoded size in bytes.
+    const SIZE: usize;
+    /// Encode to little-endian bytes, `out` has length `SIZE`.
+    fn put_le(self, out: &mut [u8]);
+    /// Decode from little-endian bytes, `bytes` has length `SIZE`.
+    fn get_le(bytes: &[u8]) -> Self;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! fixed_le {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl FixedLe for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+
+                fn put_le(self, out: &mut [u8]) {
+                    // Not `copy_from_slice`, its length check formats a panic message.
+                    if let Some(out) = out.first_chunk_mut() {
+                        *out = self.to_le_bytes();
+                    }
+                }
+
+                fn get_le(bytes: &[u8]) -> Self {
+                    // Not unwrapped to keep `core::fmt` out of decoding, callers pass `SIZE` bytes.
+                    let zero = [0; core::mem::size_of::<$ty>()];
+                    <$ty>::from_le_bytes(*bytes.first_chunk().unwrap_or(&zero))
+                }
+            }
+        )*
+    };
+}
+
+fixed_le!(u8, i8, u16, i16, u32, i32, f32, u64, i64, f64);
+
+/// View `vals` as raw bytes.
+#[cfg(target_endian = "little")]
+pub(crate) fn as_bytes<T: FixedLe>(vals: &[T]) -> &[u8] {
+    // SAFETY: `FixedLe` types have no padding, and `u8` has no alignment requirement.
+    unsafe { core::slice::from_raw_parts(vals.as_ptr() as *const u8, core::mem::size_of_val(vals)) }
+}
+
+/// View `vals` as mutable raw bytes.
+#[cfg(target_endian = "little")]
+pub(crate) fn as_bytes_mut<T: FixedLe>(vals: &mut [T]) -> &mut [u8] {
+    // SAFETY: `FixedLe` types have no padding, and any bit pattern is a valid value.
+    unsafe { core::slice::from_raw_parts_mut(vals.as_mut_ptr() as *mut u8, core::mem::size_of_val(vals)) }
+}
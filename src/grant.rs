@@ -0,0 +1,106 @@
+//! Encoding directly into queues with a grant-based API, like bbqueue.
+//!
+//! The message length is computed first, then a write grant of exactly that size is requested
+//! from the queue, the message is encoded into it and the grant is committed. This avoids
+//! encoding into a staging buffer and copying it into the queue.
+//!
+//! ```
+//! use noproto::grant::{write_granted, Grant, GrantProducer};
+//!
+//! /// A toy queue, handing out grants at the end of a buffer.
+//! struct Queue {
+//!     buf: [u8; 64],
+//!     len: usize,
+//! }
+//!
+//! struct QueueGrant<'a> {
+//!     buf: &'a mut [u8],
+//!     len: &'a mut usize,
+//! }
+//!
+//! impl core::ops::Deref for QueueGrant<'_> {
+//!     type Target = [u8];
+//!     fn deref(&self) -> &[u8] {
+//!         self.buf
+//!     }
+//! }
+//!
+//! impl core::ops::DerefMut for QueueGrant<'_> {
+//!     fn deref_mut(&mut self) -> &mut [u8] {
+//!         self.buf
+//!     }
+//! }
+//!
+//! impl Grant for QueueGrant<'_> {
+//!     fn commit(self, used: usize) {
+//!         *self.len += used;
+//!     }
+//! }
+//!
+//! impl GrantProducer for Queue {
+//!     type Grant<'a> = QueueGrant<'a>;
+//!     type Error = ();
+//!
+//!     fn grant_exact(&mut self, len: usize) -> Result<QueueGrant<'_>, ()> {
+//!         let buf = self.buf[self.len..].get_mut(..len).ok_or(())?;
+//!         Ok(QueueGrant { buf, len: &mut self.len })
+//!     }
+//! }
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//! }
+//!
+//! let mut queue = Queue { buf: [0; 64], len: 0 };
+//! write_granted(&mut queue, &Ping { seq: 1 }).unwrap();
+//! assert_eq!(queue.buf[..queue.len], [0x08, 0x01]);
+//! ```
+
+use core::ops::DerefMut;
+
+use crate::write::ByteWriter;
+use crate::{Message, WriteError};
+
+/// A writable region of a queue, like bbqueue's `GrantW`.
+pub trait Grant: DerefMut<Target = [u8]> {
+    /// Make the first `used` bytes of the grant available to the consumer.
+    fn commit(self, used: usize);
+}
+
+/// Producer side of a queue handing out write grants, like bbqueue's `Producer`.
+pub trait GrantProducer {
+    /// Write grant type.
+    type Grant<'a>: Grant
+    where
+        Self: 'a;
+    /// Error returned when a grant is not available.
+    type Error;
+
+    /// Request a grant of exactly `len` bytes.
+    fn grant_exact(&mut self, len: usize) -> Result<Self::Grant<'_>, Self::Error>;
+}
+
+/// Error returned by [`write_granted`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GrantError<E> {
+    /// The message could not be encoded.
+    Write(WriteError),
+    /// The queue could not grant enough space.
+    Grant(E),
+}
+
+/// Encode `msg` directly into a grant from `producer`, and commit it.
+///
+/// Returns the number of bytes committed. Nothing is committed if encoding fails.
+pub fn write_granted<P: GrantProducer, M: Message>(producer: &mut P, msg: &M) -> Result<usize, GrantError<P::Error>> {
+    let len = msg.encoded_len().map_err(GrantError::Write)?;
+
+    let mut grant = producer.grant_exact(len).map_err(GrantError::Grant)?;
+    let mut w = ByteWriter::new(&mut grant[..len]);
+    msg.write_raw(&mut w).map_err(GrantError::Write)?;
+    let used = crate::encoded(msg, w.pos());
+    grant.commit(used);
+    Ok(used)
+}
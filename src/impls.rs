@@ -1,19 +1,27 @@
+use core::marker::PhantomData;
+
+use crate::descriptor::{FieldDescriptor, Type};
 use crate::read::ByteReader;
+use crate::reverse::ReverseWriter;
 use crate::write::ByteWriter;
-use crate::{Message, Oneof, OptionalMessage, ReadError, RepeatedMessage, WireType, WriteError};
+use crate::{Message, Oneof, OptionalMessage, ReadError, RepeatedMessage, ValidationError, WireType, WriteError};
 
 impl Message for bool {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 1;
+    const TYPE: Type = Type::Bool;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varuint32(*self as _)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        let val = r.read_varuint32()?;
+        let val = r.read_varuint64()?;
 
         *self = match val {
             0 => false,
             1 => true,
-            _ => return Err(ReadError),
+            _ if r.config().strict_bool => return Err(ReadError::OutOfRange),
+            // Any non-zero value is true, as required by the spec.
+            _ => true,
         };
         Ok(())
     }
@@ -21,115 +29,181 @@ impl Message for bool {
 
 impl Message for u8 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 2;
+    const TYPE: Type = Type::Uint32;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varuint32(*self as _)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varuint32()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varuint32()?.try_into().map_err(|_| ReadError::OutOfRange)?;
         Ok(())
     }
 }
 
 impl Message for u16 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 3;
+    const TYPE: Type = Type::Uint32;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varuint32(*self as _)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varuint32()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varuint32()?.try_into().map_err(|_| ReadError::OutOfRange)?;
         Ok(())
     }
 }
 
 impl Message for u32 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 5;
+    const TYPE: Type = Type::Uint32;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varuint32(*self)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varuint32()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varuint32()?;
         Ok(())
     }
 }
 
 impl Message for u64 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 10;
+    const TYPE: Type = Type::Uint64;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varuint64(*self)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varuint64()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varuint64()?;
         Ok(())
     }
 }
 
 impl Message for i8 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 2;
+    const TYPE: Type = Type::Sint32;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varint32(*self as _)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varint32()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varint32()?.try_into().map_err(|_| ReadError::OutOfRange)?;
         Ok(())
     }
 }
 
 impl Message for i16 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 3;
+    const TYPE: Type = Type::Sint32;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varint32(*self as _)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varint32()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varint32()?.try_into().map_err(|_| ReadError::OutOfRange)?;
         Ok(())
     }
 }
 
 impl Message for i32 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 5;
+    const TYPE: Type = Type::Sint32;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varint32(*self)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varint32()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varint32()?;
         Ok(())
     }
 }
 
 impl Message for i64 {
     const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 10;
+    const TYPE: Type = Type::Sint64;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write_varint64(*self)
     }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
-        *self = r.read_varint64()?.try_into().map_err(|_| ReadError)?;
+        *self = r.read_varint64()?;
+        Ok(())
+    }
+}
+
+impl Message for f32 {
+    const WIRE_TYPE: WireType = WireType::ThirtyTwoBit;
+    const MAX_ENCODED_LEN: usize = 4;
+    const TYPE: Type = Type::Float;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_u32(self.to_bits())
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        *self = f32::from_bits(r.read_u32()?);
+        Ok(())
+    }
+}
+
+impl Message for f64 {
+    const WIRE_TYPE: WireType = WireType::SixtyFourBit;
+    const MAX_ENCODED_LEN: usize = 8;
+    const TYPE: Type = Type::Double;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_u64(self.to_bits())
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        *self = f64::from_bits(r.read_u64()?);
         Ok(())
     }
 }
 
 impl<const N: usize> Message for heapless::String<N> {
     const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = N;
+    const TYPE: Type = Type::String;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write(self.as_bytes())
     }
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        w.write(self.as_bytes())
+    }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
         let data = r.read_to_end()?;
-        let data = core::str::from_utf8(data).map_err(|_| ReadError)?;
+        let data = core::str::from_utf8(data).map_err(|_| ReadError::InvalidUtf8)?;
         self.clear();
-        self.push_str(data).map_err(|_| ReadError)?;
+        self.push_str(data).map_err(|_| ReadError::CapacityExceeded)?;
+        Ok(())
+    }
+    fn validate(&self) -> Result<(), ValidationError> {
+        // Check the length first, the contents can't be looked at if it's out of bounds.
+        if self.len() > N {
+            return Err(ValidationError::Capacity);
+        }
+        core::str::from_utf8(self.as_bytes()).map_err(|_| ValidationError::Utf8)?;
         Ok(())
     }
 }
 
 impl<const N: usize> Message for heapless::Vec<u8, N> {
     const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = N;
+    const TYPE: Type = Type::Bytes;
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         w.write(self)
     }
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        w.write(self)
+    }
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
         let data = r.read_to_end()?;
         self.clear();
-        self.extend_from_slice(data).map_err(|_| ReadError)?;
+        self.extend_from_slice(data).map_err(|_| ReadError::CapacityExceeded)?;
+        Ok(())
+    }
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.len() > N {
+            return Err(ValidationError::Capacity);
+        }
         Ok(())
     }
 }
@@ -137,6 +211,8 @@ impl<const N: usize> Message for heapless::Vec<u8, N> {
 impl<M: Message + Default, const N: usize> RepeatedMessage for heapless::Vec<M, N> {
     type Message = M;
 
+    const MAX_LEN: usize = N;
+
     type Iter<'a> = core::slice::Iter<'a, M> where Self: 'a ;
 
     fn iter(&self) -> Result<Self::Iter<'_>, WriteError> {
@@ -144,7 +220,14 @@ impl<M: Message + Default, const N: usize> RepeatedMessage for heapless::Vec<M,
     }
 
     fn append(&mut self, m: Self::Message) -> Result<(), ReadError> {
-        self.push(m).map_err(|_| ReadError)
+        self.push(m).map_err(|_| ReadError::CapacityExceeded)
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.len() > N {
+            return Err(ValidationError::Capacity);
+        }
+        self[..].iter().try_for_each(Message::validate)
     }
 }
 
@@ -161,7 +244,17 @@ impl<M: Message + Default> OptionalMessage for Option<M> {
     }
 }
 
+struct NestedOption<M>(PhantomData<M>);
+
+impl<M> NestedOption<M> {
+    const REJECT: () = panic!("cannot nest options with oneof");
+}
+
 impl<M: Oneof> Oneof for Option<M> {
+    const MAX_ENCODED_LEN: usize = M::MAX_ENCODED_LEN;
+    const FIELDS: &'static [FieldDescriptor] = M::FIELDS;
+    const FALLIBLE_WRITE: bool = M::FALLIBLE_WRITE;
+
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
         if let Some(x) = self {
             x.write_raw(w)?;
@@ -174,6 +267,20 @@ impl<M: Oneof> Oneof for Option<M> {
     }
 
     fn read_raw_option(_this: &mut Option<Self>, _r: crate::encoding::FieldReader) -> Result<(), ReadError> {
-        panic!("cannot nest options with oneof.")
+        // Rejects `Option<Option<M>>` oneof fields when building, as the inner `None` can't be
+        // told apart from the outer one on the wire.
+        let () = NestedOption::<M>::REJECT;
+        Err(ReadError::InvalidData)
+    }
+
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        if let Some(x) = self {
+            x.write_reverse(w)?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.as_ref().map_or(Ok(()), M::validate)
     }
 }
@@ -0,0 +1,111 @@
+use crate::read::{ByteReader, Required};
+use crate::{Message, ReadError};
+
+/// Encoded data split across several buffers, e.g. a DMA descriptor chain or the two halves
+/// of a ring buffer. See [`read_gather`](crate::read_gather).
+pub trait Input {
+    /// Get the `index`-th segment of the data, or `None` past the last one.
+    fn segment(&self, index: usize) -> Option<&[u8]>;
+}
+
+impl Input for [u8] {
+    fn segment(&self, index: usize) -> Option<&[u8]> {
+        (index == 0).then_some(self)
+    }
+}
+
+impl Input for [&[u8]] {
+    fn segment(&self, index: usize) -> Option<&[u8]> {
+        self.get(index).copied()
+    }
+}
+
+impl<const K: usize> Input for [&[u8]; K] {
+    fn segment(&self, index: usize) -> Option<&[u8]> {
+        self.get(index).copied()
+    }
+}
+
+impl Input for (&[u8], &[u8]) {
+    fn segment(&self, index: usize) -> Option<&[u8]> {
+        match index {
+            0 => Some(self.0),
+            1 => Some(self.1),
+            _ => None,
+        }
+    }
+}
+
+/// Merge the fields of `input` into `msg`.
+///
+/// Fields within a segment are decoded in place. A field straddling segments is copied into
+/// `carry` first, which must fit it.
+pub(crate) fn merge_gather<I: Input + ?Sized, M: Message>(
+    input: &I,
+    carry: &mut [u8],
+    msg: &mut M,
+) -> Result<(), ReadError> {
+    let mut required = Required::new::<M>();
+    let mut index = 0;
+    // Bytes of the current segment already consumed by a straddling field.
+    let mut skip = 0;
+    while let Some(segment) = input.segment(index) {
+        let mut data = segment.get(skip..).ok_or(ReadError::InvalidData)?;
+        skip = 0;
+
+        // Decode the fields fully contained in the segment.
+        let mut fields = ByteReader::new(data).fields();
+        loop {
+            let start = data.len() - fields.remaining().len();
+            match fields.next() {
+                Some(Ok(field)) => {
+                    required.see(field.tag());
+                    let end = data.len() - fields.remaining().len();
+                    msg.read_raw(&mut ByteReader::partial(&data[start..end]))?;
+                }
+                Some(Err(_)) => {
+                    data = &data[start..];
+                    break;
+                }
+                None => {
+                    data = &[];
+                    break;
+                }
+            }
+        }
+        index += 1;
+        if data.is_empty() {
+            continue;
+        }
+
+        // The last field is cut, fill `carry` with as much as it holds and decode it from there.
+        let mut len = data.len();
+        carry
+            .get_mut(..len)
+            .ok_or(ReadError::CapacityExceeded)?
+            .copy_from_slice(data);
+        let mut next = index;
+        while len < carry.len() {
+            let Some(segment) = input.segment(next) else { break };
+            let n = segment.len().min(carry.len() - len);
+            carry[len..][..n].copy_from_slice(&segment[..n]);
+            len += n;
+            next += 1;
+        }
+        let mut fields = ByteReader::new(&carry[..len]).fields();
+        required.see(fields.next().ok_or(ReadError::UnexpectedEof)??.tag());
+        let end = len - fields.remaining().len();
+        msg.read_raw(&mut ByteReader::partial(&carry[..end]))?;
+
+        // Resume after the field, in whichever segment it ends.
+        skip = end - data.len();
+        while let Some(segment) = input.segment(index) {
+            if skip < segment.len() {
+                break;
+            }
+            skip -= segment.len();
+            index += 1;
+        }
+    }
+    required.check()
+}
@@ -0,0 +1,261 @@
+//! Interned strings, for string fields with few distinct values.
+//!
+//! Fields like unit names or labels often repeat the same few values across a message. An
+//! [`InternedStr`] field stores only a reference to a shared copy of its value, kept in a table
+//! selected by its type parameter, so duplicates don't need storage of their own and the
+//! message doesn't need capacity for the longest value in each field.
+//!
+//! ```
+//! use noproto::intern::{InternTable, InternedStr, Interner};
+//!
+//! static UNITS: Interner<8, 64> = Interner::new();
+//!
+//! struct Units;
+//!
+//! impl InternTable for Units {
+//!     fn intern(s: &str) -> Option<&'static str> {
+//!         UNITS.intern(s)
+//!     }
+//! }
+//!
+//! #[derive(noproto::Message, Default, Debug)]
+//! struct Reading {
+//!     #[noproto(tag = 1)]
+//!     value: f32,
+//!     #[noproto(tag = 2)]
+//!     unit: InternedStr<Units>,
+//! }
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Report {
+//!     #[noproto(tag = 1, repeated)]
+//!     readings: heapless::Vec<Reading, 16>,
+//! }
+//!
+//! let mut report = Report::default();
+//! for unit in ["degC", "%RH", "degC", "degC"] {
+//!     let unit = InternedStr::new(unit).unwrap();
+//!     report.readings.push(Reading { value: 1.0, unit }).unwrap();
+//! }
+//! let mut buf = [0; 128];
+//! let n = noproto::write(&report, &mut buf).unwrap();
+//!
+//! let report: Report = noproto::read(&buf[..n]).unwrap();
+//! assert_eq!(report.readings[3].unit, "degC");
+//! assert_eq!(UNITS.len(), 2);
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use crate::descriptor::Type;
+use crate::encoding::{ByteReader, ByteWriter, ReverseWriter};
+use crate::{Message, ReadError, WireType, WriteError};
+
+/// A table of interned strings, selecting where [`InternedStr`] fields store their values.
+pub trait InternTable {
+    /// Get the shared copy of `s`, adding it to the table if needed, or `None` if the table
+    /// is full.
+    fn intern(s: &str) -> Option<&'static str>;
+}
+
+/// `string` field whose value is shared through the intern table `T`.
+pub struct InternedStr<T> {
+    s: &'static str,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: InternTable> InternedStr<T> {
+    /// Intern `s`, or return `None` if the table is full.
+    pub fn new(s: &str) -> Option<Self> {
+        Some(Self::from_static(T::intern(s)?))
+    }
+}
+
+impl<T> InternedStr<T> {
+    /// Create a field referring to a static string, without adding it to the table.
+    pub const fn from_static(s: &'static str) -> Self {
+        Self {
+            s,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Get the string.
+    pub fn as_str(&self) -> &'static str {
+        self.s
+    }
+}
+
+impl<T> Default for InternedStr<T> {
+    fn default() -> Self {
+        Self::from_static("")
+    }
+}
+
+impl<T> Clone for InternedStr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for InternedStr<T> {}
+
+impl<T> Deref for InternedStr<T> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.s
+    }
+}
+
+impl<T> PartialEq for InternedStr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.s == other.s
+    }
+}
+
+impl<T> Eq for InternedStr<T> {}
+
+impl<T> PartialEq<str> for InternedStr<T> {
+    fn eq(&self, other: &str) -> bool {
+        self.s == other
+    }
+}
+
+impl<T> PartialEq<&str> for InternedStr<T> {
+    fn eq(&self, other: &&str) -> bool {
+        self.s == *other
+    }
+}
+
+impl<T> core::hash::Hash for InternedStr<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.s.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for InternedStr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.s.fmt(f)
+    }
+}
+
+impl<T> fmt::Display for InternedStr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.s.fmt(f)
+    }
+}
+
+impl<T: InternTable> Message for InternedStr<T> {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const TYPE: Type = Type::String;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write(self.s.as_bytes())
+    }
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        w.write(self.s.as_bytes())
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        let data = r.read_to_end()?;
+        let s = core::str::from_utf8(data).map_err(|_| ReadError::InvalidUtf8)?;
+        self.s = T::intern(s).ok_or(ReadError::CapacityExceeded)?;
+        Ok(())
+    }
+}
+
+#[cfg(target_has_atomic = "8")]
+pub use interner::Interner;
+
+#[cfg(target_has_atomic = "8")]
+mod interner {
+    use core::cell::UnsafeCell;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Intern table of up to `N` strings totalling up to `S` bytes, usable in a `static`.
+    ///
+    /// Strings are never removed. Interning fails instead of waiting if the table is in use,
+    /// e.g. by a decode interrupted by another one, so it's safe to use from interrupts.
+    ///
+    /// Requires atomic compare-and-swap support on the target.
+    pub struct Interner<const N: usize, const S: usize> {
+        locked: AtomicBool,
+        len: AtomicUsize,
+        strings: UnsafeCell<[&'static str; N]>,
+        bytes: UnsafeCell<[u8; S]>,
+        used: UnsafeCell<usize>,
+    }
+
+    // SAFETY: `strings`, `used` and the unused part of `bytes` are only accessed with `locked`
+    // held. The used part of `bytes` is never written again.
+    unsafe impl<const N: usize, const S: usize> Sync for Interner<N, S> {}
+
+    impl<const N: usize, const S: usize> Interner<N, S> {
+        /// Create an empty table.
+        pub const fn new() -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                len: AtomicUsize::new(0),
+                strings: UnsafeCell::new([""; N]),
+                bytes: UnsafeCell::new([0; S]),
+                used: UnsafeCell::new(0),
+            }
+        }
+
+        /// Get the number of strings in the table.
+        pub fn len(&self) -> usize {
+            self.len.load(Ordering::Relaxed)
+        }
+
+        /// Check if the table is empty.
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Get the shared copy of `s`, adding it to the table if needed. Returns `None` if
+        /// the table is full or in use.
+        pub fn intern(&'static self, s: &str) -> Option<&'static str> {
+            if s.is_empty() {
+                return Some("");
+            }
+            self.locked
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .ok()?;
+            // SAFETY: the lock is held.
+            let res = unsafe { self.intern_locked(s) };
+            self.locked.store(false, Ordering::Release);
+            res
+        }
+
+        /// # Safety
+        ///
+        /// The lock must be held.
+        unsafe fn intern_locked(&'static self, s: &str) -> Option<&'static str> {
+            let strings = &mut *self.strings.get();
+            let len = self.len.load(Ordering::Relaxed);
+            if let Some(&found) = strings[..len].iter().find(|&&found| found == s) {
+                return Some(found);
+            }
+            let used = &mut *self.used.get();
+            if len == N || S - *used < s.len() {
+                return None;
+            }
+
+            // Only write to the unused part, the used part may be borrowed.
+            let dst = (self.bytes.get() as *mut u8).add(*used);
+            core::ptr::copy_nonoverlapping(s.as_ptr(), dst, s.len());
+            let interned = core::str::from_utf8_unchecked(core::slice::from_raw_parts(dst, s.len()));
+            *used += s.len();
+            strings[len] = interned;
+            self.len.store(len + 1, Ordering::Relaxed);
+            Some(interned)
+        }
+    }
+
+    impl<const N: usize, const S: usize> Default for Interner<N, S> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+}
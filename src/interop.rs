@@ -0,0 +1,110 @@
+//! Wire compatibility with nanopb and protobuf-c peers, e.g. ESP-IDF devices.
+//!
+//! The wire format is the same, but the defaults of `noproto` differ from code generated by
+//! nanopb and protobuf-c in a few places. To match a `.proto` file used by such peers:
+//!
+//! - `int32` fields need `#[noproto(int32)]`: a plain `i32` is a `sint32`. Negative `int32` and
+//!   enum values are sign-extended to 10 bytes as these encoders do, a 5-byte encoding would be
+//!   rejected by nanopb.
+//! - `fixed32`/`sfixed32`/`fixed64`/`sfixed64` fields need `#[noproto(fixed)]` or
+//!   `#[noproto(sfixed)]`.
+//! - Repeated scalar fields are written unpacked unless marked `#[noproto(packed)]`. This
+//!   matches proto2, where fields are packed only with `[packed = true]`. For proto3 files, where
+//!   they're packed by default, mark the message `#[noproto(packed)]` instead. Both forms are
+//!   accepted when reading, whatever the attributes.
+//! - Keep [`ReaderConfig::strict_varint32`](crate::ReaderConfig::strict_varint32) off: it
+//!   rejects the sign-extended negative `int32` values.
+//!
+//! The fixtures below are encoded in the layout nanopb and protobuf-c produce for this schema:
+//!
+//! ```proto
+//! enum Mode { IDLE = 0; RUN = 1; SLEEP = 2; }
+//!
+//! message Telemetry {
+//!   optional int32 offset = 1;
+//!   optional sint32 delta = 2;
+//!   repeated uint32 samples = 3 [packed = true];
+//!   repeated int32 codes = 4;
+//!   optional fixed32 id = 5;
+//!   optional string name = 6;
+//!   optional Mode mode = 7;
+//! }
+//! ```
+//!
+//! ```
+//! use noproto::types::Int32;
+//!
+//! #[derive(noproto::Enumeration, Clone, Copy, PartialEq, Debug)]
+//! #[repr(i32)]
+//! enum Mode {
+//!     Idle = 0,
+//!     Run = 1,
+//!     Sleep = 2,
+//! }
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Telemetry {
+//!     #[noproto(tag = 1, int32)]
+//!     offset: i32,
+//!     #[noproto(tag = 2)]
+//!     delta: i32,
+//!     #[noproto(tag = 3, packed)]
+//!     samples: heapless::Vec<u32, 4>,
+//!     #[noproto(tag = 4)]
+//!     codes: heapless::Vec<Int32, 4>,
+//!     #[noproto(tag = 5, fixed)]
+//!     id: u32,
+//!     #[noproto(tag = 6)]
+//!     name: heapless::String<8>,
+//!     #[noproto(tag = 7, enumeration = "Mode")]
+//!     mode: i32,
+//! }
+//!
+//! let telemetry = Telemetry {
+//!     offset: -2,
+//!     delta: -3,
+//!     samples: heapless::Vec::from_slice(&[1, 300]).unwrap(),
+//!     codes: heapless::Vec::from_slice(&[Int32(-1), Int32(5)]).unwrap(),
+//!     id: 0xdead_beef,
+//!     name: "esp".try_into().unwrap(),
+//!     mode: Mode::Sleep as i32,
+//! };
+//!
+//! // proto2, all fields set.
+//! let proto2 = "08 feffffffffffffffff01 10 05 1a 03 01ac02 20 ffffffffffffffffff01 20 05
+//!               2d efbeadde 32 03 657370 38 02";
+//! noproto::assert_encodes_to!(telemetry, proto2);
+//!
+//! // proto3: `codes` is packed, and zero values (`delta`, `mode`) are left out.
+//! let proto3 = [
+//!     0x08, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, // offset
+//!     0x1a, 0x03, 0x01, 0xac, 0x02, // samples
+//!     0x22, 0x0b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x05, // codes
+//!     0x2d, 0xef, 0xbe, 0xad, 0xde, // id
+//!     0x32, 0x03, b'e', b's', b'p', // name
+//! ];
+//! let decoded: Telemetry = noproto::read(&proto3).unwrap();
+//! assert_eq!(
+//!     decoded,
+//!     Telemetry {
+//!         delta: 0,
+//!         mode: Mode::Idle as i32,
+//!         ..telemetry
+//!     }
+//! );
+//! ```
+//!
+//! Negative enum values, sign-extended like `int32`:
+//!
+//! ```
+//! #[derive(noproto::Enumeration, Clone, Copy, PartialEq, Debug, Default)]
+//! #[repr(i32)]
+//! enum Error {
+//!     Timeout = -1,
+//!     #[default]
+//!     None = 0,
+//! }
+//!
+//! noproto::assert_encodes_to!(Error::Timeout, "ffffffffffffffffff01");
+//! assert_eq!(noproto::read::<Error>(&[0xff, 0xff, 0xff, 0xff, 0x0f]), Ok(Error::Timeout));
+//! ```
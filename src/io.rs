@@ -0,0 +1,172 @@
+//! Integration with [`embedded-io`](embedded_io) transports.
+//!
+//! [`Error`], [`ReadError`] and [`WriteError`] implement [`embedded_io::Error`], so transport
+//! code can propagate a single error type up the stack.
+
+use core::marker::PhantomData;
+
+use embedded_io::{ErrorKind, Read, Write};
+
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WriteError};
+
+/// Error returned when reading or writing messages over a transport.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error<E> {
+    /// The transport returned an error.
+    Io(E),
+    /// A message could not be decoded.
+    Read(ReadError),
+    /// A message could not be encoded.
+    Write(WriteError),
+    /// A message is larger than the stream's buffer.
+    TooLarge,
+    /// The transport reached end of file in the middle of a message.
+    UnexpectedEof,
+}
+
+impl<E> From<ReadError> for Error<E> {
+    fn from(e: ReadError) -> Self {
+        Self::Read(e)
+    }
+}
+
+impl<E> From<WriteError> for Error<E> {
+    fn from(e: WriteError) -> Self {
+        Self::Write(e)
+    }
+}
+
+impl<E: embedded_io::Error> embedded_io::Error for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(e) => e.kind(),
+            Self::Read(e) => e.kind(),
+            Self::Write(e) => e.kind(),
+            Self::TooLarge => ErrorKind::OutOfMemory,
+            Self::UnexpectedEof => ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_io::Error for ReadError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::CapacityExceeded => ErrorKind::OutOfMemory,
+            _ => ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl embedded_io::Error for WriteError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::OutOfMemory
+    }
+}
+
+/// Encode a message with its varint length prefix into `buf` and write it to `w`.
+///
+/// `buf` must fit the message plus its length prefix. Messages written this way can be read
+/// back with a [`MessageStream`].
+pub fn write_delimited<W: Write, M: Message>(w: &mut W, msg: &M, buf: &mut [u8]) -> Result<(), Error<W::Error>> {
+    let mut bw = ByteWriter::new(buf);
+    bw.write_length_delimited(|bw| msg.write_raw(bw))?;
+    w.write_all(bw.bytes()).map_err(Error::Io)
+}
+
+/// Reads a stream of varint length-delimited messages from a transport.
+///
+/// Data is read through an internal buffer of `N` bytes, which must fit the largest message
+/// plus its length prefix.
+pub struct MessageStream<R, M, const N: usize> {
+    r: R,
+    buf: [u8; N],
+    start: usize,
+    end: usize,
+    _phantom: PhantomData<M>,
+}
+
+impl<R: Read, M: Message + Default, const N: usize> MessageStream<R, M, N> {
+    /// Create a new [`MessageStream`] reading from `r`.
+    pub fn new(r: R) -> Self {
+        Self {
+            r,
+            buf: [0; N],
+            start: 0,
+            end: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Get back the underlying transport.
+    ///
+    /// Any data already read into the internal buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    /// Read the next message, returning `None` on end of file at a message boundary.
+    ///
+    /// If a message can't be framed (too large, truncated or with an invalid length prefix), the
+    /// buffered data is discarded, so the stream can only recover if the transport does too.
+    pub fn next_message(&mut self) -> Option<Result<M, Error<R::Error>>> {
+        loop {
+            let data = &self.buf[self.start..self.end];
+            let mut r = ByteReader::new(data);
+            match r.read_length() {
+                Ok(len) => {
+                    let header_len = data.len() - r.inner().len();
+                    let total = header_len.saturating_add(len);
+                    if total > N {
+                        self.discard();
+                        return Some(Err(Error::TooLarge));
+                    }
+                    if let Ok(payload) = r.read_slice(len) {
+                        self.start += total;
+                        let mut msg = M::default();
+                        return Some(msg.read_raw(&mut ByteReader::new(payload)).map(|_| msg).map_err(Error::Read));
+                    }
+                }
+                // Incomplete length prefix.
+                Err(ReadError::UnexpectedEof) => {}
+                // Length over `usize::MAX`.
+                Err(ReadError::InvalidLength) => {
+                    self.discard();
+                    return Some(Err(Error::TooLarge));
+                }
+                Err(e) => {
+                    self.discard();
+                    return Some(Err(Error::Read(e)));
+                }
+            }
+
+            // Incomplete message, move it to the front and read more.
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+            match self.r.read(&mut self.buf[self.end..]) {
+                Ok(0) if self.end == 0 => return None,
+                Ok(0) => {
+                    self.discard();
+                    return Some(Err(Error::UnexpectedEof));
+                }
+                Ok(n) => self.end += n,
+                Err(e) => return Some(Err(Error::Io(e))),
+            }
+        }
+    }
+
+    fn discard(&mut self) {
+        self.start = 0;
+        self.end = 0;
+    }
+}
+
+impl<R: Read, M: Message + Default, const N: usize> Iterator for MessageStream<R, M, N> {
+    type Item = Result<M, Error<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message()
+    }
+}
@@ -0,0 +1,214 @@
+//! Decoding from byte iterators.
+//!
+//! [`IterReader`] pulls bytes one at a time from any `Iterator<Item = u8>`, such as a PIO or
+//! bit-banged receiver, and decodes messages without first accumulating them into a slice.
+//! Only one top-level field at a time is buffered, in an internal lookahead of `N` bytes, and
+//! merged into the message as soon as it's complete.
+//!
+//! ```
+//! use noproto::iter::IterReader;
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//!     #[noproto(tag = 2)]
+//!     name: heapless::String<8>,
+//! }
+//!
+//! let bytes = [0x08, 0x01, 0x12, 0x02, b'h', b'i'];
+//! let mut r = IterReader::<_, 16>::new(bytes.into_iter());
+//! let ping: Ping = r.read().unwrap();
+//! assert_eq!(ping.seq, 1);
+//! assert_eq!(ping.name, "hi");
+//! ```
+
+use crate::read::{ByteReader, Required};
+use crate::write::MAX_VARUINT64_LEN;
+use crate::{Message, ReadError};
+
+/// Pull-based message reader over a byte iterator.
+///
+/// The internal buffer of `N` bytes must fit the largest top-level field, including its header.
+pub struct IterReader<I, const N: usize> {
+    iter: I,
+    buf: [u8; N],
+}
+
+impl<I: Iterator<Item = u8>, const N: usize> IterReader<I, N> {
+    /// Create a new [`IterReader`] pulling bytes from `iter`.
+    pub fn new(iter: I) -> Self {
+        Self { iter, buf: [0; N] }
+    }
+
+    /// Get back the underlying iterator.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+
+    /// Decode a message from all the remaining bytes.
+    pub fn read<M: Message + Default>(&mut self) -> Result<M, ReadError> {
+        let mut msg = M::default();
+        self.merge(&mut msg)?;
+        Ok(msg)
+    }
+
+    /// Decode all the remaining bytes into `msg`, merging with its current contents.
+    pub fn merge<M: Message>(&mut self, msg: &mut M) -> Result<(), ReadError> {
+        let src = Source {
+            iter: &mut self.iter,
+            left: None,
+        };
+        merge_fields(src, &mut self.buf, msg)
+    }
+
+    /// Decode a message prefixed by its varint length, returning `None` if the iterator ends
+    /// before the prefix.
+    ///
+    /// Bytes following the message are left in the iterator, so this can be called repeatedly
+    /// to read a stream of delimited messages.
+    pub fn read_delimited<M: Message + Default>(&mut self) -> Result<Option<M>, ReadError> {
+        let mut src = Source {
+            iter: &mut self.iter,
+            left: None,
+        };
+        let Some(first) = src.first_byte()? else {
+            return Ok(None);
+        };
+        let mut prefix = [0; MAX_VARUINT64_LEN];
+        let n = src.varint(Some(first), &mut prefix)?;
+        let len = ByteReader::new(&prefix[..n]).read_length()?;
+
+        let mut msg = M::default();
+        let src = Source {
+            iter: &mut self.iter,
+            left: Some(len),
+        };
+        merge_fields(src, &mut self.buf, &mut msg)?;
+        Ok(Some(msg))
+    }
+}
+
+/// Bytes pulled from an iterator, with an optional limit.
+struct Source<'a, I> {
+    iter: &'a mut I,
+    left: Option<usize>,
+}
+
+impl<I: Iterator<Item = u8>> Source<'_, I> {
+    /// Pull the first byte of a field, or `None` at the end of the data.
+    fn first_byte(&mut self) -> Result<Option<u8>, ReadError> {
+        match self.left {
+            Some(0) => Ok(None),
+            Some(_) => self.byte().map(Some),
+            None => Ok(self.iter.next()),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, ReadError> {
+        if let Some(left) = &mut self.left {
+            *left = left.checked_sub(1).ok_or(ReadError::UnexpectedEof)?;
+        }
+        self.iter.next().ok_or(ReadError::UnexpectedEof)
+    }
+
+    /// Pull a varint, starting with `first` if already pulled, into `out`. Returns its length.
+    fn varint(&mut self, first: Option<u8>, out: &mut [u8]) -> Result<usize, ReadError> {
+        let mut b = match first {
+            Some(b) => b,
+            None => self.byte()?,
+        };
+        for i in 0..MAX_VARUINT64_LEN {
+            *out.get_mut(i).ok_or(ReadError::CapacityExceeded)? = b;
+            if b & 0x80 == 0 {
+                return Ok(i + 1);
+            }
+            b = self.byte()?;
+        }
+        Err(ReadError::InvalidVarint)
+    }
+
+    /// Pull exactly `out.len()` bytes into `out`.
+    fn fill(&mut self, out: &mut [u8]) -> Result<(), ReadError> {
+        for b in out {
+            *b = self.byte()?;
+        }
+        Ok(())
+    }
+
+    /// Pull a group into `out`, after its start-group header, up to and including the matching
+    /// end-group header. Returns its length.
+    fn group(&mut self, tag: u32, out: &mut [u8]) -> Result<usize, ReadError> {
+        let mut n = 0;
+        let mut depth = 0usize;
+        loop {
+            let h = self.varint(None, &mut out[n..])?;
+            let header = ByteReader::new(&out[n..n + h]).read_varuint32()?;
+            n += h;
+            let len = match header & 0b111 {
+                0 => self.varint(None, &mut out[n..])?,
+                1 => self.fill_at(out, n, 8)?,
+                2 => {
+                    let l = self.varint(None, &mut out[n..])?;
+                    let len = ByteReader::new(&out[n..n + l]).read_varuint32()?;
+                    l + self.fill_at(out, n + l, len as usize)?
+                }
+                5 => self.fill_at(out, n, 4)?,
+                3 => {
+                    depth += 1;
+                    0
+                }
+                4 if depth > 0 => {
+                    depth -= 1;
+                    0
+                }
+                4 if header >> 3 == tag => return Ok(n),
+                _ => return Err(ReadError::InvalidGroup),
+            };
+            n += len;
+        }
+    }
+
+    /// Pull `len` bytes into `out` at `pos`. Returns `len`.
+    fn fill_at(&mut self, out: &mut [u8], pos: usize, len: usize) -> Result<usize, ReadError> {
+        let end = pos.checked_add(len).ok_or(ReadError::InvalidLength)?;
+        self.fill(out.get_mut(pos..end).ok_or(ReadError::CapacityExceeded)?)?;
+        Ok(len)
+    }
+}
+
+/// Pull fields from `src` one at a time into `buf`, and merge each of them into `msg`.
+fn merge_fields<I: Iterator<Item = u8>, M: Message>(
+    mut src: Source<'_, I>,
+    buf: &mut [u8],
+    msg: &mut M,
+) -> Result<(), ReadError> {
+    let mut required = Required::new::<M>();
+    while let Some(first) = src.first_byte()? {
+        let h = src.varint(Some(first), buf)?;
+        let header = ByteReader::new(&buf[..h]).read_varuint32()?;
+        required.see(header >> 3);
+        // Range of the field body still to be pulled.
+        let (start, end) = match header & 0b111 {
+            0 => {
+                let end = h + src.varint(None, &mut buf[h..])?;
+                (end, end)
+            }
+            1 => (h, h + 8),
+            2 => {
+                let n = src.varint(None, &mut buf[h..])?;
+                let len = ByteReader::new(&buf[h..h + n]).read_length()?;
+                (h + n, (h + n).checked_add(len).ok_or(ReadError::InvalidLength)?)
+            }
+            5 => (h, h + 4),
+            3 => {
+                let end = h + src.group(header >> 3, &mut buf[h..])?;
+                (end, end)
+            }
+            _ => return Err(ReadError::InvalidWireType),
+        };
+        src.fill(buf.get_mut(start..end).ok_or(ReadError::CapacityExceeded)?)?;
+        msg.read_raw(&mut ByteReader::partial(&buf[..end]))?;
+    }
+    required.check()
+}
@@ -2,18 +2,73 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod capacity;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod catalog;
+pub mod checksum;
+pub mod chunked;
+pub mod compress;
+mod dedup;
+pub mod described;
+pub mod diff;
+pub mod descriptor;
+pub mod envelope;
+mod fixed;
+pub mod grant;
 mod impls;
+mod input;
+pub mod interop;
+pub mod intern;
+#[cfg(feature = "embedded-io")]
+pub mod io;
+pub mod iter;
+pub mod negotiate;
+pub mod path;
+pub mod plan;
+pub mod pool;
+mod presence;
+pub mod push;
 mod read;
+mod reverse;
+pub mod rpc;
+pub mod scratch;
+#[cfg(feature = "size-sink")]
+pub mod sink;
+pub mod splice;
+#[cfg(feature = "std")]
+pub mod std_io;
+pub mod testing;
+pub mod transaction;
+pub mod transcode;
+pub mod types;
+#[cfg(feature = "uom")]
+pub mod uom;
+mod validate;
 mod write;
 
-pub use read::ReadError;
-use read::{ByteReader, FieldReader};
-use write::ByteWriter;
-pub use write::WriteError;
+use core::ops::ControlFlow;
+
+use descriptor::{FieldDescriptor, Type};
+use input::Input;
+pub use dedup::Dedup;
+pub use presence::Presence;
+pub use read::{ErrorContext, ReadError, ReadLimits, ReaderConfig, Skipped};
+pub use validate::ValidationError;
+use read::{Budget, ByteReader, FieldReader, Required, Trace};
+use reverse::ReverseWriter;
+use write::{ByteWriter, Output};
+pub use write::{SizedWriteError, WriteError};
 
 pub mod encoding {
     //! Encoding and decoding of primitive types.
+    pub use crate::fixed::FixedLe;
+    pub use crate::input::Input;
     pub use crate::read::*;
+    pub use crate::reverse::ReverseWriter;
     pub use crate::write::*;
 }
 
@@ -28,26 +83,75 @@ pub use noproto_derive::*;
 
 /// Wire type of a field.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum WireType {
     /// Varint.
     Varint = 0,
-    //SixtyFourBit = 1,
+    /// 64-bit fixed width, little endian.
+    SixtyFourBit = 1,
     /// Length-delimited.
     LengthDelimited = 2,
-    //StartGroup = 3,
+    /// Group, delimited by a start-group and an end-group header. Fields only have this wire type
+    /// when written with [`ByteWriter::write_group`].
+    StartGroup = 3,
     //EndGroup = 4,
-    //ThirtyTwoBit = 5,
+    /// 32-bit fixed width, little endian.
+    ThirtyTwoBit = 5,
 }
 
 /// A protobuf message.
 pub trait Message {
     /// The wire type of the message.
     const WIRE_TYPE: WireType;
+    /// Upper bound on the number of bytes written by [`write_raw`](Message::write_raw),
+    /// or `usize::MAX` if unbounded.
+    const MAX_ENCODED_LEN: usize = usize::MAX;
+    /// Protobuf type of the message, for reflection.
+    const TYPE: Type = Type::Unknown;
+    /// Whether [`write_raw`](Message::write_raw) can fail with a large enough buffer, e.g. in a
+    /// `before_write` hook. [`encode_exact`] doesn't accept such messages.
+    const FALLIBLE_WRITE: bool = false;
     /// Serialize the message.
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError>;
     /// Deserialize the message.
     fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError>;
+
+    /// Check the invariants of the message in memory: UTF-8 strings, container lengths within
+    /// capacity and known enumeration values.
+    ///
+    /// Useful to sanity-check messages restored from retained RAM or DMA buffers before use.
+    /// The default accepts any value.
+    fn validate(&self) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Serialize the message back to front, for [`write_reverse`].
+    ///
+    /// The default sizes the message with a counting pass, then writes it forward with
+    /// [`write_raw`](Message::write_raw).
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        w.write_forward(|w| self.write_raw(w))
+    }
+
+    /// Number of bytes written by [`write_raw`](Message::write_raw), without storing them.
+    fn encoded_len(&self) -> Result<usize, WriteError> {
+        let mut w = ByteWriter::counting();
+        self.write_raw(&mut w)?;
+        Ok(w.pos())
+    }
+
+    /// Serialize only the `index`-th top-level field, in encoding order.
+    ///
+    /// Returns `false` if the message has no field at `index`. The default treats the whole
+    /// message as a single field.
+    fn write_nth_field(&self, index: usize, w: &mut ByteWriter) -> Result<bool, WriteError> {
+        if index != 0 {
+            return Ok(false);
+        }
+        self.write_raw(w)?;
+        Ok(true)
+    }
 }
 
 /// An optional protobuf message.
@@ -59,6 +163,11 @@ pub trait OptionalMessage {
     fn get(&self) -> Option<&Self::Message>;
     /// Set the message.
     fn set(&mut self, m: Self::Message) -> Result<(), ReadError>;
+
+    /// Check the invariants of the message, if any. See [`Message::validate`].
+    fn validate(&self) -> Result<(), ValidationError> {
+        self.get().map_or(Ok(()), Message::validate)
+    }
 }
 
 /// A repeated protobuf message.
@@ -69,34 +178,748 @@ pub trait RepeatedMessage {
     type Iter<'a>: Iterator<Item = &'a Self::Message>
     where
         Self: 'a;
+    /// Maximum number of messages, or `usize::MAX` if unbounded.
+    const MAX_LEN: usize = usize::MAX;
 
     /// Get an iterator over the messages.
     fn iter(&self) -> Result<Self::Iter<'_>, WriteError>;
     /// Append a message.
     fn append(&mut self, m: Self::Message) -> Result<(), ReadError>;
+
+    /// Check the invariants of each message. See [`Message::validate`].
+    fn validate(&self) -> Result<(), ValidationError> {
+        for m in self.iter().map_err(|_| ValidationError::Capacity)? {
+            m.validate()?;
+        }
+        Ok(())
+    }
 }
 
 /// A oneof protobuf message.
 pub trait Oneof: Sized {
+    /// Upper bound on the number of bytes written by [`write_raw`](Oneof::write_raw), including
+    /// the field header, or `usize::MAX` if unbounded.
+    const MAX_ENCODED_LEN: usize = usize::MAX;
+    /// Descriptions of the variants, for reflection.
+    const FIELDS: &'static [FieldDescriptor] = &[];
+    /// Whether [`write_raw`](Oneof::write_raw) can fail with a large enough buffer. See
+    /// [`Message::FALLIBLE_WRITE`].
+    const FALLIBLE_WRITE: bool = false;
     /// Serialize the message.
     fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError>;
     /// Deserialize the message.
     fn read_raw(&mut self, r: FieldReader) -> Result<(), ReadError>;
     /// Deserialize a oneof variant.
     fn read_raw_option(this: &mut Option<Self>, r: FieldReader) -> Result<(), ReadError>;
+
+    /// Serialize the message back to front. See [`Message::write_reverse`].
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        w.write_forward(|w| self.write_raw(w))
+    }
+
+    /// Check the invariants of the current variant. See [`Message::validate`].
+    fn validate(&self) -> Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+/// Fields of a message that can be embedded in another one, with `#[noproto(extend)]`.
+///
+/// Implemented by the `Message` derive. The fields of the part are encoded inline in the
+/// message embedding it, as if they were declared there, so a large message can be split into
+/// structs grouping fields with distinct tags.
+///
+/// ```
+/// #[derive(noproto::Message, Default, PartialEq, Debug)]
+/// struct Radio {
+///     #[noproto(tag = 10)]
+///     channel: u32,
+///     #[noproto(tag = 11)]
+///     power: i32,
+/// }
+///
+/// #[derive(noproto::Message, Default, PartialEq, Debug)]
+/// struct Config {
+///     #[noproto(tag = 1)]
+///     name: heapless::String<16>,
+///     #[noproto(extend)]
+///     radio: Radio,
+/// }
+///
+/// #[derive(noproto::Message, Default, PartialEq, Debug)]
+/// struct FlatConfig {
+///     #[noproto(tag = 1)]
+///     name: heapless::String<16>,
+///     #[noproto(tag = 10)]
+///     channel: u32,
+///     #[noproto(tag = 11)]
+///     power: i32,
+/// }
+///
+/// let config = Config {
+///     name: "node".try_into().unwrap(),
+///     radio: Radio { channel: 6, power: -3 },
+/// };
+/// let mut buf = [0; 32];
+/// let n = noproto::write(&config, &mut buf).unwrap();
+/// let flat: FlatConfig = noproto::read(&buf[..n]).unwrap();
+/// assert_eq!((flat.channel, flat.power), (6, -3));
+/// assert_eq!(noproto::read::<Config>(&buf[..n]).unwrap(), config);
+///
+/// // The descriptor lists the fields of the parts after the message's own.
+/// let desc = noproto::descriptor::descriptor::<Config>().unwrap();
+/// assert_eq!(desc.fields.len(), 3);
+/// assert_eq!(desc.field(11).unwrap().name, "power");
+/// ```
+///
+/// A part using a tag of the message or of another part fails the build:
+///
+/// ```compile_fail
+/// #[derive(noproto::Message, Default)]
+/// struct Radio {
+///     #[noproto(tag = 1)]
+///     channel: u32,
+/// }
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Config {
+///     #[noproto(tag = 1)]
+///     id: u32,
+///     #[noproto(extend)]
+///     radio: Radio,
+/// }
+/// ```
+///
+/// Messages with type parameters can't have parts, and part types need a descriptor. The
+/// `required` fields and `after_read` hooks of a part are not applied when it's read as part of
+/// another message.
+pub trait Part: Message {
+    /// Check if field `tag` belongs to the part.
+    fn has_tag(tag: u32) -> bool;
+    /// Deserialize a field of the part, one for which [`has_tag`](Part::has_tag) is true.
+    fn read_field(&mut self, r: FieldReader) -> Result<(), ReadError>;
 }
 
 /// Serialize a protobuf message to a buffer.
 pub fn write<M: Message>(msg: &M, buf: &mut [u8]) -> Result<usize, WriteError> {
     let mut w = ByteWriter::new(buf);
     msg.write_raw(&mut w)?;
-    Ok(w.pos())
+    Ok(encoded(msg, w.pos()))
+}
+
+/// Serialize a protobuf message to a buffer, reporting the length needed if it doesn't fit.
+///
+/// If the buffer is too small, the message is sized without storing it, so the caller can
+/// retry with a large enough buffer or report a meaningful diagnostic.
+///
+/// ```
+/// #[derive(noproto::Message)]
+/// struct Name {
+///     #[noproto(tag = 1)]
+///     name: heapless::String<32>,
+/// }
+///
+/// let msg = Name { name: "thermostat".try_into().unwrap() };
+/// let mut buf = [0; 8];
+/// let err = noproto::write_sized(&msg, &mut buf).unwrap_err();
+/// assert_eq!(err.required, Some(12));
+/// ```
+pub fn write_sized<M: Message>(msg: &M, buf: &mut [u8]) -> Result<usize, SizedWriteError> {
+    write(msg, buf).map_err(|_| SizedWriteError {
+        required: msg.encoded_len().ok(),
+    })
+}
+
+/// Serialize a protobuf message, appending it to a growable output such as a `heapless::Vec`.
+///
+/// Returns the number of bytes appended. On error, `out` may hold part of the message.
+///
+/// ```
+/// #[derive(noproto::Message)]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// let mut out = heapless::Vec::<u8, 16>::new();
+/// assert_eq!(noproto::write_to(&Ping { seq: 300 }, &mut out), Ok(3));
+/// assert_eq!(out, [0x08, 0xac, 0x02]);
+/// ```
+pub fn write_to<M: Message>(msg: &M, out: &mut impl Output) -> Result<usize, WriteError> {
+    let start = out.position();
+    let mut w = ByteWriter::with_output(out);
+    msg.write_raw(&mut w)?;
+    Ok(encoded(msg, w.pos() - start))
+}
+
+/// Serialize a protobuf message whose [`MAX_ENCODED_LEN`](Message::MAX_ENCODED_LEN) fits in `N`
+/// bytes, without error handling. Returns the buffer and the number of bytes written.
+///
+/// That the message fits is checked at compile time. Messages whose encoding can fail for other
+/// reasons ([`Message::FALLIBLE_WRITE`]), e.g. with a `before_write` hook, are rejected.
+///
+/// # Panics
+///
+/// Panics if the message writes more than its `MAX_ENCODED_LEN`, or fails without setting
+/// `FALLIBLE_WRITE`, which are bugs in its [`Message`] impl.
+///
+/// ```
+/// #[derive(noproto::Message)]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// let (buf, n) = noproto::encode_exact::<_, 8>(&Ping { seq: 300 });
+/// assert_eq!(&buf[..n], [0x08, 0xac, 0x02]);
+/// ```
+///
+/// A buffer that may be too small doesn't compile:
+///
+/// ```compile_fail
+/// # #[derive(noproto::Message)]
+/// # struct Ping {
+/// #     #[noproto(tag = 1)]
+/// #     seq: u32,
+/// # }
+/// let (buf, n) = noproto::encode_exact::<_, 4>(&Ping { seq: 300 });
+/// ```
+///
+/// Neither does a message with a `before_write` hook:
+///
+/// ```compile_fail
+/// #[derive(noproto::Message)]
+/// #[noproto(before_write = "check")]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// fn check(ping: &Ping) -> Result<(), noproto::WriteError> {
+///     if ping.seq == 0 {
+///         return Err(noproto::WriteError);
+///     }
+///     Ok(())
+/// }
+///
+/// let (buf, n) = noproto::encode_exact::<_, 8>(&Ping { seq: 0 });
+/// ```
+///
+/// Even when the hook is on a message held by a oneof:
+///
+/// ```compile_fail
+/// #[derive(noproto::Message, Default)]
+/// #[noproto(before_write = "check")]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// fn check(ping: &Ping) -> Result<(), noproto::WriteError> {
+///     if ping.seq == 0 {
+///         return Err(noproto::WriteError);
+///     }
+///     Ok(())
+/// }
+///
+/// #[derive(noproto::Oneof)]
+/// enum Choice {
+///     #[noproto(tag = 2)]
+///     Ping(Ping),
+/// }
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Request {
+///     #[noproto(oneof, tags = "2")]
+///     choice: Option<Choice>,
+/// }
+///
+/// let (buf, n) = noproto::encode_exact::<_, 16>(&Request::default());
+/// ```
+pub fn encode_exact<M: Message, const N: usize>(msg: &M) -> ([u8; N], usize) {
+    const { assert!(M::MAX_ENCODED_LEN <= N, "buffer smaller than the message MAX_ENCODED_LEN") };
+    const { assert!(!M::FALLIBLE_WRITE, "message encoding can fail, e.g. in a before_write hook") };
+    let mut buf = [0; N];
+    let mut w = ByteWriter::new(&mut buf);
+    // No `expect`, it would format the error with `core::fmt`.
+    if msg.write_raw(&mut w).is_err() {
+        panic!("message failed to encode within its MAX_ENCODED_LEN");
+    }
+    let len = encoded(msg, w.pos());
+    (buf, len)
+}
+
+/// Size of the window on the encoding of `b` in [`__wire_eq`], and of the pieces hashed by
+/// [`__wire_hash`].
+const WIRE_EQ_CHUNK: usize = 64;
+
+/// Stream the encoding of `msg` to `f`, returning the number of bytes streamed and whether the
+/// encoding succeeded.
+fn wire_stream<M: Message>(msg: &M, f: &mut dyn FnMut(&[u8]) -> Result<(), WriteError>) -> (usize, bool) {
+    let mut len = 0;
+    let mut count = |bytes: &[u8]| {
+        f(bytes)?;
+        len += bytes.len();
+        Ok(())
+    };
+    let ok = msg.write_raw(&mut ByteWriter::streaming(&mut count)).is_ok();
+    (len, ok)
+}
+
+/// Store the streamed encoding of `msg` at `start..start + buf.len()` in `buf`.
+fn wire_window<M: Message>(msg: &M, buf: &mut [u8], start: usize) -> (usize, bool) {
+    let mut pos: usize = 0;
+    wire_stream(msg, &mut |bytes| {
+        for &byte in bytes {
+            if let Some(b) = pos.checked_sub(start).and_then(|i| buf.get_mut(i)) {
+                *b = byte;
+            }
+            pos += 1;
+        }
+        Ok(())
+    })
+}
+
+/// Compare the encodings of `a` and `b`, for the `PartialEq` impl generated by
+/// `#[noproto(wire_eq)]`.
+///
+/// `a` is encoded once, streamed into a comparison with a window on the encoding of `b`, which
+/// is encoded again each time the window moves, so no buffer for a whole message is needed.
+/// Messages failing to encode are equal if they produce the same bytes before failing.
+#[doc(hidden)]
+pub fn __wire_eq<M: Message>(a: &M, b: &M) -> bool {
+    let mut window = [0; WIRE_EQ_CHUNK];
+    let mut start = 0;
+    let (b_len, b_ok) = wire_window(b, &mut window, start);
+    let mut pos = 0;
+    let mut equal = true;
+    let (a_len, a_ok) = wire_stream(a, &mut |bytes| {
+        for &byte in bytes {
+            if pos == start + WIRE_EQ_CHUNK {
+                start = pos;
+                wire_window(b, &mut window, start);
+            }
+            if pos >= b_len || window[pos - start] != byte {
+                // Stop encoding `a`, the rest can't make up for it.
+                equal = false;
+                return Err(WriteError);
+            }
+            pos += 1;
+        }
+        Ok(())
+    });
+    equal && a_len == b_len && a_ok == b_ok
+}
+
+/// Hash the encoding of `msg`, for the `Hash` impl generated by `#[noproto(wire_eq)]`.
+///
+/// The message is encoded once and hashed in pieces of a fixed size, so the hash doesn't depend
+/// on how the encoding was split into writes. Like [`__wire_eq`], it covers the bytes produced
+/// before a failure and the failure itself.
+#[doc(hidden)]
+pub fn __wire_hash<M: Message, H: core::hash::Hasher>(msg: &M, state: &mut H) {
+    let mut piece = [0; WIRE_EQ_CHUNK];
+    let mut n = 0;
+    let (len, ok) = wire_stream(msg, &mut |bytes| {
+        for &byte in bytes {
+            piece[n] = byte;
+            n += 1;
+            if n == WIRE_EQ_CHUNK {
+                state.write(&piece);
+                n = 0;
+            }
+        }
+        Ok(())
+    });
+    state.write(&piece[..n]);
+    state.write_usize(len);
+    state.write_u8(ok as u8);
+}
+
+/// Serialize a protobuf message from the end of a buffer towards the front.
+///
+/// Nested length prefixes are written after the data they describe, so derived messages are
+/// encoded in a single pass, without moving bytes or sizing them first. Returns the end of
+/// `buf` holding the encoded message.
+///
+/// ```
+/// #[derive(noproto::Message, Default)]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// let mut buf = [0; 16];
+/// let data = noproto::write_reverse(&Ping { seq: 150 }, &mut buf).unwrap();
+/// assert_eq!(data, [0x08, 0x96, 0x01]);
+/// ```
+pub fn write_reverse<'a, M: Message>(msg: &M, buf: &'a mut [u8]) -> Result<&'a [u8], WriteError> {
+    let mut w = ReverseWriter::new(buf);
+    msg.write_reverse(&mut w)?;
+    encoded(msg, w.len());
+    Ok(w.into_bytes())
+}
+
+/// Report the encoded length of a message to the size sink, if enabled.
+#[inline]
+fn encoded<M: ?Sized>(_msg: &M, len: usize) -> usize {
+    #[cfg(feature = "size-sink")]
+    sink::record::<M>(len);
+    len
+}
+
+/// Serialize a protobuf message across several buffers, filling them in order.
+///
+/// Useful when the output buffer wraps around, e.g. the two halves of a DMA ring.
+/// Returns the number of bytes written to each segment.
+pub fn write_scatter<'a, M: Message, const N: usize>(
+    msg: &M,
+    segments: &'a mut [&'a mut [u8]; N],
+) -> Result<[usize; N], WriteError> {
+    let mut lens = [0; N];
+    for (len, segment) in lens.iter_mut().zip(segments.iter()) {
+        *len = segment.len();
+    }
+    let mut w = ByteWriter::new_segmented(segments);
+    msg.write_raw(&mut w)?;
+    let mut remaining = encoded(msg, w.pos());
+    for len in &mut lens {
+        *len = remaining.min(*len);
+        remaining -= *len;
+    }
+    Ok(lens)
+}
+
+/// Serialize a protobuf message one top-level field at a time.
+///
+/// Each field is encoded into `buf` and handed to `f`, so `buf` only needs to fit the largest
+/// single field instead of the whole message. Returns the total number of bytes produced.
+pub fn write_fields<M: Message>(
+    msg: &M,
+    buf: &mut [u8],
+    mut f: impl FnMut(&[u8]) -> Result<(), WriteError>,
+) -> Result<usize, WriteError> {
+    let mut total = 0;
+    for index in 0.. {
+        let mut w = ByteWriter::new(buf);
+        if !msg.write_nth_field(index, &mut w)? {
+            break;
+        }
+        if w.pos() != 0 {
+            f(w.bytes())?;
+            total += w.pos();
+        }
+    }
+    Ok(encoded(msg, total))
 }
 
 /// Deserialize a protobuf message from a buffer.
 pub fn read<M: Message + Default>(buf: &[u8]) -> Result<M, ReadError> {
+    read_with_config(buf, ReaderConfig::default())
+}
+
+/// Deserialize a protobuf message from a buffer, stopping once every tag in `tags` was seen.
+///
+/// The rest of the buffer is skipped without being parsed, which minimizes latency when only a
+/// few fields (e.g. routing headers) are needed from a large message. Fields after the last
+/// wanted tag are left at their default, and repeated wanted fields only get the elements up to
+/// their first occurrence. At most 64 tags are supported.
+pub fn read_until_tags<M: Message + Default>(buf: &[u8], tags: &[u32]) -> Result<M, ReadError> {
+    let mut fields = ByteReader::new(buf).fields();
+    let mut missing = tags.len();
+    let mut seen = [false; 64];
+    let seen = seen.get_mut(..tags.len()).ok_or(ReadError::CapacityExceeded)?;
+    while missing != 0 {
+        let Some(field) = fields.next() else { break };
+        let field = field?;
+        for (tag, seen) in tags.iter().zip(seen.iter_mut()) {
+            if *tag == field.tag() && !*seen {
+                *seen = true;
+                missing -= 1;
+            }
+        }
+    }
+    let end = buf.len() - fields.remaining().len();
+    if end == buf.len() {
+        return read(buf);
+    }
+    // Required fields after the wanted ones may be cut off.
     let mut msg = M::default();
+    msg.read_raw(&mut ByteReader::partial(&buf[..end]))?;
+    Ok(msg)
+}
+
+/// Deserialize a protobuf message from a buffer, stopping as soon as `done` returns true.
+///
+/// `done` is called with the partially decoded message after each top-level field. Since the
+/// fields are decoded one by one, `after_read` hooks run after each of them. Required fields
+/// are only checked if `done` never returns true.
+pub fn read_until<M: Message + Default>(buf: &[u8], mut done: impl FnMut(&M) -> bool) -> Result<M, ReadError> {
+    let mut msg = M::default();
+    let mut required = Required::new::<M>();
+    let mut fields = ByteReader::new(buf).fields();
+    loop {
+        let start = buf.len() - fields.remaining().len();
+        let Some(field) = fields.next() else { break };
+        required.see(field?.tag());
+        let end = buf.len() - fields.remaining().len();
+        msg.read_raw(&mut ByteReader::partial(&buf[start..end]))?;
+        if done(&msg) {
+            return Ok(msg);
+        }
+    }
+    required.check()?;
+    Ok(msg)
+}
+
+/// Deserialize a buffer of varint length-delimited messages, calling `f` with each of them.
+///
+/// Stops early when `f` returns [`ControlFlow::Break`], returning its value, or `None` once all
+/// messages were handled. Messages are decoded one at a time, so records batched in one payload
+/// don't all have to fit in memory at once.
+///
+/// ```
+/// use core::ops::ControlFlow;
+/// use noproto::encoding::ByteWriter;
+/// use noproto::Message;
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Record {
+///     #[noproto(tag = 1)]
+///     value: u32,
+/// }
+///
+/// let mut buf = [0; 32];
+/// let mut w = ByteWriter::new(&mut buf);
+/// for value in [3, 0, 5] {
+///     w.write_length_delimited(|w| Record { value }.write_raw(w)).unwrap();
+/// }
+/// let n = w.pos();
+///
+/// // Sum the records, stopping at the first zero.
+/// let mut sum = 0;
+/// let res = noproto::read_stream(&buf[..n], |r: Record| {
+///     if r.value == 0 {
+///         return ControlFlow::Break("zero");
+///     }
+///     sum += r.value;
+///     ControlFlow::Continue(())
+/// });
+/// assert_eq!(res, Ok(Some("zero")));
+/// assert_eq!(sum, 3);
+/// ```
+pub fn read_stream<M: Message + Default, B>(
+    buf: &[u8],
+    mut f: impl FnMut(M) -> ControlFlow<B>,
+) -> Result<Option<B>, ReadError> {
     let mut r = ByteReader::new(buf);
+    while !r.eof() {
+        let mut msg = M::default();
+        msg.read_raw(&mut ByteReader::new(r.read_varslice()?))?;
+        if let ControlFlow::Break(b) = f(msg) {
+            return Ok(Some(b));
+        }
+    }
+    Ok(None)
+}
+
+/// Deserialize a protobuf message from a buffer, yielding to the executor every `every`
+/// top-level fields.
+///
+/// For large messages decoded in cooperative schedulers such as embassy, so a long decode
+/// doesn't starve other tasks or trip the watchdog. Fields are decoded one by one, so
+/// `after_read` hooks run after each of them. `every` must be non-zero.
+///
+/// ```
+/// # #[derive(noproto::Message, Default)]
+/// # struct Log { #[noproto(tag = 1, repeated)] lines: heapless::Vec<heapless::String<32>, 64> }
+/// async fn handle(buf: &[u8]) -> Result<(), noproto::ReadError> {
+///     let log: Log = noproto::read_yielding(buf, 8).await?;
+///     // ...
+///     # let _ = log;
+///     Ok(())
+/// }
+/// ```
+pub async fn read_yielding<M: Message + Default>(buf: &[u8], every: usize) -> Result<M, ReadError> {
+    let mut msg = M::default();
+    let mut fields = ByteReader::new(buf).fields();
+    let mut required = Required::new::<M>();
+    let mut count = 0;
+    loop {
+        let start = buf.len() - fields.remaining().len();
+        let Some(field) = fields.next() else { break };
+        required.see(field?.tag());
+        let end = buf.len() - fields.remaining().len();
+        msg.read_raw(&mut ByteReader::partial(&buf[start..end]))?;
+        count += 1;
+        if count == every {
+            count = 0;
+            yield_now().await;
+        }
+    }
+    required.check()?;
+    Ok(msg)
+}
+
+/// Let other tasks run, by returning `Pending` once after waking the task.
+async fn yield_now() {
+    let mut yielded = false;
+    core::future::poll_fn(|cx| {
+        if yielded {
+            return core::task::Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        core::task::Poll::Pending
+    })
+    .await
+}
+
+/// Deserialize a protobuf message from a buffer into an existing message.
+///
+/// Fields present in `buf` are merged into `msg`, the others keep their value. Useful for
+/// messages that need setup before decoding, e.g. [`scratch::ScratchBytes`] fields.
+pub fn merge<M: Message>(buf: &[u8], msg: &mut M) -> Result<(), ReadError> {
+    msg.read_raw(&mut ByteReader::new(buf))
+}
+
+/// Deserialize a protobuf message split across several buffers, e.g. the two halves of a DMA
+/// ring.
+///
+/// Fields within a segment are decoded in place, only a field straddling segments is copied to
+/// an internal buffer of `N` bytes, which must fit it.
+///
+/// ```
+/// #[derive(noproto::Message, Default)]
+/// struct Ping {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+///     #[noproto(tag = 2)]
+///     name: heapless::String<8>,
+/// }
+///
+/// let data = [0x08, 0x01, 0x12, 0x02, b'h', b'i'];
+/// let (a, b) = data.split_at(4);
+/// let ping: Ping = noproto::read_gather::<_, 8>(&[a, b]).unwrap();
+/// assert_eq!(ping.seq, 1);
+/// assert_eq!(ping.name, "hi");
+/// ```
+pub fn read_gather<M: Message + Default, const N: usize>(input: &(impl Input + ?Sized)) -> Result<M, ReadError> {
+    let mut msg = M::default();
+    input::merge_gather(input, &mut [0; N], &mut msg)?;
+    Ok(msg)
+}
+
+/// Deserialize a protobuf message from a buffer, reporting where decoding failed.
+///
+/// On error, the [`ErrorContext`] holds the path of tags to the malformed field and its byte
+/// offset in `buf`, turning a bare decoding failure into an actionable report.
+///
+/// ```
+/// #[derive(noproto::Message, Default, Debug)]
+/// struct Inner {
+///     #[noproto(tag = 1)]
+///     name: heapless::String<8>,
+/// }
+///
+/// #[derive(noproto::Message, Default, Debug)]
+/// struct Outer {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+///     #[noproto(tag = 3)]
+///     inner: Inner,
+/// }
+///
+/// let buf = [0x08, 0x01, 0x1a, 0x03, 0x0a, 0x01, 0xff];
+/// let err = noproto::read_with_context::<Outer>(&buf).unwrap_err();
+/// assert_eq!(err.error, noproto::ReadError::InvalidUtf8);
+/// assert_eq!(err.path(), [3, 1]);
+/// assert_eq!(err.offset, Some(6));
+/// ```
+pub fn read_with_context<M: Message + Default>(buf: &[u8]) -> Result<M, ErrorContext> {
+    let trace = Trace::new(buf);
+    let mut msg = M::default();
+    let mut r = ByteReader::with_trace(buf, ReaderConfig::default(), &trace);
+    match msg.read_raw(&mut r) {
+        Ok(()) => Ok(msg),
+        Err(e) => Err(trace.context(e)),
+    }
+}
+
+/// Deserialize a protobuf message from untrusted input, failing with
+/// [`ReadError::LimitExceeded`] if it exceeds `limits`.
+///
+/// Bounds the worst-case work and memory of decoding, e.g. the recursion depth of deeply
+/// nested messages.
+///
+/// ```
+/// use noproto::{ReadError, ReadLimits};
+///
+/// #[derive(noproto::Message, Default, Debug)]
+/// struct Note {
+///     #[noproto(tag = 1)]
+///     text: heapless::String<64>,
+/// }
+///
+/// let mut buf = [0; 64];
+/// let note = Note { text: "hello world".try_into().unwrap() };
+/// let n = noproto::write(&note, &mut buf).unwrap();
+///
+/// let limits = ReadLimits { max_bytes_len: 8, ..Default::default() };
+/// let res = noproto::read_with_limits::<Note>(&buf[..n], limits);
+/// assert_eq!(res.unwrap_err(), ReadError::LimitExceeded);
+/// ```
+pub fn read_with_limits<M: Message + Default>(buf: &[u8], limits: ReadLimits) -> Result<M, ReadError> {
+    if buf.len() > limits.max_message_size {
+        return Err(ReadError::LimitExceeded);
+    }
+    let budget = Budget::new(limits);
+    let mut msg = M::default();
+    msg.read_raw(&mut ByteReader::with_budget(buf, ReaderConfig::default(), &budget))?;
+    Ok(msg)
+}
+
+/// Deserialize a protobuf message, skipping the fields whose wire type doesn't match their
+/// declared type instead of failing.
+///
+/// Returns the message with a report of the skipped fields, so a schema mismatch with the peer
+/// can be logged while the rest of the message is still used. See
+/// [`ReaderConfig::skip_mismatched`].
+///
+/// ```
+/// #[derive(noproto::Message, Default, Debug)]
+/// struct Status {
+///     #[noproto(tag = 1)]
+///     uptime: u32,
+///     #[noproto(tag = 2)]
+///     label: heapless::String<8>,
+/// }
+///
+/// // `uptime` sent as a fixed32 by a peer with another schema.
+/// let buf = [0x0d, 0x01, 0x00, 0x00, 0x00, 0x12, 0x02, b'o', b'k'];
+/// assert!(noproto::read::<Status>(&buf).is_err());
+///
+/// let (status, skipped) = noproto::read_tolerant::<Status>(&buf).unwrap();
+/// assert_eq!(status.uptime, 0);
+/// assert_eq!(status.label, "ok");
+/// assert_eq!(skipped.count, 1);
+/// assert_eq!(skipped.first_tag, Some(1));
+/// ```
+pub fn read_tolerant<M: Message + Default>(buf: &[u8]) -> Result<(M, Skipped), ReadError> {
+    let config = ReaderConfig {
+        skip_mismatched: true,
+        ..Default::default()
+    };
+    let skipped = core::cell::Cell::new(Skipped::default());
+    let mut msg = M::default();
+    msg.read_raw(&mut ByteReader::with_skipped(buf, config, &skipped))?;
+    Ok((msg, skipped.get()))
+}
+
+/// Deserialize a protobuf message from a buffer, with the given decoding options.
+pub fn read_with_config<M: Message + Default>(buf: &[u8], config: ReaderConfig) -> Result<M, ReadError> {
+    let mut msg = M::default();
+    let mut r = ByteReader::with_config(buf, config);
     msg.read_raw(&mut r)?;
     Ok(msg)
 }
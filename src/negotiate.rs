@@ -0,0 +1,165 @@
+//! Schema negotiation between peers.
+//!
+//! Each side sends a [`Handshake`] listing the message types it supports, identified by an
+//! application-assigned type ID, together with their schema [fingerprint] and the largest
+//! encoding it accepts. [`Handshake::compatible`] then computes the types both sides can
+//! safely exchange, and the size limit to use for each.
+//!
+//! [fingerprint]: crate::descriptor::MessageDescriptor::fingerprint
+//!
+//! ```
+//! use noproto::negotiate::{Handshake, SchemaInfo};
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//! }
+//!
+//! let mut local = Handshake::<4>::new();
+//! local.push(SchemaInfo::of::<Ping>(1)).unwrap();
+//!
+//! let mut buf = [0; 64];
+//! let n = noproto::write(&local, &mut buf).unwrap();
+//! let remote: Handshake<4> = noproto::read(&buf[..n]).unwrap();
+//!
+//! let agreed = local.compatible(&remote);
+//! assert!(agreed.get(1).is_some());
+//! ```
+
+use heapless::Vec;
+
+use crate::descriptor::descriptor;
+use crate::encoding::{max_field_len, max_repeated_len};
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, ValidationError, WireType, WriteError};
+
+/// Schema of one message type supported by a peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SchemaInfo {
+    /// Application-assigned type ID.
+    pub type_id: u32,
+    /// Schema fingerprint.
+    pub hash: u32,
+    /// Largest accepted encoded length.
+    pub max_size: u32,
+}
+
+impl SchemaInfo {
+    /// Describe message `M` under `type_id`.
+    ///
+    /// The hash is the fingerprint of `M`'s descriptor, or 0 if it has none. The maximum size is
+    /// `M::MAX_ENCODED_LEN`, saturated to `u32::MAX`.
+    pub const fn of<M: Message>(type_id: u32) -> Self {
+        let hash = match descriptor::<M>() {
+            Some(d) => d.fingerprint(),
+            None => 0,
+        };
+        let max_size = if M::MAX_ENCODED_LEN > u32::MAX as usize {
+            u32::MAX
+        } else {
+            M::MAX_ENCODED_LEN as u32
+        };
+        Self {
+            type_id,
+            hash,
+            max_size,
+        }
+    }
+}
+
+impl Message for SchemaInfo {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = 3 * max_field_len::<u32>(3);
+
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_field(1, &self.type_id)?;
+        w.write_field(2, &self.hash)?;
+        w.write_field(3, &self.max_size)?;
+        Ok(())
+    }
+
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        for r in r.read_fields() {
+            let r = r?;
+            match r.tag() {
+                1 => r.read(&mut self.type_id)?,
+                2 => r.read(&mut self.hash)?,
+                3 => r.read(&mut self.max_size)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handshake message, listing the message types supported by a peer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Handshake<const N: usize> {
+    schemas: Vec<SchemaInfo, N>,
+}
+
+impl<const N: usize> Handshake<N> {
+    /// Create an empty handshake.
+    pub const fn new() -> Self {
+        Self { schemas: Vec::new() }
+    }
+
+    /// Add a supported message type.
+    ///
+    /// Returns the entry back if the handshake is full.
+    pub fn push(&mut self, schema: SchemaInfo) -> Result<(), SchemaInfo> {
+        self.schemas.push(schema)
+    }
+
+    /// Supported message types.
+    pub fn schemas(&self) -> &[SchemaInfo] {
+        &self.schemas
+    }
+
+    /// Find the entry for `type_id`.
+    pub fn get(&self, type_id: u32) -> Option<&SchemaInfo> {
+        self.schemas.iter().find(|s| s.type_id == type_id)
+    }
+
+    /// Compute the message types supported by both `self` and `remote` with the same schema.
+    ///
+    /// The maximum size of each agreed type is the smaller of the two sides' limits.
+    pub fn compatible<const M: usize>(&self, remote: &Handshake<M>) -> Handshake<N> {
+        let mut agreed = Handshake::new();
+        for local in &self.schemas {
+            if let Some(remote) = remote.get(local.type_id).filter(|r| r.hash == local.hash) {
+                // Can't overflow, `agreed` has at most as many entries as `self`.
+                let _ = agreed.push(SchemaInfo {
+                    max_size: local.max_size.min(remote.max_size),
+                    ..*local
+                });
+            }
+        }
+        agreed
+    }
+}
+
+impl<const N: usize> Message for Handshake<N> {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = max_repeated_len::<Vec<SchemaInfo, N>>(1);
+
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_repeated(1, &self.schemas)
+    }
+
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        for r in r.read_fields() {
+            let r = r?;
+            if r.tag() == 1 {
+                r.read_repeated(&mut self.schemas)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        crate::RepeatedMessage::validate(&self.schemas)
+    }
+}
@@ -0,0 +1,164 @@
+//! Typed paths to fields, checked at compile time.
+//!
+//! Messages marked `#[noproto(fields)]` get a `fields()` function returning a selector with a
+//! method per field. Selecting a message field returns its own selector, so paths into nested
+//! messages are built by chaining calls, and a misspelled or removed field fails the build
+//! instead of silently matching nothing. Paths through repeated and optional fields select
+//! their elements. Oneof fields have no selector.
+//!
+//! A path resolves to its tags, for APIs working with tag paths:
+//!
+//! ```
+//! #[derive(noproto::Message, Default)]
+//! #[noproto(fields)]
+//! struct Radio {
+//!     #[noproto(tag = 1)]
+//!     power: i32,
+//!     #[noproto(tag = 2)]
+//!     channel: u32,
+//!     #[noproto(tag = 3)]
+//!     label: heapless::String<8>,
+//! }
+//!
+//! #[derive(noproto::Message, Default)]
+//! #[noproto(fields)]
+//! struct Config {
+//!     #[noproto(tag = 1)]
+//!     name: heapless::String<16>,
+//!     #[noproto(tag = 4, optional)]
+//!     radio: Option<Radio>,
+//! }
+//!
+//! let path = Config::fields().radio().channel();
+//! assert_eq!(path.tags(), [4, 2]);
+//! assert_eq!(Config::fields().radio().tags(), [4]);
+//!
+//! // Tell where decoding failed.
+//! let Err(err) = noproto::read_with_context::<Config>(&[0x22, 0x03, 0x1a, 0x01, 0xff]) else { panic!() };
+//! assert_eq!(err.path(), Config::fields().radio().label().tags());
+//! ```
+//!
+//! Field types of a `fields` message must implement [`Select`]: derived messages marked
+//! `#[noproto(fields)]` and the scalar, string and bytes types do. Other types, e.g. with a
+//! manual [`Message`](crate::Message) impl, can be selected as a whole by implementing it
+//! with `type Fields<R> = FieldPath<R, Self>`.
+
+use core::marker::PhantomData;
+
+/// Maximum number of fields in a [`FieldPath`].
+pub const MAX_PATH_DEPTH: usize = 8;
+
+/// Types whose fields can be selected.
+pub trait Select: Sized {
+    /// Selector for the fields of `Self`, within a path from messages of type `R`. Plain
+    /// [`FieldPath`] for types without fields.
+    type Fields<R>: From<FieldPath<R, Self>>;
+}
+
+/// Path of tags from a message of type `R` to a field of type `T`.
+pub struct FieldPath<R, T> {
+    tags: [u32; MAX_PATH_DEPTH],
+    len: usize,
+    _marker: PhantomData<fn(&R) -> &T>,
+}
+
+impl<R> FieldPath<R, R> {
+    /// Create the empty path, selecting the whole message.
+    pub const fn root() -> Self {
+        Self {
+            tags: [0; MAX_PATH_DEPTH],
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<R, T> FieldPath<R, T> {
+    /// Get the tags of the fields along the path, outermost first.
+    pub fn tags(&self) -> &[u32] {
+        &self.tags[..self.len]
+    }
+
+    /// Append the field `tag` of type `U`, for derived selectors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the path is already [`MAX_PATH_DEPTH`] fields long.
+    #[doc(hidden)]
+    pub const fn __push<U>(self, tag: u32) -> FieldPath<R, U> {
+        assert!(self.len < MAX_PATH_DEPTH, "field path deeper than MAX_PATH_DEPTH");
+        let mut tags = self.tags;
+        tags[self.len] = tag;
+        FieldPath {
+            tags,
+            len: self.len + 1,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<R, T> Clone for FieldPath<R, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R, T> Copy for FieldPath<R, T> {}
+
+impl<R, T> PartialEq for FieldPath<R, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.tags() == other.tags()
+    }
+}
+
+impl<R, T> Eq for FieldPath<R, T> {}
+
+impl<R, T> core::fmt::Debug for FieldPath<R, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("FieldPath").field(&self.tags()).finish()
+    }
+}
+
+macro_rules! leaf {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Select for $ty {
+                type Fields<R> = FieldPath<R, Self>;
+            }
+        )*
+    };
+}
+
+leaf!(bool, u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+leaf!(
+    crate::types::Fixed32,
+    crate::types::Fixed64,
+    crate::types::SFixed32,
+    crate::types::SFixed64,
+    crate::types::Sint32,
+    crate::types::Sint64,
+    crate::types::Int32,
+    crate::types::Int64,
+    crate::types::Uint32,
+    crate::types::Uint64,
+);
+
+impl<const N: usize> Select for heapless::String<N> {
+    type Fields<R> = FieldPath<R, Self>;
+}
+
+impl<const N: usize> Select for heapless::Vec<u8, N> {
+    type Fields<R> = FieldPath<R, Self>;
+}
+
+impl<T> Select for crate::types::RawLe<T> {
+    type Fields<R> = FieldPath<R, Self>;
+}
+
+impl<T: crate::intern::InternTable> Select for crate::intern::InternedStr<T> {
+    type Fields<R> = FieldPath<R, Self>;
+}
+
+impl Select for crate::scratch::ScratchBytes<'_, '_> {
+    type Fields<R> = FieldPath<R, Self>;
+}
@@ -0,0 +1,129 @@
+//! Planning how a message fits into a buffer before encoding it.
+
+use crate::descriptor::{self, Label};
+use crate::write::ByteWriter;
+use crate::{Message, WriteError};
+
+/// Maximum number of top-level fields tracked by a [`Plan`].
+pub const MAX_PLAN_FIELDS: usize = 32;
+
+/// Encoded size of a top-level field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldPlan {
+    /// Field tag. For oneofs, the lowest tag of the oneof.
+    pub tag: u32,
+    /// Cardinality. Oneofs are reported as [`Label::Optional`].
+    pub label: Label,
+    /// Encoded length of the field, including headers.
+    pub len: usize,
+}
+
+/// What to do with a field so the message fits, see [`Plan::degrade`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Leave the field out.
+    Drop {
+        /// Field tag.
+        tag: u32,
+    },
+    /// Truncate a repeated field so it encodes to at most `len` bytes.
+    Truncate {
+        /// Field tag.
+        tag: u32,
+        /// Maximum encoded length of the field, including headers.
+        len: usize,
+    },
+}
+
+/// Per-field encoded sizes of a message, compared to a buffer capacity.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    /// Top-level fields in encoding order.
+    pub fields: heapless::Vec<FieldPlan, MAX_PLAN_FIELDS>,
+    /// Total encoded length.
+    pub len: usize,
+    /// Buffer capacity the plan was made for.
+    pub capacity: usize,
+}
+
+impl Plan {
+    /// Whether the message fits as-is.
+    pub fn fits(&self) -> bool {
+        self.len <= self.capacity
+    }
+
+    /// Number of bytes over capacity.
+    pub fn excess(&self) -> usize {
+        self.len.saturating_sub(self.capacity)
+    }
+
+    /// Suggest optional and repeated fields to drop or truncate so the message fits.
+    ///
+    /// Fields are picked largest first. A repeated field is truncated instead of dropped if that
+    /// is enough. Returns `None` if dropping every optional and repeated field is still not enough.
+    pub fn degrade(&self) -> Option<heapless::Vec<Action, MAX_PLAN_FIELDS>> {
+        let mut candidates: heapless::Vec<&FieldPlan, MAX_PLAN_FIELDS> = self
+            .fields
+            .iter()
+            .filter(|f| f.label != Label::Single && f.len != 0)
+            .collect();
+        candidates.sort_unstable_by_key(|f| core::cmp::Reverse(f.len));
+
+        let mut actions = heapless::Vec::new();
+        let mut excess = self.excess();
+        for f in candidates {
+            if excess == 0 {
+                break;
+            }
+            let action = if f.label == Label::Repeated && f.len > excess {
+                Action::Truncate {
+                    tag: f.tag,
+                    len: f.len - excess,
+                }
+            } else {
+                Action::Drop { tag: f.tag }
+            };
+            excess = excess.saturating_sub(f.len);
+            // Can't overflow, there is at most one action per field.
+            let _ = actions.push(action);
+        }
+        (excess == 0).then_some(actions)
+    }
+}
+
+/// Measure the encoded size of each top-level field of `msg` against a buffer of `capacity` bytes.
+///
+/// Nothing is written anywhere. Messages without a descriptor are reported as a single field.
+pub fn plan<M: Message>(msg: &M, capacity: usize) -> Result<Plan, WriteError> {
+    let mut plan = Plan {
+        fields: heapless::Vec::new(),
+        len: 0,
+        capacity,
+    };
+
+    // Reconstruct the encoding order used by the derive: fields and oneofs, sorted by lowest tag.
+    let mut layout: heapless::Vec<(u32, Label), MAX_PLAN_FIELDS> = heapless::Vec::new();
+    match descriptor::descriptor::<M>() {
+        Some(d) => {
+            for f in d.fields {
+                layout.push((f.tag, f.label)).map_err(|_| WriteError)?;
+            }
+            for o in d.oneofs {
+                let tag = o.fields.iter().map(|f| f.tag).min().unwrap_or(0);
+                layout.push((tag, Label::Optional)).map_err(|_| WriteError)?;
+            }
+            layout.sort_unstable_by_key(|&(tag, _)| tag);
+        }
+        None => layout.push((0, Label::Single)).map_err(|_| WriteError)?,
+    }
+
+    for (index, &(tag, label)) in layout.iter().enumerate() {
+        let mut w = ByteWriter::counting();
+        msg.write_nth_field(index, &mut w)?;
+        let len = w.pos();
+        plan.len += len;
+        // Can't overflow, `layout` has the same capacity.
+        let _ = plan.fields.push(FieldPlan { tag, label, len });
+    }
+    Ok(plan)
+}
@@ -0,0 +1,73 @@
+//! Reusable message instances, for decoding large messages without building them on the stack.
+//!
+//! [`read`](crate::read) builds a fresh message for each decode and returns it by value, which
+//! for messages with big `heapless` containers means a large stack frame and copies of the
+//! whole message. A [`DecodePool`] instead owns `N` instances, typically in a `static` or a
+//! long-lived task, and decodes in place into a free one, handing out a reference to it. The
+//! instance goes back to the pool when the reference is dropped, and is reset before its next
+//! decode.
+//!
+//! ```
+//! use noproto::pool::DecodePool;
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Frame {
+//!     #[noproto(tag = 1)]
+//!     payload: heapless::Vec<u8, 256>,
+//! }
+//!
+//! let pool = DecodePool::<Frame, 2>::new();
+//! let a = pool.decode(&[0x0a, 0x01, 0xaa]).unwrap();
+//! let b = pool.decode(&[0x0a, 0x02, 0xbb, 0xcc]).unwrap();
+//! assert_eq!(a.payload, [0xaa]);
+//! assert_eq!(b.payload, [0xbb, 0xcc]);
+//!
+//! // Both instances are in use.
+//! assert!(pool.decode(&[]).is_err());
+//! drop(a);
+//! assert_eq!(pool.available(), 1);
+//! ```
+
+use core::cell::{RefCell, RefMut};
+
+use crate::{Message, ReadError};
+
+/// Pool of `N` message instances reused across decodes.
+pub struct DecodePool<M, const N: usize> {
+    slots: [RefCell<M>; N],
+}
+
+impl<M: Message + Default, const N: usize> DecodePool<M, N> {
+    /// Create a new [`DecodePool`] with `N` default instances.
+    pub fn new() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| RefCell::new(M::default())),
+        }
+    }
+
+    /// Decode `buf` into a free instance and return it.
+    ///
+    /// Fails with [`ReadError::CapacityExceeded`] if all instances are in use. If decoding
+    /// fails, the instance is released right away.
+    pub fn decode(&self, buf: &[u8]) -> Result<RefMut<'_, M>, ReadError> {
+        let mut msg = self
+            .slots
+            .iter()
+            .find_map(|slot| slot.try_borrow_mut().ok())
+            .ok_or(ReadError::CapacityExceeded)?;
+        *msg = M::default();
+        crate::merge(buf, &mut *msg)?;
+        Ok(msg)
+    }
+
+    /// Get the number of instances not in use.
+    pub fn available(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.try_borrow_mut().is_ok()).count()
+    }
+}
+
+impl<M: Message + Default, const N: usize> Default for DecodePool<M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,38 @@
+/// Presence bits for fields marked `#[noproto(has_bit)]`.
+///
+/// Holds `32 * N` bits. Messages with many optional scalars can track presence in a
+/// `Presence` field instead of wrapping each field in an `Option`, which usually
+/// wastes space for padding and discriminants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Presence<const N: usize>([u32; N]);
+
+impl<const N: usize> Presence<N> {
+    /// Number of bits available.
+    pub const BITS: usize = 32 * N;
+
+    /// Create a new [`Presence`] with no bits set.
+    pub const fn new() -> Self {
+        Self([0; N])
+    }
+
+    /// Check if `bit` is set.
+    pub fn get(&self, bit: usize) -> bool {
+        self.0[bit / 32] & (1 << (bit % 32)) != 0
+    }
+
+    /// Set `bit`.
+    pub fn set(&mut self, bit: usize) {
+        self.0[bit / 32] |= 1 << (bit % 32);
+    }
+
+    /// Clear `bit`.
+    pub fn clear(&mut self, bit: usize) {
+        self.0[bit / 32] &= !(1 << (bit % 32));
+    }
+}
+
+impl<const N: usize> Default for Presence<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,183 @@
+//! Incremental decoding from chunks of bytes.
+//!
+//! A [`PushDecoder`] is fed chunks as they arrive, e.g. from a receive interrupt or a radio
+//! packet, and keeps track of partially received fields between them, so a message larger than
+//! any single chunk is decoded without first being buffered whole. Only one top-level field at
+//! a time is buffered, in an internal buffer of `N` bytes.
+//!
+//! ```
+//! use core::task::Poll;
+//!
+//! use noproto::push::PushDecoder;
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//!     #[noproto(tag = 2)]
+//!     name: heapless::String<8>,
+//! }
+//!
+//! // Two delimited messages, received in arbitrary chunks.
+//! let stream = [0x06, 0x08, 0x01, 0x12, 0x02, b'h', b'i', 0x02, 0x08, 0x02];
+//! let mut d = PushDecoder::<Ping, 8>::delimited();
+//! let mut chunk = &stream[..4];
+//! assert_eq!(d.push(&mut chunk), Poll::Pending);
+//! let mut chunk = &stream[4..];
+//! let Poll::Ready(Ok(ping)) = d.push(&mut chunk) else { panic!() };
+//! assert_eq!(ping.name, "hi");
+//! let Poll::Ready(Ok(ping)) = d.push(&mut chunk) else { panic!() };
+//! assert_eq!(ping.seq, 2);
+//! assert!(chunk.is_empty());
+//! ```
+
+use core::task::Poll;
+
+use crate::read::{ByteReader, Required};
+use crate::write::MAX_VARUINT64_LEN;
+use crate::{Message, ReadError};
+
+/// Resumable decoder fed with chunks of bytes.
+///
+/// The internal buffer of `N` bytes must fit the largest top-level field, including its header.
+pub struct PushDecoder<M, const N: usize> {
+    msg: M,
+    required: Required,
+    buf: [u8; N],
+    /// Bytes of the current field in `buf`.
+    len: usize,
+    delimited: bool,
+    /// Bytes of the message not pushed yet, once known.
+    left: Option<usize>,
+    prefix: [u8; MAX_VARUINT64_LEN],
+    prefix_len: usize,
+}
+
+impl<M: Message + Default, const N: usize> PushDecoder<M, N> {
+    /// Create a decoder for a single message, ended by [`finish`](Self::finish).
+    pub fn new() -> Self {
+        Self {
+            msg: M::default(),
+            required: Required::new::<M>(),
+            buf: [0; N],
+            len: 0,
+            delimited: false,
+            left: None,
+            prefix: [0; MAX_VARUINT64_LEN],
+            prefix_len: 0,
+        }
+    }
+
+    /// Create a decoder for a stream of messages, each prefixed by its varint length.
+    pub fn delimited() -> Self {
+        Self {
+            delimited: true,
+            ..Self::new()
+        }
+    }
+
+    /// Discard the message decoded so far.
+    pub fn reset(&mut self) {
+        self.msg = M::default();
+        self.required = Required::new::<M>();
+        self.len = 0;
+        self.left = None;
+        self.prefix_len = 0;
+    }
+
+    /// Feed bytes to the decoder, advancing `data` past the bytes consumed.
+    ///
+    /// Returns the message once a delimited message is complete, leaving any following bytes
+    /// in `data`. On error, the message is discarded and the decoder starts over.
+    pub fn push(&mut self, data: &mut &[u8]) -> Poll<Result<M, ReadError>> {
+        match self.push_inner(data) {
+            Ok(Some(msg)) => Poll::Ready(Ok(msg)),
+            Ok(None) => Poll::Pending,
+            Err(e) => {
+                self.reset();
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+
+    /// End the message, for a decoder created with [`new`](Self::new). Fails if a field or a
+    /// delimited message is incomplete.
+    pub fn finish(&mut self) -> Result<M, ReadError> {
+        let complete = self.len == 0 && self.prefix_len == 0 && self.left.is_none_or(|left| left == 0);
+        let msg = core::mem::take(&mut self.msg);
+        let required = self.required.check();
+        self.reset();
+        if !complete {
+            return Err(ReadError::UnexpectedEof);
+        }
+        required?;
+        Ok(msg)
+    }
+
+    fn push_inner(&mut self, data: &mut &[u8]) -> Result<Option<M>, ReadError> {
+        loop {
+            if self.delimited && self.left.is_none() {
+                while let Some((&b, rest)) = data.split_first() {
+                    *data = rest;
+                    *self.prefix.get_mut(self.prefix_len).ok_or(ReadError::InvalidVarint)? = b;
+                    self.prefix_len += 1;
+                    if b & 0x80 == 0 {
+                        let len = ByteReader::new(&self.prefix[..self.prefix_len]).read_length()?;
+                        self.left = Some(len);
+                        self.prefix_len = 0;
+                        break;
+                    }
+                }
+                if self.left.is_none() {
+                    return Ok(None);
+                }
+            }
+
+            // Buffer as much of the message as fits.
+            let mut n = data.len().min(N - self.len);
+            if let Some(left) = &mut self.left {
+                n = n.min(*left);
+                *left -= n;
+            }
+            self.buf[self.len..][..n].copy_from_slice(&data[..n]);
+            *data = &data[n..];
+            self.len += n;
+
+            // Decode the complete fields.
+            let buf = &self.buf[..self.len];
+            let mut fields = ByteReader::new(buf).fields();
+            let mut done = 0;
+            while let Some(Ok(field)) = fields.next() {
+                self.required.see(field.tag());
+                let end = buf.len() - fields.remaining().len();
+                self.msg.read_raw(&mut ByteReader::partial(&buf[done..end]))?;
+                done = end;
+            }
+            self.buf.copy_within(done..self.len, 0);
+            self.len -= done;
+
+            if self.left == Some(0) {
+                if self.len != 0 {
+                    return Err(ReadError::UnexpectedEof);
+                }
+                self.required.check()?;
+                let msg = core::mem::take(&mut self.msg);
+                self.reset();
+                return Ok(Some(msg));
+            }
+            if data.is_empty() {
+                return Ok(None);
+            }
+            if self.len == N {
+                // The field doesn't fit in the buffer.
+                return Err(ReadError::CapacityExceeded);
+            }
+        }
+    }
+}
+
+impl<M: Message + Default, const N: usize> Default for PushDecoder<M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,18 +1,371 @@
+use core::cell::Cell;
+
+use crate::descriptor::{descriptor, FieldDescriptor, Type};
+use crate::fixed::FixedLe;
+use crate::write::{assert_group, MAX_VARUINT64_LEN};
 use crate::{Message, Oneof, OptionalMessage, RepeatedMessage, WireType};
 
-/// Error returned by [`ByteReader`].
+/// Error returned by [`ByteReader`] and when decoding messages.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ReadError {
+    /// The data ends in the middle of a value.
+    UnexpectedEof,
+    /// A varint is longer than 10 bytes, or is rejected by the [`ReaderConfig`].
+    InvalidVarint,
+    /// A field has an invalid wire type, or one not matching its type.
+    InvalidWireType,
+    /// A group is not terminated by a matching end-group header.
+    InvalidGroup,
+    /// A string is not valid UTF-8.
+    InvalidUtf8,
+    /// A container is too small for the decoded data.
+    CapacityExceeded,
+    /// An enumeration value is unknown.
+    InvalidEnumValue,
+    /// A number doesn't fit in its Rust type.
+    OutOfRange,
+    /// A value doesn't have the length of its type.
+    InvalidLength,
+    /// Other invalid data, e.g. a corrupt compressed field or framing.
+    InvalidData,
+    /// The data exceeds a [`ReadLimits`] limit.
+    LimitExceeded,
+    /// A field marked `#[noproto(required)]` is missing.
+    MissingField,
+}
+
+impl core::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ReadError::UnexpectedEof => "unexpected end of data",
+            ReadError::InvalidVarint => "invalid varint",
+            ReadError::InvalidWireType => "invalid wire type",
+            ReadError::InvalidGroup => "invalid group",
+            ReadError::InvalidUtf8 => "invalid UTF-8 string",
+            ReadError::CapacityExceeded => "capacity exceeded",
+            ReadError::InvalidEnumValue => "invalid enumeration value",
+            ReadError::OutOfRange => "number out of range",
+            ReadError::InvalidLength => "invalid length",
+            ReadError::InvalidData => "invalid data",
+            ReadError::LimitExceeded => "decode limit exceeded",
+            ReadError::MissingField => "missing required field",
+        })
+    }
+}
+
+impl core::error::Error for ReadError {}
+
+/// Maximum nesting depth recorded in an [`ErrorContext`] path.
+pub const MAX_ERROR_DEPTH: usize = 8;
+
+/// A [`ReadError`] with the location where decoding failed, returned by
+/// [`read_with_context`](crate::read_with_context).
+///
+/// Fields are recorded by derived messages, so the path stops at the first message decoded by a
+/// manual [`Message`] impl.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub struct ReadError;
+pub struct ErrorContext {
+    /// The error.
+    pub error: ReadError,
+    /// Byte offset in the buffer of the data of the innermost failing field, or of its header if
+    /// the header is malformed. `None` if the location is unknown.
+    pub offset: Option<usize>,
+    path: [u32; MAX_ERROR_DEPTH],
+    depth: usize,
+}
+
+impl ErrorContext {
+    /// Get the tags of the failing field and of the fields of the messages containing it,
+    /// outermost first.
+    ///
+    /// Only the outermost [`MAX_ERROR_DEPTH`] levels are kept, see [`depth`](Self::depth).
+    pub fn path(&self) -> &[u32] {
+        &self.path[..self.depth.min(MAX_ERROR_DEPTH)]
+    }
+
+    /// Get the nesting depth of the failing field, 0 if the message itself failed.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl From<ErrorContext> for ReadError {
+    fn from(e: ErrorContext) -> Self {
+        e.error
+    }
+}
+
+impl core::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.error.fmt(f)?;
+        if let Some((first, rest)) = self.path().split_first() {
+            write!(f, " in field {}", first)?;
+            for tag in rest {
+                write!(f, ".{}", tag)?;
+            }
+            if self.depth > MAX_ERROR_DEPTH {
+                f.write_str("...")?;
+            }
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for ErrorContext {}
+
+/// Records the location of a decoding failure while the error propagates.
+pub(crate) struct Trace {
+    base: usize,
+    offset: Cell<Option<usize>>,
+    /// Tags recorded innermost first, shifted out once full so the outermost ones are kept.
+    path: Cell<[u32; MAX_ERROR_DEPTH]>,
+    depth: Cell<usize>,
+}
+
+impl Trace {
+    pub(crate) fn new(buf: &[u8]) -> Self {
+        Self {
+            base: buf.as_ptr() as usize,
+            offset: Cell::new(None),
+            path: Cell::new([0; MAX_ERROR_DEPTH]),
+            depth: Cell::new(0),
+        }
+    }
+
+    /// Record the failure location, if not already known.
+    fn at(&self, data: &[u8]) {
+        if self.offset.get().is_none() {
+            self.offset.set(Some(data.as_ptr() as usize - self.base));
+        }
+    }
+
+    /// Record a field containing the failure.
+    fn field(&self, tag: u32, data: &[u8]) {
+        self.at(data);
+        let mut path = self.path.get();
+        let depth = self.depth.get();
+        if depth < MAX_ERROR_DEPTH {
+            path[depth] = tag;
+        } else {
+            path.copy_within(1.., 0);
+            path[MAX_ERROR_DEPTH - 1] = tag;
+        }
+        self.path.set(path);
+        self.depth.set(depth + 1);
+    }
+
+    pub(crate) fn context(&self, error: ReadError) -> ErrorContext {
+        let depth = self.depth.get();
+        let mut path = self.path.get();
+        path[..depth.min(MAX_ERROR_DEPTH)].reverse();
+        ErrorContext {
+            error,
+            offset: self.offset.get(),
+            path,
+            depth,
+        }
+    }
+}
+
+/// Decoding options for [`ByteReader`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ReaderConfig {
+    /// Reject 32-bit varints whose value doesn't fit in 32 bits.
+    ///
+    /// By default (lenient mode), the excess high bits are silently discarded. Some encoders,
+    /// like nanopb, sign-extend negative `int32` values to 64 bits, which decode correctly
+    /// thanks to this truncation. The strict mode turns such truncation into an error, to catch
+    /// peers encoding values that don't fit their field.
+    pub strict_varint32: bool,
+
+    /// Reject varints that overflow 64 bits, or that aren't minimally encoded (e.g. `0x80 0x00`
+    /// for 0).
+    ///
+    /// By default, the excess bits are discarded and padding bytes are accepted, so several
+    /// encodings alias the same value. Varints longer than 10 bytes are always rejected.
+    pub strict_varint: bool,
+
+    /// Reject `bool` values other than 0 and 1.
+    ///
+    /// By default, any non-zero value is decoded as `true`, as the protobuf spec requires.
+    pub strict_bool: bool,
+
+    /// Skip fields whose wire type doesn't match their declared type, instead of failing with
+    /// [`ReadError::InvalidWireType`].
+    ///
+    /// This keeps the rest of the message readable when a peer changed the type of a field,
+    /// see [`read_tolerant`](crate::read_tolerant) to also find out which fields were dropped.
+    pub skip_mismatched: bool,
+}
+
+/// Fields dropped by [`read_tolerant`](crate::read_tolerant) because of a wire type mismatch.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Skipped {
+    /// Number of skipped fields, in the message and its nested messages.
+    pub count: usize,
+    /// Tag of the first skipped field, in whichever message it was.
+    pub first_tag: Option<u32>,
+}
+
+/// Limits bounding the work and memory of decoding untrusted input, see
+/// [`read_with_limits`](crate::read_with_limits).
+///
+/// The default doesn't limit anything.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ReadLimits {
+    /// Maximum length of the encoded message.
+    pub max_message_size: usize,
+    /// Maximum number of fields, counting those of nested messages and unknown fields.
+    pub max_fields: usize,
+    /// Maximum length of a `string` or `bytes` field.
+    pub max_bytes_len: usize,
+    /// Maximum nesting depth of messages, 0 to only allow fields of the top-level message.
+    pub max_depth: usize,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_message_size: usize::MAX,
+            max_fields: usize::MAX,
+            max_bytes_len: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+}
+
+/// Tracks the use of [`ReadLimits`] during a decode.
+pub(crate) struct Budget {
+    limits: ReadLimits,
+    fields: Cell<usize>,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: ReadLimits) -> Self {
+        Self {
+            limits,
+            fields: Cell::new(0),
+        }
+    }
+}
+
+/// Required fields of a message seen by a decode done one top-level field at a time, whose
+/// readers are [partial](ByteReader::is_partial).
+pub(crate) struct Required {
+    fields: &'static [FieldDescriptor],
+    seen: u64,
+}
+
+impl Required {
+    pub(crate) fn new<M: Message>() -> Self {
+        Self {
+            fields: descriptor::<M>().map_or(&[], |desc| desc.fields),
+            seen: 0,
+        }
+    }
+
+    /// Record a top-level field.
+    pub(crate) fn see(&mut self, tag: u32) {
+        let required = self.fields.iter().filter(|f| f.required);
+        if let Some(i) = required.clone().position(|f| f.tag == tag) {
+            self.seen |= 1 << i;
+        }
+    }
+
+    /// Check that all required fields were seen.
+    pub(crate) fn check(&self) -> Result<(), ReadError> {
+        let count = self.fields.iter().filter(|f| f.required).count();
+        match self.seen.count_ones() as usize == count {
+            true => Ok(()),
+            false => Err(ReadError::MissingField),
+        }
+    }
+}
 
 /// Reader for protobuf messages.
+#[derive(Clone)]
 pub struct ByteReader<'a> {
     data: &'a [u8],
+    config: ReaderConfig,
+    trace: Option<&'a Trace>,
+    budget: Option<&'a Budget>,
+    skipped: Option<&'a Cell<Skipped>>,
+    /// Nesting depth of the message being read.
+    depth: usize,
+    /// Holds only some of the top-level fields of the message.
+    partial: bool,
 }
 
 impl<'a> ByteReader<'a> {
     /// Create a new [`ByteReader`] that reads from `data`.
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data }
+        Self::with_config(data, ReaderConfig::default())
+    }
+
+    /// Create a new [`ByteReader`] that reads from `data` with the given options.
+    pub fn with_config(data: &'a [u8], config: ReaderConfig) -> Self {
+        Self {
+            data,
+            config,
+            trace: None,
+            budget: None,
+            skipped: None,
+            depth: 0,
+            partial: false,
+        }
+    }
+
+    /// Create a new [`ByteReader`] for some of the top-level fields of a message, see
+    /// [`is_partial`](Self::is_partial).
+    pub(crate) fn partial(data: &'a [u8]) -> Self {
+        Self {
+            partial: true,
+            ..Self::new(data)
+        }
+    }
+
+    /// Create a new [`ByteReader`] recording decoding failures into `trace`.
+    pub(crate) fn with_trace(data: &'a [u8], config: ReaderConfig, trace: &'a Trace) -> Self {
+        Self {
+            trace: Some(trace),
+            ..Self::with_config(data, config)
+        }
+    }
+
+    /// Create a new [`ByteReader`] enforcing the limits of `budget`.
+    pub(crate) fn with_budget(data: &'a [u8], config: ReaderConfig, budget: &'a Budget) -> Self {
+        Self {
+            budget: Some(budget),
+            ..Self::with_config(data, config)
+        }
+    }
+
+    /// Create a new [`ByteReader`] counting the fields skipped by
+    /// [`ReaderConfig::skip_mismatched`] into `skipped`.
+    pub(crate) fn with_skipped(data: &'a [u8], config: ReaderConfig, skipped: &'a Cell<Skipped>) -> Self {
+        Self {
+            skipped: Some(skipped),
+            ..Self::with_config(data, config)
+        }
+    }
+
+    /// Get the decoding options.
+    pub fn config(&self) -> ReaderConfig {
+        self.config
+    }
+
+    /// Check if the reader holds only some of the top-level fields of the message, when it's
+    /// decoded one field at a time.
+    ///
+    /// Derived messages don't check their required fields then, the caller does once all the
+    /// fields are decoded.
+    pub fn is_partial(&self) -> bool {
+        self.partial
     }
 
     /// Get a reference to the remaining bytes.
@@ -27,9 +380,9 @@ impl<'a> ByteReader<'a> {
 
     /// Read `N` bytes from the buffer.
     pub fn read<const N: usize>(&mut self) -> Result<[u8; N], ReadError> {
-        let n = self.data.get(0..N).ok_or(ReadError)?;
-        self.data = &self.data[N..];
-        Ok(n.try_into().unwrap())
+        let (n, rest) = self.data.split_first_chunk().ok_or(ReadError::UnexpectedEof)?;
+        self.data = rest;
+        Ok(*n)
     }
 
     /// Read a single byte from the buffer.
@@ -54,11 +407,26 @@ impl<'a> ByteReader<'a> {
 
     /// Read a slice of length `len` from the buffer.
     pub fn read_slice(&mut self, len: usize) -> Result<&'a [u8], ReadError> {
-        let res = self.data.get(0..len).ok_or(ReadError)?;
+        let res = self.data.get(0..len).ok_or(ReadError::UnexpectedEof)?;
         self.data = &self.data[len..];
         Ok(res)
     }
 
+    /// Read fixed-width values from the buffer into `out`, e.g. from the body of a packed
+    /// repeated field.
+    ///
+    /// On little-endian targets this is a single copy.
+    pub fn read_fixed_slice<T: FixedLe>(&mut self, out: &mut [T]) -> Result<(), ReadError> {
+        let bytes = self.read_slice(out.len() * T::SIZE)?;
+        #[cfg(target_endian = "little")]
+        crate::fixed::as_bytes_mut(out).copy_from_slice(bytes);
+        #[cfg(not(target_endian = "little"))]
+        for (v, bytes) in out.iter_mut().zip(bytes.chunks_exact(T::SIZE)) {
+            *v = T::get_le(bytes);
+        }
+        Ok(())
+    }
+
     /// Read the remaining bytes from the buffer.
     pub fn read_to_end(&mut self) -> Result<&'a [u8], ReadError> {
         let res = self.data;
@@ -68,15 +436,23 @@ impl<'a> ByteReader<'a> {
 
     /// Read a variable length slice from the buffer.
     pub fn read_varslice(&mut self) -> Result<&'a [u8], ReadError> {
-        let len = self.read_varuint32()? as usize;
+        let len = self.read_length()?;
         self.read_slice(len)
     }
 
+    /// Read a varint length prefix from the buffer.
+    ///
+    /// The full 64-bit varint is decoded, so lengths from peers writing 64-bit length prefixes
+    /// aren't truncated. Fails if the length doesn't fit in `usize`, it can't be in the buffer.
+    pub fn read_length(&mut self) -> Result<usize, ReadError> {
+        usize::try_from(self.read_varuint64()?).map_err(|_| ReadError::InvalidLength)
+    }
+
     /// Read varint-encoded bytes from the buffer.
     pub fn read_varuint_bytes(&mut self) -> Result<&'a [u8], ReadError> {
-        for i in 0.. {
+        for i in 0..MAX_VARUINT64_LEN {
             if i >= self.data.len() {
-                return Err(ReadError);
+                return Err(ReadError::UnexpectedEof);
             }
             if self.data[i] & 0x80 == 0 {
                 let res = &self.data[..i + 1];
@@ -84,29 +460,21 @@ impl<'a> ByteReader<'a> {
                 return Ok(res);
             }
         }
-        unreachable!()
+        Err(ReadError::InvalidVarint)
     }
 
     /// Read varint-encoded u32 from the buffer.
+    ///
+    /// Values that don't fit in 32 bits are truncated, unless [`ReaderConfig::strict_varint32`] is set.
     pub fn read_varuint32(&mut self) -> Result<u32, ReadError> {
-        let mut res = 0;
-        let mut shift = 0;
-        loop {
-            let x = self.read_u8()?;
+        let res = self.read_varuint64()?;
 
-            // avoid shift overflow if the varuint is more than 32bit.
-            // this happpens in practice: negative int32's are encoded as 64bit two's complement
-            // (in nanopb at least, I haven't checked other impls.)
-            if shift < 32 {
-                res |= (x as u32 & 0x7F) << shift;
-            }
-
-            if x & 0x80 == 0 {
-                break;
-            }
-            shift += 7;
+        // Values over 32 bits happen in practice: negative int32's are encoded as 64bit two's
+        // complement (in nanopb at least, I haven't checked other impls.)
+        if res > u32::MAX as u64 && self.config.strict_varint32 {
+            return Err(ReadError::InvalidVarint);
         }
-        Ok(res)
+        Ok(res as u32)
     }
 
     /// Read a varint-encoded i32 from the buffer.
@@ -120,21 +488,22 @@ impl<'a> ByteReader<'a> {
     /// Read a varint-encoded u64 from the buffer.
     pub fn read_varuint64(&mut self) -> Result<u64, ReadError> {
         let mut res = 0;
-        let mut shift = 0;
-        loop {
+        for i in 0..MAX_VARUINT64_LEN {
             let x = self.read_u8()?;
 
-            // avoid shift overflow if the varuint is more than 64bit.
-            if shift < 64 {
-                res |= (x as u64 & 0x7F) << shift;
-            }
+            // Bits past the 64th, in the 10th byte, are discarded.
+            res |= (x as u64 & 0x7F) << (7 * i);
 
             if x & 0x80 == 0 {
-                break;
+                let overflow = i == MAX_VARUINT64_LEN - 1 && x > 1;
+                let padded = i > 0 && x == 0;
+                if (overflow || padded) && self.config.strict_varint {
+                    return Err(ReadError::InvalidVarint);
+                }
+                return Ok(res);
             }
-            shift += 7;
         }
-        Ok(res)
+        Err(ReadError::InvalidVarint)
     }
 
     /// Read a varint-encoded i64 from the buffer.
@@ -145,18 +514,112 @@ impl<'a> ByteReader<'a> {
         Ok(((u >> 1) as i64) ^ -((u & 1) as i64))
     }
 
-    /// Return an iterator over the fields in the buffer.
-    pub fn read_fields(&mut self) -> FieldIter<'_, 'a> {
-        FieldIter { r: self }
+    /// Return an iterator over the remaining fields in the buffer, consuming them from the reader.
+    pub fn read_fields(&mut self) -> FieldIter<'a> {
+        FieldIter {
+            r: ByteReader {
+                data: core::mem::take(&mut self.data),
+                ..*self
+            },
+        }
+    }
+
+    /// Return an iterator over the remaining fields in the buffer, without consuming them.
+    ///
+    /// The iterator is cheap to clone, so it can be used to scan the same fields several times.
+    pub fn fields(&self) -> FieldIter<'a> {
+        FieldIter { r: self.clone() }
     }
 }
 
 /// Iterator over the fields in a buffer.
-pub struct FieldIter<'a, 'b> {
-    r: &'a mut ByteReader<'b>,
+///
+/// Fields of every valid wire type are consumed, including groups, so messages with fields
+/// unknown to the decoder (e.g. added by a newer schema) can still be parsed: derived messages
+/// ignore them.
+///
+/// ```
+/// #[derive(noproto::Message, Default)]
+/// struct Old {
+///     #[noproto(tag = 1)]
+///     seq: u32,
+/// }
+///
+/// let buf = [
+///     0x10, 0x96, 0x01, // 2: varint
+///     0x19, 1, 2, 3, 4, 5, 6, 7, 8, // 3: 64-bit
+///     0x22, 0x01, 0xff, // 4: length-delimited
+///     0x2b, 0x08, 0x01, 0x2c, // 5: group
+///     0x35, 1, 2, 3, 4, // 6: 32-bit
+///     0x08, 0x07, // 1: varint
+/// ];
+/// let old: Old = noproto::read(&buf).unwrap();
+/// assert_eq!(old.seq, 7);
+/// ```
+#[derive(Clone)]
+pub struct FieldIter<'a> {
+    r: ByteReader<'a>,
 }
 
-impl<'a, 'b> Iterator for FieldIter<'a, 'b> {
+impl<'a> FieldIter<'a> {
+    /// Get the bytes of the fields not yet returned by the iterator.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.r.data
+    }
+}
+
+/// Skip the body of a field with the given wire type, after its header.
+///
+/// Returns the skipped data: the varint bytes, the fixed-width bytes, the contents of a
+/// length-delimited field without its length prefix, or the fields of a group without its
+/// end-group header.
+pub fn skip_field<'a>(wire_type: WireType, r: &mut ByteReader<'a>) -> Result<&'a [u8], ReadError> {
+    match wire_type {
+        WireType::Varint => r.read_varuint_bytes(),
+        WireType::SixtyFourBit => r.read_slice(8),
+        WireType::ThirtyTwoBit => r.read_slice(4),
+        WireType::LengthDelimited => r.read_varslice(),
+        WireType::StartGroup => skip_group(None, r),
+    }
+}
+
+const START_GROUP: u32 = 3;
+/// Wire type of the header closing a group, not a [`WireType`] as no field has it.
+pub(crate) const END_GROUP: u32 = 4;
+
+/// Skip the body of a group, after its start-group header, up to and including the matching
+/// end-group header. Returns the body, without the end-group header.
+///
+/// Nested groups are tracked by depth only, just the outermost end tag is checked, if given.
+fn skip_group<'a>(tag: Option<u32>, r: &mut ByteReader<'a>) -> Result<&'a [u8], ReadError> {
+    let data = r.data;
+    let mut depth = 0usize;
+    loop {
+        let body_len = data.len() - r.data.len();
+        let header = r.read_varuint32()?;
+        match header & 0b111 {
+            0 => {
+                r.read_varuint_bytes()?;
+            }
+            1 => {
+                r.read_slice(8)?;
+            }
+            2 => {
+                r.read_varslice()?;
+            }
+            5 => {
+                r.read_slice(4)?;
+            }
+            START_GROUP => depth += 1,
+            END_GROUP if depth > 0 => depth -= 1,
+            END_GROUP if tag.is_none_or(|tag| header >> 3 == tag) => return Ok(&data[..body_len]),
+            END_GROUP => return Err(ReadError::InvalidGroup),
+            _ => return Err(ReadError::InvalidWireType),
+        }
+    }
+}
+
+impl<'a> Iterator for FieldIter<'a> {
     type Item = Result<FieldReader<'a>, ReadError>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -164,44 +627,65 @@ impl<'a, 'b> Iterator for FieldIter<'a, 'b> {
             return None;
         }
 
+        let start = self.r.data;
+        let res = self.next_field();
+        if let (Err(_), Some(trace)) = (&res, self.r.trace) {
+            trace.at(start);
+        }
+        Some(res)
+    }
+}
+
+impl<'a> FieldIter<'a> {
+    fn next_field(&mut self) -> Result<FieldReader<'a>, ReadError> {
         // Read header
-        let header = match self.r.read_varuint32() {
-            Ok(x) => x,
-            Err(e) => return Some(Err(e)),
-        };
+        let header = self.r.read_varuint32()?;
         let tag = header >> 3;
         let wire_type = match header & 0b111 {
             0 => WireType::Varint,
+            1 => WireType::SixtyFourBit,
             2 => WireType::LengthDelimited,
-            _ => return Some(Err(ReadError)),
+            START_GROUP => WireType::StartGroup,
+            5 => WireType::ThirtyTwoBit,
+            END_GROUP => return Err(ReadError::InvalidGroup),
+            _ => return Err(ReadError::InvalidWireType),
         };
 
         let data = match wire_type {
-            WireType::Varint => match self.r.read_varuint_bytes() {
-                Ok(x) => x,
-                Err(e) => return Some(Err(e)),
-            },
-            WireType::LengthDelimited => {
-                let len = match self.r.read_varuint32() {
-                    Ok(x) => x as usize,
-                    Err(e) => return Some(Err(e)),
-                };
-
-                match self.r.read_slice(len) {
-                    Ok(x) => x,
-                    Err(e) => return Some(Err(e)),
-                }
-            }
+            WireType::StartGroup => skip_group(Some(tag), &mut self.r)?,
+            _ => skip_field(wire_type, &mut self.r)?,
         };
-        Some(Ok(FieldReader { tag, data, wire_type }))
+        if let Some(budget) = self.r.budget {
+            let fields = budget.fields.get() + 1;
+            budget.fields.set(fields);
+            if fields > budget.limits.max_fields || self.r.depth > budget.limits.max_depth {
+                return Err(ReadError::LimitExceeded);
+            }
+        }
+        Ok(FieldReader {
+            tag,
+            data,
+            wire_type,
+            config: self.r.config,
+            trace: self.r.trace,
+            budget: self.r.budget,
+            skipped: self.r.skipped,
+            depth: self.r.depth,
+        })
     }
 }
 
 /// Reader for fields in a protobuf message.
+#[derive(Clone)]
 pub struct FieldReader<'a> {
     tag: u32,
     data: &'a [u8],
     wire_type: WireType,
+    config: ReaderConfig,
+    trace: Option<&'a Trace>,
+    budget: Option<&'a Budget>,
+    skipped: Option<&'a Cell<Skipped>>,
+    depth: usize,
 }
 
 impl<'a> FieldReader<'a> {
@@ -210,19 +694,102 @@ impl<'a> FieldReader<'a> {
         self.tag
     }
 
+    /// Get the wire type of the field.
+    pub fn wire_type(&self) -> WireType {
+        self.wire_type
+    }
+
+    /// Get the raw encoded value of the field, without the length prefix for length-delimited fields.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Run `f` on this field, recording the field in the [`ErrorContext`] if it fails.
+    ///
+    /// Used by derived messages to report the path to a failing field.
+    pub fn traced(self, f: impl FnOnce(Self) -> Result<(), ReadError>) -> Result<(), ReadError> {
+        let (tag, data, trace) = (self.tag, self.data, self.trace);
+        f(self).inspect_err(|_| {
+            if let Some(trace) = trace {
+                trace.field(tag, data);
+            }
+        })
+    }
+
+    /// Reader for the contents of the field, one level deeper.
+    fn reader(&self, data: &'a [u8]) -> ByteReader<'a> {
+        ByteReader {
+            data,
+            config: self.config,
+            trace: self.trace,
+            budget: self.budget,
+            skipped: self.skipped,
+            depth: self.depth + 1,
+            partial: false,
+        }
+    }
+
+    /// Check the wire type of the field against `expected`.
+    ///
+    /// On mismatch, fails unless [`ReaderConfig::skip_mismatched`] is set, in which case the
+    /// field is recorded as skipped and `true` is returned.
+    fn skip_mismatched(&self, expected: WireType) -> Result<bool, ReadError> {
+        if self.wire_type == expected {
+            return Ok(false);
+        }
+        if !self.config.skip_mismatched {
+            return Err(ReadError::InvalidWireType);
+        }
+        if let Some(skipped) = self.skipped {
+            let mut s = skipped.get();
+            s.count += 1;
+            s.first_tag.get_or_insert(self.tag);
+            skipped.set(s);
+        }
+        Ok(true)
+    }
+
+    /// Check a field decoded as `M` against the `string`/`bytes` length limit.
+    fn check_len<M: Message>(&self) -> Result<(), ReadError> {
+        match self.budget {
+            Some(budget)
+                if matches!(M::TYPE, Type::String | Type::Bytes | Type::FixedBytes(_)) && self.data.len() > budget.limits.max_bytes_len =>
+            {
+                Err(ReadError::LimitExceeded)
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Read into a message of type `M`.
     pub fn read<M: Message>(self, msg: &mut M) -> Result<(), ReadError> {
-        if self.wire_type != M::WIRE_TYPE {
-            return Err(ReadError);
+        if self.skip_mismatched(M::WIRE_TYPE)? {
+            return Ok(());
         }
+        self.check_len::<M>()?;
 
-        msg.read_raw(&mut ByteReader::new(self.data))
+        msg.read_raw(&mut self.reader(self.data))
     }
 
     /// Read a repeated field into a message of type `M`.
+    ///
+    /// Scalar elements are accepted both unpacked (one field per element) and packed (all
+    /// elements in one length-delimited field).
     pub fn read_repeated<M: RepeatedMessage>(self, msg: &mut M) -> Result<(), ReadError> {
-        if self.wire_type != M::Message::WIRE_TYPE {
-            return Err(ReadError);
+        let element_wire_type = M::Message::WIRE_TYPE;
+        if self.wire_type == WireType::LengthDelimited && element_wire_type != WireType::LengthDelimited {
+            let mut r = self.reader(self.data);
+            while !r.eof() {
+                let data = skip_field(element_wire_type, &mut r)?;
+                let mut m = M::Message::default();
+                m.read_raw(&mut self.reader(data))?;
+                msg.append(m)?;
+            }
+            return Ok(());
+        }
+
+        if self.skip_mismatched(element_wire_type)? {
+            return Ok(());
         }
 
         let mut m = M::Message::default();
@@ -231,10 +798,42 @@ impl<'a> FieldReader<'a> {
         Ok(())
     }
 
+    /// Read a group field into a message of type `M`.
+    pub fn read_group<M: Message>(self, msg: &mut M) -> Result<(), ReadError> {
+        const { assert_group::<M>() };
+        if self.skip_mismatched(WireType::StartGroup)? {
+            return Ok(());
+        }
+
+        msg.read_raw(&mut self.reader(self.data))
+    }
+
+    /// Read a repeated group field into a message of type `M`.
+    pub fn read_repeated_group<M: RepeatedMessage>(self, msg: &mut M) -> Result<(), ReadError> {
+        if self.skip_mismatched(WireType::StartGroup)? {
+            return Ok(());
+        }
+        let mut m = M::Message::default();
+        self.read_group(&mut m)?;
+        msg.append(m)?;
+        Ok(())
+    }
+
+    /// Read an optional group field into a message of type `M`.
+    pub fn read_optional_group<M: OptionalMessage>(self, msg: &mut M) -> Result<(), ReadError> {
+        if self.skip_mismatched(WireType::StartGroup)? {
+            return Ok(());
+        }
+        let mut m = M::Message::default();
+        self.read_group(&mut m)?;
+        msg.set(m)?;
+        Ok(())
+    }
+
     /// Read an optional field into a message of type `M`.
     pub fn read_optional<M: OptionalMessage>(self, msg: &mut M) -> Result<(), ReadError> {
-        if self.wire_type != M::Message::WIRE_TYPE {
-            return Err(ReadError);
+        if self.skip_mismatched(M::Message::WIRE_TYPE)? {
+            return Ok(());
         }
 
         let mut m = M::Message::default();
@@ -251,11 +850,21 @@ impl<'a> FieldReader<'a> {
     /// Read a oneof variant into a message of type `M`.
     pub fn read_oneof_variant<M: Message + Default>(self) -> Result<M, ReadError> {
         if self.wire_type != M::WIRE_TYPE {
-            return Err(ReadError);
+            return Err(ReadError::InvalidWireType);
         }
+        self.check_len::<M>()?;
 
         let mut msg: M = Default::default();
-        msg.read_raw(&mut ByteReader::new(self.data))?;
+        msg.read_raw(&mut self.reader(self.data))?;
         Ok(msg)
     }
+
+    /// Read a oneof variant into a message of type `M`, or `None` if it was skipped because of
+    /// [`ReaderConfig::skip_mismatched`].
+    pub fn try_read_oneof_variant<M: Message + Default>(self) -> Result<Option<M>, ReadError> {
+        if self.skip_mismatched(M::WIRE_TYPE)? {
+            return Ok(None);
+        }
+        self.read_oneof_variant().map(Some)
+    }
 }
@@ -0,0 +1,174 @@
+use crate::read::END_GROUP;
+use crate::write::{assert_group, varuint_len, ByteWriter, MAX_LENGTH_DELIMITED, MAX_VARUINT64_LEN};
+use crate::{Message, Oneof, OptionalMessage, RepeatedMessage, WireType, WriteError};
+
+/// Writer encoding messages from the end of a buffer towards the front.
+///
+/// Fields are written in reverse order, each value before its header, so the length of a
+/// nested message is known when its length prefix is written, and no bytes are moved or
+/// counted twice. Types without a dedicated [`Message::write_reverse`] are sized with a
+/// counting pass and written forward into the space they need.
+pub struct ReverseWriter<'a> {
+    buf: &'a mut [u8],
+    /// Start of the written data, which runs to the end of `buf`.
+    pos: usize,
+}
+
+impl<'a> ReverseWriter<'a> {
+    /// Create a new [`ReverseWriter`] that writes to the end of `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        let pos = buf.len();
+        Self { buf, pos }
+    }
+
+    /// Get the bytes written so far, at the end of the buffer.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Get the bytes written so far, consuming the writer.
+    pub fn into_bytes(self) -> &'a [u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Get the number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Check if nothing was written yet.
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    /// Reserve `len` bytes in front of the data written so far, and return them.
+    fn reserve(&mut self, len: usize) -> Result<&mut [u8], WriteError> {
+        self.pos = self.pos.checked_sub(len).ok_or(WriteError)?;
+        Ok(&mut self.buf[self.pos..][..len])
+    }
+
+    /// Write `bytes` in front of the data written so far.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.reserve(bytes.len())?.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Write a varint-encoded u32 in front of the data written so far.
+    pub fn write_varuint32(&mut self, val: u32) -> Result<(), WriteError> {
+        self.write_varuint64(val as u64)
+    }
+
+    /// Write a varint-encoded u64 in front of the data written so far.
+    pub fn write_varuint64(&mut self, val: u64) -> Result<(), WriteError> {
+        let mut bytes = [0; MAX_VARUINT64_LEN];
+        let mut w = ByteWriter::new(&mut bytes);
+        w.write_varuint64(val)?;
+        debug_assert!(w.pos() == varuint_len(val));
+        self.write(w.bytes())
+    }
+
+    /// Write data encoded forward by `f`, sizing it first with a counting pass.
+    pub fn write_forward(&mut self, f: impl Fn(&mut ByteWriter) -> Result<(), WriteError>) -> Result<(), WriteError> {
+        let mut w = ByteWriter::counting();
+        f(&mut w)?;
+        let len = w.pos();
+
+        let mut w = ByteWriter::new(self.reserve(len)?);
+        f(&mut w)?;
+        // The space is already reserved, the data must not change length between passes.
+        if w.pos() != len {
+            return Err(WriteError);
+        }
+        Ok(())
+    }
+
+    /// Write a protobuf field in front of the data written so far.
+    pub fn write_field<M: Message>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        let end = self.pos;
+        msg.write_reverse(self)?;
+        if M::WIRE_TYPE == WireType::LengthDelimited {
+            let len = end - self.pos;
+            if len > MAX_LENGTH_DELIMITED {
+                return Err(WriteError);
+            }
+            self.write_varuint32(len as u32)?;
+        }
+        self.write_varuint32((tag << 3) | (M::WIRE_TYPE as u32))
+    }
+
+    /// Write a repeated protobuf field in front of the data written so far.
+    ///
+    /// Elements are visited back to front with `Iterator::nth`, which is constant time for
+    /// slices.
+    pub fn write_repeated<M: RepeatedMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        let count = msg.iter()?.count();
+        for i in (0..count).rev() {
+            let m = msg.iter()?.nth(i).ok_or(WriteError)?;
+            self.write_field(tag, m)?;
+        }
+        Ok(())
+    }
+
+    /// Write a repeated protobuf field in front of the data written so far, packing scalar
+    /// elements. See [`ByteWriter::write_packed`].
+    pub fn write_packed<M: RepeatedMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        if M::Message::WIRE_TYPE == WireType::LengthDelimited {
+            return self.write_repeated(tag, msg);
+        }
+
+        let count = msg.iter()?.count();
+        if count == 0 {
+            return Ok(());
+        }
+        let end = self.pos;
+        for i in (0..count).rev() {
+            let m = msg.iter()?.nth(i).ok_or(WriteError)?;
+            m.write_reverse(self)?;
+        }
+        let len = end - self.pos;
+        if len > MAX_LENGTH_DELIMITED {
+            return Err(WriteError);
+        }
+        self.write_varuint32(len as u32)?;
+        self.write_varuint32((tag << 3) | WireType::LengthDelimited as u32)
+    }
+
+    /// Write an optional protobuf field in front of the data written so far.
+    pub fn write_optional<M: OptionalMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        if let Some(msg) = msg.get() {
+            self.write_field(tag, msg)?;
+        }
+        Ok(())
+    }
+
+    /// Write a message as a group in front of the data written so far.
+    pub fn write_group<M: Message>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        const { assert_group::<M>() };
+        self.write_varuint32((tag << 3) | END_GROUP)?;
+        msg.write_reverse(self)?;
+        self.write_varuint32((tag << 3) | (WireType::StartGroup as u32))
+    }
+
+    /// Write a repeated group field in front of the data written so far.
+    pub fn write_repeated_group<M: RepeatedMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        let count = msg.iter()?.count();
+        for i in (0..count).rev() {
+            let m = msg.iter()?.nth(i).ok_or(WriteError)?;
+            self.write_group(tag, m)?;
+        }
+        Ok(())
+    }
+
+    /// Write an optional group field in front of the data written so far.
+    pub fn write_optional_group<M: OptionalMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        if let Some(msg) = msg.get() {
+            self.write_group(tag, msg)?;
+        }
+        Ok(())
+    }
+
+    /// Write a oneof protobuf field in front of the data written so far.
+    pub fn write_oneof<M: Oneof>(&mut self, msg: &M) -> Result<(), WriteError> {
+        msg.write_reverse(self)
+    }
+}
@@ -0,0 +1,176 @@
+//! Endpoint and topic definitions in the style of postcard-rpc, with protobuf payloads.
+//!
+//! Each endpoint or topic has a path, and each message sent on it is identified by an 8-byte
+//! [`Key`] hashing the path with the schema [fingerprint] of the payload type, so peers with
+//! mismatched schemas don't misinterpret each other's messages. This allows reusing the
+//! tooling built around such definitions while keeping the protobuf wire format.
+//!
+//! A frame is the key, a varint sequence number to match responses to requests, and the
+//! encoded message.
+//!
+//! ```
+//! use noproto::rpc::{Endpoint, Header};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct GetTemp {
+//!     #[noproto(tag = 1)]
+//!     sensor: u32,
+//! }
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Temp {
+//!     #[noproto(tag = 1)]
+//!     millidegrees: i32,
+//! }
+//!
+//! noproto::endpoint!(GetTempEndpoint, GetTemp, Temp, "sensors/temp/get");
+//!
+//! // Client side.
+//! let mut buf = [0; 32];
+//! let req = GetTemp { sensor: 2 };
+//! let n = noproto::rpc::write_frame(GetTempEndpoint::REQ_KEY, 1, &req, &mut buf).unwrap();
+//!
+//! // Server side.
+//! let (header, body) = Header::peek(&buf[..n]).unwrap();
+//! if header.key == GetTempEndpoint::REQ_KEY {
+//!     let req: GetTemp = noproto::read(body).unwrap();
+//!     assert_eq!(req.sensor, 2);
+//!     assert_eq!(header.seq, 1);
+//! }
+//! ```
+//!
+//! [fingerprint]: crate::descriptor::MessageDescriptor::fingerprint
+
+use crate::negotiate::SchemaInfo;
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WriteError};
+
+/// Identifier of the messages of one type sent on one path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Key(pub [u8; 8]);
+
+impl Key {
+    /// Compute the key for messages of type `M` on `path`: the 64-bit FNV-1a hash of the path
+    /// followed by the little-endian schema fingerprint of `M` (0 if it has no descriptor).
+    pub const fn for_path<M: Message>(path: &str) -> Key {
+        let mut h = FNV_OFFSET;
+        h = fnv1a(h, path.as_bytes());
+        h = fnv1a(h, &SchemaInfo::of::<M>(0).hash.to_le_bytes());
+        Key(h.to_le_bytes())
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+const fn fnv1a(mut h: u64, bytes: &[u8]) -> u64 {
+    let mut i = 0;
+    while i < bytes.len() {
+        h ^= bytes[i] as u64;
+        h = h.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    h
+}
+
+/// A request/response endpoint, usually defined with [`endpoint!`](crate::endpoint).
+pub trait Endpoint {
+    /// Request message.
+    type Request: Message;
+    /// Response message.
+    type Response: Message;
+    /// Path of the endpoint.
+    const PATH: &'static str;
+    /// Key of the requests.
+    const REQ_KEY: Key;
+    /// Key of the responses.
+    const RESP_KEY: Key;
+}
+
+/// A topic for unsolicited messages, usually defined with [`topic!`](crate::topic).
+pub trait Topic {
+    /// Message published on the topic.
+    type Message: Message;
+    /// Path of the topic.
+    const PATH: &'static str;
+    /// Key of the messages.
+    const TOPIC_KEY: Key;
+}
+
+/// Define a unit struct implementing [`Endpoint`](crate::rpc::Endpoint), from its name, request
+/// type, response type and path.
+///
+/// See the [`rpc`](crate::rpc) module for an example.
+#[macro_export]
+macro_rules! endpoint {
+    ($(#[$meta:meta])* $vis:vis $name:ident, $req:ty, $resp:ty, $path:expr $(,)?) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $crate::rpc::Endpoint for $name {
+            type Request = $req;
+            type Response = $resp;
+            const PATH: &'static str = $path;
+            const REQ_KEY: $crate::rpc::Key = $crate::rpc::Key::for_path::<$req>($path);
+            const RESP_KEY: $crate::rpc::Key = $crate::rpc::Key::for_path::<$resp>($path);
+        }
+    };
+}
+
+/// Define a unit struct implementing [`Topic`](crate::rpc::Topic), from its name, message type
+/// and path.
+///
+/// ```
+/// use noproto::rpc::Topic;
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Log {
+///     #[noproto(tag = 1)]
+///     line: heapless::String<64>,
+/// }
+///
+/// noproto::topic!(LogTopic, Log, "logs");
+/// assert_eq!(LogTopic::PATH, "logs");
+/// ```
+#[macro_export]
+macro_rules! topic {
+    ($(#[$meta:meta])* $vis:vis $name:ident, $msg:ty, $path:expr $(,)?) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl $crate::rpc::Topic for $name {
+            type Message = $msg;
+            const PATH: &'static str = $path;
+            const TOPIC_KEY: $crate::rpc::Key = $crate::rpc::Key::for_path::<$msg>($path);
+        }
+    };
+}
+
+/// Header of a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Header {
+    /// Key of the message.
+    pub key: Key,
+    /// Sequence number, echoed in responses.
+    pub seq: u32,
+}
+
+impl Header {
+    /// Decode the header of a frame, returning it with the encoded message.
+    pub fn peek(buf: &[u8]) -> Result<(Header, &[u8]), ReadError> {
+        let mut r = ByteReader::new(buf);
+        let key = Key(r.read()?);
+        let seq = r.read_varuint32()?;
+        Ok((Header { key, seq }, r.read_to_end()?))
+    }
+}
+
+/// Write a frame holding `msg` with the given key and sequence number, returning its length.
+pub fn write_frame<M: Message>(key: Key, seq: u32, msg: &M, buf: &mut [u8]) -> Result<usize, WriteError> {
+    let mut w = ByteWriter::new(buf);
+    w.write(&key.0)?;
+    w.write_varuint32(seq)?;
+    msg.write_raw(&mut w)?;
+    Ok(w.pos())
+}
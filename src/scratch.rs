@@ -0,0 +1,142 @@
+//! Caller-supplied arena for large transient `bytes` fields.
+//!
+//! Sizing a `heapless::Vec` field for the rare worst case wastes RAM in every instance of the
+//! message. A [`ScratchBytes`] field instead copies its data into a [`Scratch`] arena borrowed
+//! for the duration of the decode, so the arena can be shared by all such fields and reused
+//! once the message is inspected and dropped.
+//!
+//! Since the field must know its arena before decoding, the message is built first and decoded
+//! with [`merge`](crate::merge). A [`ScratchBytes`] without an arena fails to decode.
+//!
+//! ```
+//! use noproto::scratch::{Scratch, ScratchBytes};
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Upload<'s, 'a> {
+//!     #[noproto(tag = 1)]
+//!     name: heapless::String<16>,
+//!     #[noproto(tag = 2)]
+//!     blob: ScratchBytes<'s, 'a>,
+//! }
+//!
+//! let mut buf = [0; 64];
+//! let blob = [0xAA; 40];
+//! let msg = Upload { name: "fw".try_into().unwrap(), blob: ScratchBytes::from_slice(&blob) };
+//! let n = noproto::write(&msg, &mut buf).unwrap();
+//!
+//! let mut arena = [0; 48];
+//! let scratch = Scratch::new(&mut arena);
+//! let mut msg = Upload { blob: ScratchBytes::new(&scratch), ..Default::default() };
+//! noproto::merge(&buf[..n], &mut msg).unwrap();
+//! assert_eq!(msg.blob.data(), &blob[..]);
+//! assert_eq!(scratch.remaining(), 8);
+//! ```
+
+use core::cell::Cell;
+use core::ops::Deref;
+
+use crate::descriptor::Type;
+use crate::encoding::{ByteReader, ByteWriter, ReverseWriter};
+use crate::{Message, ReadError, WireType, WriteError};
+
+/// Bump arena handing out disjoint parts of a caller-supplied buffer.
+///
+/// Allocations live as long as the buffer borrow and are never freed individually. To reuse
+/// the buffer, drop everything borrowing from the arena and create a new one.
+pub struct Scratch<'a> {
+    free: Cell<&'a mut [u8]>,
+}
+
+impl<'a> Scratch<'a> {
+    /// Create a new [`Scratch`] arena allocating from `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { free: Cell::new(buf) }
+    }
+
+    /// Get the number of bytes still available.
+    pub fn remaining(&self) -> usize {
+        let free = self.free.take();
+        let len = free.len();
+        self.free.set(free);
+        len
+    }
+
+    /// Allocate `len` bytes, or return `None` if the arena is exhausted.
+    pub fn alloc(&self, len: usize) -> Option<&'a mut [u8]> {
+        let free = self.free.take();
+        if len > free.len() {
+            self.free.set(free);
+            return None;
+        }
+        let (data, free) = free.split_at_mut(len);
+        self.free.set(free);
+        Some(data)
+    }
+}
+
+/// `bytes` field decoded into a [`Scratch`] arena.
+///
+/// A field created with [`new`](Self::new) copies its data into the arena when decoded, a
+/// field created with [`from_slice`](Self::from_slice) or [`Default`] has no arena and can
+/// only be encoded. Decoding a field again allocates again, the previous data is not reclaimed.
+#[derive(Clone, Copy, Default)]
+pub struct ScratchBytes<'s, 'a> {
+    scratch: Option<&'s Scratch<'a>>,
+    data: &'a [u8],
+}
+
+impl<'s, 'a> ScratchBytes<'s, 'a> {
+    /// Create an empty field decoding into `scratch`.
+    pub fn new(scratch: &'s Scratch<'a>) -> Self {
+        Self {
+            scratch: Some(scratch),
+            data: &[],
+        }
+    }
+
+    /// Create a field holding `data`, for encoding.
+    pub fn from_slice(data: &'a [u8]) -> Self {
+        Self { scratch: None, data }
+    }
+
+    /// Get the field data.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl Deref for ScratchBytes<'_, '_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl core::fmt::Debug for ScratchBytes<'_, '_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+impl Message for ScratchBytes<'_, '_> {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const TYPE: Type = Type::Bytes;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write(self.data)
+    }
+    fn write_reverse(&self, w: &mut ReverseWriter) -> Result<(), WriteError> {
+        w.write(self.data)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        let data = r.read_to_end()?;
+        let buf = self
+            .scratch
+            .ok_or(ReadError::CapacityExceeded)?
+            .alloc(data.len())
+            .ok_or(ReadError::CapacityExceeded)?;
+        buf.copy_from_slice(data);
+        self.data = buf;
+        Ok(())
+    }
+}
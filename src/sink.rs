@@ -0,0 +1,63 @@
+//! Global sink for the sizes of encoded messages.
+//!
+//! Once a [`SizeSink`] is installed with [`set_size_sink`], it's called after each message
+//! encoded through the top-level functions ([`write`](crate::write),
+//! [`write_scatter`](crate::write_scatter), [`write_fields`](crate::write_fields), ...), so
+//! firmware can build size histograms for capacity planning without wrapping every call.
+//!
+//! Requires atomic compare-and-swap support on the target.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Receiver of encoded message sizes.
+pub trait SizeSink: Sync {
+    /// Called with the Rust type name of a message and its encoded size in bytes.
+    fn record(&self, type_name: &'static str, size: usize);
+}
+
+/// Error returned by [`set_size_sink`] if a sink was already installed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SetSinkError;
+
+impl core::fmt::Display for SetSinkError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("size sink already set")
+    }
+}
+
+impl core::error::Error for SetSinkError {}
+
+const UNSET: u8 = 0;
+const SETTING: u8 = 1;
+const SET: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(UNSET);
+static SINK: SinkCell = SinkCell(UnsafeCell::new(None));
+
+struct SinkCell(UnsafeCell<Option<&'static dyn SizeSink>>);
+
+// SAFETY: the cell is only written once, before `STATE` is set to `SET`, and only read after.
+unsafe impl Sync for SinkCell {}
+
+/// Install the global size sink. Can only be done once.
+pub fn set_size_sink(sink: &'static dyn SizeSink) -> Result<(), SetSinkError> {
+    STATE
+        .compare_exchange(UNSET, SETTING, Ordering::Acquire, Ordering::Relaxed)
+        .map_err(|_| SetSinkError)?;
+    // SAFETY: the state transition above gives us exclusive access.
+    unsafe { *SINK.0.get() = Some(sink) };
+    STATE.store(SET, Ordering::Release);
+    Ok(())
+}
+
+/// Report the encoded size of a message of type `M` to the sink, if any.
+pub(crate) fn record<M: ?Sized>(size: usize) {
+    if STATE.load(Ordering::Acquire) != SET {
+        return;
+    }
+    // SAFETY: the sink is never written again once `STATE` is `SET`.
+    if let Some(sink) = unsafe { *SINK.0.get() } {
+        sink.record(core::any::type_name::<M>(), size);
+    }
+}
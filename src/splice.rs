@@ -0,0 +1,177 @@
+//! Editing top-level fields of an encoded message in place.
+//!
+//! The message is kept encoded in `buf[..len]`, with room to grow in the rest of `buf`. Fields
+//! are found by tag and the bytes after them are shifted, without decoding the other fields.
+//! This lets a gateway stamp a field into a forwarded message cheaply, even one it has no
+//! schema for:
+//!
+//! ```
+//! use noproto::splice::replace_field;
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Packet {
+//!     #[noproto(tag = 1)]
+//!     hop: u32,
+//!     #[noproto(tag = 2)]
+//!     payload: heapless::Vec<u8, 32>,
+//! }
+//!
+//! let mut buf = [0; 64];
+//! let packet = Packet { hop: 1, payload: heapless::Vec::from_slice(b"data").unwrap() };
+//! let len = noproto::write(&packet, &mut buf).unwrap();
+//!
+//! // Increment the hop count.
+//! let len = replace_field(&mut buf, len, 1, &200u32).unwrap();
+//! let packet: Packet = noproto::read(&buf[..len]).unwrap();
+//! assert_eq!(packet.hop, 200);
+//! assert_eq!(packet.payload, *b"data");
+//! ```
+//!
+//! On error, `buf` is left unchanged.
+
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WriteError};
+
+/// Error returned when splicing a field.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SpliceError {
+    /// The encoded message is invalid.
+    Read(ReadError),
+    /// The buffer is too small for the new field.
+    Write(WriteError),
+}
+
+impl core::fmt::Display for SpliceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SpliceError::Read(e) => e.fmt(f),
+            SpliceError::Write(e) => e.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for SpliceError {}
+
+impl From<ReadError> for SpliceError {
+    fn from(e: ReadError) -> Self {
+        Self::Read(e)
+    }
+}
+
+impl From<WriteError> for SpliceError {
+    fn from(e: WriteError) -> Self {
+        Self::Write(e)
+    }
+}
+
+/// Remove every occurrence of field `tag` from the message in `buf[..len]`, returning the new
+/// length.
+///
+/// All the elements of a repeated field are removed.
+pub fn remove_field(buf: &mut [u8], len: usize, tag: u32) -> Result<usize, ReadError> {
+    let found = find(buf, len, tag)?;
+    compact(buf, len, tag)?;
+    Ok(len - found.removed)
+}
+
+/// Replace field `tag` of the message in `buf[..len]` with `value`, returning the new length.
+///
+/// Every occurrence of the field is removed, and the new one is written where the first one
+/// was, or at the end if there was none.
+pub fn replace_field<M: Message>(buf: &mut [u8], len: usize, tag: u32, value: &M) -> Result<usize, SpliceError> {
+    let found = find(buf, len, tag)?;
+    let field_len = field_len(tag, value)?;
+    let new_len = (len - found.removed).checked_add(field_len).ok_or(WriteError)?;
+    if new_len > buf.len() {
+        return Err(WriteError.into());
+    }
+    compact(buf, len, tag)?;
+    // Nothing before the first occurrence moved.
+    let pos = found.first.unwrap_or(len - found.removed);
+    insert(buf, len - found.removed, pos, tag, value, field_len)?;
+    Ok(new_len)
+}
+
+/// Append field `tag` with `value` to the message in `buf[..len]`, returning the new length.
+///
+/// This adds an element to a repeated field. For other fields, decoders keep the last value
+/// (or merge messages), so this overrides any earlier occurrence without removing it.
+pub fn append_field<M: Message>(buf: &mut [u8], len: usize, tag: u32, value: &M) -> Result<usize, SpliceError> {
+    find(buf, len, tag)?;
+    let field_len = field_len(tag, value)?;
+    let new_len = len.checked_add(field_len).ok_or(WriteError)?;
+    if new_len > buf.len() {
+        return Err(WriteError.into());
+    }
+    insert(buf, len, len, tag, value, field_len)?;
+    Ok(new_len)
+}
+
+/// Occurrences of a field in an encoded message.
+struct Found {
+    /// Offset of the first occurrence.
+    first: Option<usize>,
+    /// Total length of the occurrences.
+    removed: usize,
+}
+
+/// Check that `buf[..len]` is a valid sequence of fields, and find field `tag` in it.
+fn find(buf: &[u8], len: usize, tag: u32) -> Result<Found, ReadError> {
+    let data = buf.get(..len).ok_or(ReadError::InvalidLength)?;
+    let mut found = Found { first: None, removed: 0 };
+    let mut fields = ByteReader::new(data).fields();
+    loop {
+        let start = len - fields.remaining().len();
+        let Some(field) = fields.next() else { break };
+        if field?.tag() == tag {
+            found.first.get_or_insert(start);
+            found.removed += len - fields.remaining().len() - start;
+        }
+    }
+    Ok(found)
+}
+
+/// Remove field `tag` from `buf[..len]`, which must have been checked by [`find`].
+fn compact(buf: &mut [u8], len: usize, tag: u32) -> Result<(), ReadError> {
+    let mut read = 0;
+    let mut write = 0;
+    while read < len {
+        let data = buf.get(read..len).ok_or(ReadError::InvalidLength)?;
+        let mut fields = ByteReader::new(data).fields();
+        let field_tag = fields.next().ok_or(ReadError::UnexpectedEof)??.tag();
+        let end = len - fields.remaining().len();
+        if field_tag != tag {
+            buf.copy_within(read..end, write);
+            write += end - read;
+        }
+        read = end;
+    }
+    Ok(())
+}
+
+/// Encoded length of field `tag` holding `value`, with its header.
+fn field_len<M: Message>(tag: u32, value: &M) -> Result<usize, WriteError> {
+    let mut w = ByteWriter::counting();
+    w.write_field(tag, value)?;
+    Ok(w.pos())
+}
+
+/// Insert field `tag` holding `value`, of length `field_len`, at `pos` in `buf[..len]`. The
+/// caller checked that it fits.
+fn insert<M: Message>(
+    buf: &mut [u8],
+    len: usize,
+    pos: usize,
+    tag: u32,
+    value: &M,
+    field_len: usize,
+) -> Result<(), WriteError> {
+    buf.copy_within(pos..len, pos + field_len);
+    let mut w = ByteWriter::new(buf.get_mut(pos..pos + field_len).ok_or(WriteError)?);
+    w.write_field(tag, value)?;
+    if w.pos() != field_len {
+        return Err(WriteError);
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Integration with [`std::io`] readers and writers, for host-side tooling and tests.
+//!
+//! The delimited format is a varint length prefix followed by the message, compatible with
+//! Java's `writeDelimitedTo` and `parseDelimitedFrom`.
+//!
+//! ```
+//! use noproto::std_io::{read_delimited_from, write_delimited_to};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Ping {
+//!     #[noproto(tag = 1)]
+//!     seq: u32,
+//! }
+//!
+//! let mut file = Vec::new();
+//! write_delimited_to(&Ping { seq: 1 }, &mut file).unwrap();
+//! write_delimited_to(&Ping { seq: 2 }, &mut file).unwrap();
+//!
+//! let mut r = &file[..];
+//! assert_eq!(read_delimited_from::<Ping>(&mut r).unwrap(), Some(Ping { seq: 1 }));
+//! assert_eq!(read_delimited_from::<Ping>(&mut r).unwrap(), Some(Ping { seq: 2 }));
+//! assert_eq!(read_delimited_from::<Ping>(&mut r).unwrap(), None);
+//! ```
+
+use core::marker::PhantomData;
+use std::io::{self, Read, Write};
+use std::vec::Vec;
+
+use crate::read::ByteReader;
+use crate::write::{ByteWriter, MAX_VARUINT64_LEN};
+use crate::{Message, ReadError};
+
+fn invalid_data(e: ReadError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Encode a message and write it to `w`.
+pub fn write_to<M: Message>(msg: &M, w: &mut impl Write) -> io::Result<()> {
+    let mut buf = Vec::new();
+    crate::write_to(msg, &mut buf).map_err(io::Error::other)?;
+    w.write_all(&buf)
+}
+
+/// Encode a message prefixed by its varint length and write it to `w`.
+pub fn write_delimited_to<M: Message>(msg: &M, w: &mut impl Write) -> io::Result<()> {
+    let len = msg.encoded_len().map_err(io::Error::other)?;
+    let len = u32::try_from(len).map_err(io::Error::other)?;
+    let mut buf = Vec::new();
+    let mut bw = ByteWriter::with_output(&mut buf);
+    bw.write_varuint32(len).map_err(io::Error::other)?;
+    msg.write_raw(&mut bw).map_err(io::Error::other)?;
+    w.write_all(&buf)
+}
+
+/// Read all of `r` and decode it as a message.
+pub fn read_from<M: Message + Default>(r: &mut impl Read) -> io::Result<M> {
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf)?;
+    crate::read(&buf).map_err(invalid_data)
+}
+
+/// Read a message prefixed by its varint length from `r`, returning `None` on end of file
+/// before the prefix.
+///
+/// Only the prefix and the message are read, so this can be called repeatedly on a stream.
+pub fn read_delimited_from<M: Message + Default>(r: &mut impl Read) -> io::Result<Option<M>> {
+    let mut prefix = [0; MAX_VARUINT64_LEN];
+    let mut n = 0;
+    loop {
+        let mut b = [0];
+        if r.read(&mut b)? == 0 {
+            if n == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        *prefix
+            .get_mut(n)
+            .ok_or_else(|| invalid_data(ReadError::InvalidVarint))? = b[0];
+        n += 1;
+        if b[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let len = ByteReader::new(&prefix[..n]).read_length().map_err(invalid_data)?;
+
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    crate::read(&buf).map(Some).map_err(invalid_data)
+}
+
+/// Reads a stream of varint length-delimited messages from a reader.
+pub struct MessageStream<R, M> {
+    r: R,
+    _phantom: PhantomData<M>,
+}
+
+impl<R: Read, M: Message + Default> MessageStream<R, M> {
+    /// Create a new [`MessageStream`] reading from `r`.
+    pub fn new(r: R) -> Self {
+        Self {
+            r,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Get back the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+}
+
+impl<R: Read, M: Message + Default> Iterator for MessageStream<R, M> {
+    type Item = io::Result<M>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_delimited_from(&mut self.r).transpose()
+    }
+}
@@ -0,0 +1,395 @@
+//! Helpers for testing the wire format of messages.
+
+use core::fmt;
+
+use crate::descriptor::{descriptor, FieldDescriptor, Label, MessageDescriptor, Type};
+use crate::read::{ByteReader, END_GROUP};
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WireType, WriteError};
+
+/// Size of the scratch buffers used by [`assert_encodes_to`].
+pub const MAX_GOLDEN_LEN: usize = 1024;
+
+/// Maximum number of distinct top-level tags tracked by [`WireStats`].
+pub const MAX_STATS_TAGS: usize = 64;
+
+/// Maximum length of strings and bytes, and count of repeated fields, generated by
+/// [`random_message`] for fields without a bound.
+pub const MAX_RANDOM_UNBOUNDED: usize = 16;
+
+/// Assert that a message encodes to the given bytes.
+///
+/// The expected encoding can be a hex string (whitespace is ignored) or a byte slice.
+///
+/// ```
+/// noproto::assert_encodes_to!(150u32, "9601");
+/// noproto::assert_encodes_to!(150u32, b"\x96\x01");
+/// ```
+#[macro_export]
+macro_rules! assert_encodes_to {
+    ($msg:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_encodes_to(&$msg, $expected)
+    };
+}
+
+/// Expected encoding passed to [`assert_encodes_to`].
+pub trait Golden {
+    /// Decode into `buf`, returning the number of bytes, or `None` if invalid or too long.
+    fn decode(&self, buf: &mut [u8]) -> Option<usize>;
+}
+
+impl Golden for &str {
+    fn decode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut n = 0;
+        let mut high = None;
+        for c in self.chars().filter(|c| !c.is_whitespace()) {
+            let nibble = c.to_digit(16)? as u8;
+            match high.take() {
+                None => high = Some(nibble),
+                Some(h) => {
+                    *buf.get_mut(n)? = h << 4 | nibble;
+                    n += 1;
+                }
+            }
+        }
+        match high {
+            None => Some(n),
+            Some(_) => None,
+        }
+    }
+}
+
+impl Golden for &[u8] {
+    fn decode(&self, buf: &mut [u8]) -> Option<usize> {
+        buf.get_mut(..self.len())?.copy_from_slice(self);
+        Some(self.len())
+    }
+}
+
+impl<const N: usize> Golden for &[u8; N] {
+    fn decode(&self, buf: &mut [u8]) -> Option<usize> {
+        (&self[..]).decode(buf)
+    }
+}
+
+/// Assert that `msg` encodes to `expected`, panicking with a field-by-field dump if it doesn't.
+///
+/// Usually called through the [`assert_encodes_to!`](crate::assert_encodes_to) macro.
+#[track_caller]
+pub fn assert_encodes_to<M: Message>(msg: &M, expected: impl Golden) {
+    let mut expected_buf = [0; MAX_GOLDEN_LEN];
+    let Some(expected_len) = expected.decode(&mut expected_buf) else {
+        panic!("invalid expected encoding (bad hex, or longer than {MAX_GOLDEN_LEN} bytes)");
+    };
+    let expected = &expected_buf[..expected_len];
+
+    let mut actual_buf = [0; MAX_GOLDEN_LEN];
+    let actual = match crate::write(msg, &mut actual_buf) {
+        Ok(n) => &actual_buf[..n],
+        Err(e) => panic!("failed to encode message: {e:?}"),
+    };
+
+    if actual != expected {
+        let offset = actual.iter().zip(expected).take_while(|(a, e)| a == e).count();
+        panic!(
+            "message encoding mismatch at byte {offset}\n  expected: {}\n    actual: {}\nexpected fields:\n{}actual fields:\n{}",
+            Hex(expected),
+            Hex(actual),
+            Fields(expected),
+            Fields(actual),
+        );
+    }
+}
+
+/// Formats bytes as lowercase hex.
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the top-level fields of an encoded message, one per line.
+pub struct Fields<'a>(pub &'a [u8]);
+
+impl fmt::Display for Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields = ByteReader::new(self.0).fields();
+        loop {
+            let remaining = fields.remaining();
+            let offset = self.0.len() - remaining.len();
+            match fields.next() {
+                None => return Ok(()),
+                Some(Ok(field)) => writeln!(
+                    f,
+                    "  @{offset}: tag {} {:?} {}",
+                    field.tag(),
+                    field.wire_type(),
+                    Hex(field.data())
+                )?,
+                Some(Err(_)) => return writeln!(f, "  @{offset}: malformed: {}", Hex(remaining)),
+            }
+        }
+    }
+}
+
+/// Structured summary of an encoded message, returned by [`wire_stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WireStats {
+    /// Number of top-level fields, per tag.
+    pub fields: heapless::LinearMap<u32, usize, MAX_STATS_TAGS>,
+    /// Total encoded size in bytes.
+    pub total_size: usize,
+    /// Maximum nesting depth. A message with no submessages has depth 1.
+    pub max_depth: usize,
+}
+
+impl WireStats {
+    /// Number of top-level fields with the given tag.
+    ///
+    /// A repeated field encoded unpacked counts once per element, packed counts once.
+    pub fn count(&self, tag: u32) -> usize {
+        self.fields.get(&tag).copied().unwrap_or(0)
+    }
+
+    /// Total number of top-level fields.
+    pub fn field_count(&self) -> usize {
+        self.fields.values().sum()
+    }
+}
+
+/// Decode `buf` without a schema and summarize its wire layout, for asserting on in tests.
+///
+/// Without a schema, strings and bytes can't be told apart from submessages, so any non-empty
+/// length-delimited field that parses as a valid message counts as nesting for `max_depth`.
+pub fn wire_stats(buf: &[u8]) -> Result<WireStats, ReadError> {
+    let mut stats = WireStats {
+        total_size: buf.len(),
+        max_depth: 1,
+        ..Default::default()
+    };
+    for field in ByteReader::new(buf).fields() {
+        let field = field?;
+        match stats.fields.get_mut(&field.tag()) {
+            Some(count) => *count += 1,
+            None => {
+                stats
+                    .fields
+                    .insert(field.tag(), 1)
+                    .map_err(|_| ReadError::CapacityExceeded)?;
+            }
+        }
+        if matches!(field.wire_type(), WireType::LengthDelimited | WireType::StartGroup) {
+            stats.max_depth = stats.max_depth.max(1 + nested_depth(field.data()));
+        }
+    }
+    Ok(stats)
+}
+
+/// Nesting depth of `data` if it looks like a message, 0 otherwise.
+fn nested_depth(data: &[u8]) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+    let mut depth = 1;
+    for field in ByteReader::new(data).fields() {
+        let Ok(field) = field else { return 0 };
+        if matches!(field.wire_type(), WireType::LengthDelimited | WireType::StartGroup) {
+            depth = depth.max(1 + nested_depth(field.data()));
+        }
+    }
+    depth
+}
+
+/// Small deterministic pseudo-random number generator (SplitMix64), used by [`random_message`].
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// Create a generator from a seed. The same seed always gives the same sequence.
+    pub const fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Get the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Get a random number in `0..=max`.
+    fn up_to(&mut self, max: usize) -> usize {
+        match (max as u64).checked_add(1) {
+            Some(n) => (self.next_u64() % n) as usize,
+            None => self.next_u64() as usize,
+        }
+    }
+}
+
+/// Error returned by [`random_message`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RandomError {
+    /// `buf` is too small for the generated encoding.
+    Write(WriteError),
+    /// The generated encoding doesn't decode as the message, e.g. because a required field
+    /// without a description was left out.
+    Read(ReadError),
+}
+
+impl fmt::Display for RandomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RandomError::Write(e) => e.fmt(f),
+            RandomError::Read(e) => e.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for RandomError {}
+
+impl From<WriteError> for RandomError {
+    fn from(e: WriteError) -> Self {
+        Self::Write(e)
+    }
+}
+
+impl From<ReadError> for RandomError {
+    fn from(e: ReadError) -> Self {
+        Self::Read(e)
+    }
+}
+
+/// Generate a random valid instance of `M` within its bounds, using `buf` as scratch space for
+/// its encoding.
+///
+/// Values are generated from the message descriptor, so fields without a description (and
+/// messages without a descriptor) are left at their default, as are compressed fields. If that
+/// makes the encoding invalid, e.g. for a required field encoded `with` a custom module,
+/// [`RandomError::Read`] is returned.
+///
+/// ```
+/// use noproto::testing::{random_message, Rng};
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Sample {
+///     #[noproto(tag = 1)]
+///     channel: u8,
+///     #[noproto(tag = 2, repeated)]
+///     values: heapless::Vec<i32, 4>,
+/// }
+///
+/// let mut rng = Rng::new(42);
+/// let mut buf = [0; 64];
+/// let sample: Sample = random_message(&mut rng, &mut buf).unwrap();
+/// assert!(sample.values.len() <= 4);
+/// ```
+pub fn random_message<M: Message + Default>(rng: &mut Rng, buf: &mut [u8]) -> Result<M, RandomError> {
+    let Some(desc) = descriptor::<M>() else {
+        return Ok(M::default());
+    };
+    let n = random_encoded(desc, rng, buf)?;
+    Ok(crate::read(&buf[..n])?)
+}
+
+/// Encode a random valid instance of the message described by `desc` into `buf`, returning
+/// the number of bytes written.
+pub fn random_encoded(desc: &MessageDescriptor, rng: &mut Rng, buf: &mut [u8]) -> Result<usize, WriteError> {
+    let mut w = ByteWriter::new(buf);
+    write_random_message(desc, rng, &mut w)?;
+    Ok(w.pos())
+}
+
+fn write_random_message(desc: &MessageDescriptor, rng: &mut Rng, w: &mut ByteWriter) -> Result<(), WriteError> {
+    for field in desc.fields {
+        let count = match field.label {
+            Label::Single => 1,
+            Label::Optional => rng.up_to(1),
+            Label::Repeated => rng.up_to(field.max_count.min(MAX_RANDOM_UNBOUNDED)),
+        };
+        for _ in 0..count {
+            write_random_field(field, rng, w)?;
+        }
+    }
+    for oneof in desc.oneofs {
+        // Index `len` leaves the oneof empty.
+        if let Some(field) = oneof.fields.get(rng.up_to(oneof.fields.len())) {
+            write_random_field(field, rng, w)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_random_field(field: &FieldDescriptor, rng: &mut Rng, w: &mut ByteWriter) -> Result<(), WriteError> {
+    let Some(wire_type) = field.wire_type() else {
+        return Ok(());
+    };
+    if field.compressed {
+        // Random bytes are not a valid compressed payload.
+        return Ok(());
+    }
+    w.write_varuint32((field.tag << 3) | wire_type as u32)?;
+
+    // Narrow Rust types such as `u8` are described as 32-bit protobuf types, their encoded
+    // length bound tells their actual width.
+    let len_bits = match field.max_len {
+        0..=2 => 8,
+        3 => 16,
+        4 | 5 => 32,
+        _ => 64,
+    };
+    let unsigned = |rng: &mut Rng, bits: u32| rng.next_u64() >> (64 - bits.min(len_bits));
+    let signed = |rng: &mut Rng, bits: u32| (rng.next_u64() as i64) >> (64 - bits.min(len_bits));
+
+    match field.ty {
+        Type::Bool => w.write_varuint32(rng.up_to(1) as u32),
+        Type::Uint32 => w.write_varuint32(unsigned(rng, 32) as u32),
+        Type::Uint64 => w.write_varuint64(unsigned(rng, 64)),
+        Type::Int32 => w.write_varuint64(signed(rng, 32) as u64),
+        Type::Int64 => w.write_varuint64(signed(rng, 64) as u64),
+        Type::Sint32 => w.write_varint32(signed(rng, 32) as i32),
+        Type::Sint64 => w.write_varint64(signed(rng, 64)),
+        Type::Fixed32 | Type::Sfixed32 => w.write_u32(rng.next_u64() as u32),
+        Type::Fixed64 | Type::Sfixed64 => w.write_u64(rng.next_u64()),
+        // Only finite values, so generated messages compare equal to themselves.
+        Type::Float => w.write_u32((rng.next_u64() as i32 as f32 / 65536.0).to_bits()),
+        Type::Double => w.write_u64((rng.next_u64() as i64 as f64 / 4294967296.0).to_bits()),
+        Type::String => {
+            let len = rng.up_to(field.max_len.min(MAX_RANDOM_UNBOUNDED));
+            w.write_length_delimited(|w| {
+                for _ in 0..len {
+                    w.write_u8(b' ' + rng.up_to(94) as u8)?;
+                }
+                Ok(())
+            })
+        }
+        Type::Bytes | Type::FixedBytes(_) => {
+            let len = match field.ty {
+                Type::FixedBytes(len) => len,
+                _ => rng.up_to(field.max_len.min(MAX_RANDOM_UNBOUNDED)),
+            };
+            w.write_length_delimited(|w| {
+                for _ in 0..len {
+                    w.write_u8(rng.next_u64() as u8)?;
+                }
+                Ok(())
+            })
+        }
+        Type::Enum(e) => {
+            let value = e.values.get(rng.up_to(e.values.len().saturating_sub(1)));
+            w.write_varuint64(value.map_or(0, |&(_, v)| v) as i64 as u64)
+        }
+        Type::Message(m) if field.group => {
+            write_random_message(m, rng, w)?;
+            w.write_varuint32((field.tag << 3) | END_GROUP)
+        }
+        Type::Message(m) => w.write_length_delimited(|w| write_random_message(m, rng, w)),
+        Type::Unknown => unreachable!(),
+    }
+}
@@ -0,0 +1,175 @@
+//! Several messages committed together in one buffer.
+//!
+//! A [`TransactionWriter`] encodes messages one after another, then appends a table of their
+//! offsets and lengths and a trailer. The buffer can then go out in a single flash write or
+//! radio frame. Since the trailer is written last, a buffer cut short by a reset or a lost
+//! fragment has no valid trailer and [`Transaction::parse`] rejects it as a whole.
+//!
+//! The layout is, all integers little endian:
+//!
+//! ```text
+//! messages: [u8]
+//! table:    (offset:u32 len:u32) for each message
+//! trailer:  count:u32 b"NPTX"
+//! ```
+//!
+//! ```
+//! use noproto::transaction::{Transaction, TransactionWriter};
+//!
+//! #[derive(noproto::Message, Default, PartialEq, Debug)]
+//! struct Setting {
+//!     #[noproto(tag = 1)]
+//!     key: u32,
+//!     #[noproto(tag = 2)]
+//!     value: i32,
+//! }
+//!
+//! let mut buf = [0; 64];
+//! let mut w = TransactionWriter::<4>::new(&mut buf);
+//! w.push(&Setting { key: 1, value: -1 }).unwrap();
+//! w.push(&Setting { key: 2, value: 10 }).unwrap();
+//! let frame = w.finish().unwrap();
+//!
+//! let tx = Transaction::parse(frame).unwrap();
+//! assert_eq!(tx.len(), 2);
+//! assert_eq!(tx.read::<Setting>(1).unwrap(), Setting { key: 2, value: 10 });
+//! ```
+
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WriteError};
+
+const MAGIC: &[u8; 4] = b"NPTX";
+const ENTRY_LEN: usize = 8;
+const TRAILER_LEN: usize = 8;
+
+/// Writes several messages into one buffer, followed by a table locating them.
+///
+/// Up to `N` messages can be written.
+pub struct TransactionWriter<'a, const N: usize> {
+    buf: &'a mut [u8],
+    pos: usize,
+    entries: heapless::Vec<(u32, u32), N>,
+}
+
+impl<'a, const N: usize> TransactionWriter<'a, N> {
+    /// Create a new [`TransactionWriter`] that writes to `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            entries: heapless::Vec::new(),
+        }
+    }
+
+    /// Get the number of messages written so far.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no message was written yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encode a message, returning its index in the transaction.
+    ///
+    /// Fails if the buffer is too small to also hold the table, or `N` messages were already
+    /// written. A failed message is not part of the transaction.
+    pub fn push<M: Message>(&mut self, msg: &M) -> Result<usize, WriteError> {
+        if self.entries.is_full() {
+            return Err(WriteError);
+        }
+        // Leave room for the table, including this message's entry.
+        let table_len = (self.entries.len() + 1) * ENTRY_LEN + TRAILER_LEN;
+        let end = self.buf.len().checked_sub(table_len).ok_or(WriteError)?;
+        let mut w = ByteWriter::new(self.buf.get_mut(self.pos..end).ok_or(WriteError)?);
+        msg.write_raw(&mut w)?;
+        let len = w.pos();
+
+        let offset = u32::try_from(self.pos).map_err(|_| WriteError)?;
+        self.entries.push((offset, len as u32)).map_err(|_| WriteError)?;
+        self.pos += len;
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Append the table and trailer, and return the whole transaction.
+    pub fn finish(self) -> Result<&'a [u8], WriteError> {
+        let mut w = ByteWriter::new(&mut self.buf[self.pos..]);
+        for &(offset, len) in &self.entries {
+            w.write_u32(offset)?;
+            w.write_u32(len)?;
+        }
+        w.write_u32(self.entries.len() as u32)?;
+        w.write(MAGIC)?;
+        let len = self.pos + w.pos();
+        Ok(&self.buf[..len])
+    }
+}
+
+/// A transaction written by [`TransactionWriter`].
+#[derive(Clone, Copy, Debug)]
+pub struct Transaction<'a> {
+    messages: &'a [u8],
+    table: &'a [u8],
+}
+
+impl<'a> Transaction<'a> {
+    /// Parse a transaction, checking its trailer and that every message lies within it.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ReadError> {
+        let trailer_start = data.len().checked_sub(TRAILER_LEN).ok_or(ReadError::UnexpectedEof)?;
+        let (rest, trailer) = data.split_at(trailer_start);
+        if trailer[4..] != MAGIC[..] {
+            return Err(ReadError::InvalidData);
+        }
+        let count = u32::from_le_bytes(trailer[..4].try_into().unwrap()) as usize;
+        let table_len = count.checked_mul(ENTRY_LEN).ok_or(ReadError::InvalidLength)?;
+        let table_start = rest.len().checked_sub(table_len).ok_or(ReadError::InvalidLength)?;
+        let (messages, table) = rest.split_at(table_start);
+
+        let tx = Self { messages, table };
+        for i in 0..count {
+            let (offset, len) = tx.entry(i);
+            if offset.checked_add(len).is_none_or(|end| end > messages.len()) {
+                return Err(ReadError::InvalidLength);
+            }
+        }
+        Ok(tx)
+    }
+
+    /// Get the number of messages.
+    pub fn len(&self) -> usize {
+        self.table.len() / ENTRY_LEN
+    }
+
+    /// Check if the transaction holds no message.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    fn entry(&self, index: usize) -> (usize, usize) {
+        let entry = &self.table[index * ENTRY_LEN..][..ENTRY_LEN];
+        let offset = u32::from_le_bytes(entry[..4].try_into().unwrap());
+        let len = u32::from_le_bytes(entry[4..].try_into().unwrap());
+        (offset as usize, len as usize)
+    }
+
+    /// Get the encoded `index`-th message.
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.len() {
+            return None;
+        }
+        let (offset, len) = self.entry(index);
+        Some(&self.messages[offset..][..len])
+    }
+
+    /// Decode the `index`-th message.
+    pub fn read<M: Message + Default>(&self, index: usize) -> Result<M, ReadError> {
+        crate::read(self.get(index).ok_or(ReadError::InvalidData)?)
+    }
+
+    /// Iterate over the encoded messages.
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        let tx = *self;
+        (0..tx.len()).filter_map(move |i| tx.get(i))
+    }
+}
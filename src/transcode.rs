@@ -0,0 +1,852 @@
+//! Conversion between encoded messages and JSON or text format, driven by descriptors.
+//!
+//! This works for any described message without per-type formatting code, e.g. for debug
+//! endpoints on gateways forwarding arbitrary messages.
+//!
+//! Field names are the Rust field names. 64-bit integers are written as JSON numbers, and
+//! bytes as base64 strings. Fields without a description are skipped, and compressed fields are
+//! written as their compressed payload.
+//!
+//! Numbers are formatted without allocating. Integers are written digit by digit, and floats
+//! with `core::fmt`, or with the smaller and faster [`ryu`](https://docs.rs/ryu) if the `ryu`
+//! feature is enabled.
+
+use core::fmt::{self, Write};
+
+use crate::descriptor::{FieldDescriptor, Label, MessageDescriptor, Type};
+use crate::read::{ByteReader, END_GROUP};
+use crate::write::ByteWriter;
+use crate::{ReadError, WireType, WriteError};
+
+/// Error returned by the transcoder.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TranscodeError {
+    /// The encoded message is invalid.
+    Read(ReadError),
+    /// The output buffer is too small.
+    Write(WriteError),
+    /// The output formatter failed.
+    Fmt,
+    /// The JSON input is invalid, or doesn't match the descriptor.
+    Json,
+}
+
+impl From<ReadError> for TranscodeError {
+    fn from(e: ReadError) -> Self {
+        Self::Read(e)
+    }
+}
+
+impl From<WriteError> for TranscodeError {
+    fn from(e: WriteError) -> Self {
+        Self::Write(e)
+    }
+}
+
+impl From<fmt::Error> for TranscodeError {
+    fn from(_: fmt::Error) -> Self {
+        Self::Fmt
+    }
+}
+
+/// Output options.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Options {
+    /// Leave out fields marked `#[noproto(redact)]`, instead of masking their value.
+    pub omit_redacted: bool,
+}
+
+const REDACTED: &str = "<redacted>";
+
+/// Write the encoded message `data`, described by `desc`, as JSON.
+pub fn to_json(desc: &MessageDescriptor, data: &[u8], opts: Options, w: &mut impl Write) -> Result<(), TranscodeError> {
+    json_message(desc, data, opts, w)
+}
+
+/// Write the encoded message `data`, described by `desc`, in protobuf text format.
+pub fn to_text(desc: &MessageDescriptor, data: &[u8], opts: Options, w: &mut impl Write) -> Result<(), TranscodeError> {
+    text_message(desc, data, opts, 0, w)
+}
+
+/// Encode the JSON object `json` as the message described by `desc`.
+///
+/// Returns the number of bytes written to `buf`.
+pub fn from_json(desc: &MessageDescriptor, json: &str, buf: &mut [u8]) -> Result<usize, TranscodeError> {
+    let mut p = Parser {
+        s: json.as_bytes(),
+        pos: 0,
+    };
+    let mut w = ByteWriter::new(buf);
+    parse_message(&mut p, desc, &mut w)?;
+    p.ws();
+    if p.pos != p.s.len() {
+        return Err(TranscodeError::Json);
+    }
+    Ok(w.pos())
+}
+
+/// Call `f` for each value of `field` in `data`, unpacking packed repeated fields.
+fn for_each_value(
+    field: &FieldDescriptor,
+    data: &[u8],
+    mut f: impl FnMut(&[u8]) -> Result<(), TranscodeError>,
+) -> Result<(), TranscodeError> {
+    let Some(wire_type) = field.wire_type() else {
+        return Ok(());
+    };
+    for r in ByteReader::new(data).fields() {
+        let r = r?;
+        if r.tag() != field.tag {
+            continue;
+        }
+        if r.wire_type() == wire_type {
+            f(r.data())?;
+        } else if r.wire_type() == WireType::LengthDelimited {
+            // Packed repeated scalars.
+            let mut packed = ByteReader::new(r.data());
+            while !packed.eof() {
+                let value = match wire_type {
+                    WireType::Varint => packed.read_varuint_bytes()?,
+                    WireType::ThirtyTwoBit => packed.read_slice(4)?,
+                    WireType::SixtyFourBit => packed.read_slice(8)?,
+                    WireType::LengthDelimited | WireType::StartGroup => return Err(ReadError::InvalidWireType.into()),
+                };
+                f(value)?;
+            }
+        } else {
+            return Err(ReadError::InvalidWireType.into());
+        }
+    }
+    Ok(())
+}
+
+/// Get the last value of `field` in `data`, if any.
+fn last_value<'a>(field: &FieldDescriptor, data: &'a [u8]) -> Result<Option<&'a [u8]>, TranscodeError> {
+    let mut last = None;
+    for r in ByteReader::new(data).fields() {
+        let r = r?;
+        if r.tag() == field.tag && Some(r.wire_type()) == field.wire_type() {
+            last = Some(r.data());
+        }
+    }
+    Ok(last)
+}
+
+/// A decoded scalar value.
+enum Scalar<'a> {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f32),
+    Double(f64),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    Enum(Option<&'static str>, i32),
+}
+
+fn scalar<'a>(ty: &Type, data: &'a [u8]) -> Result<Scalar<'a>, TranscodeError> {
+    let mut r = ByteReader::new(data);
+    Ok(match ty {
+        Type::Bool => Scalar::Bool(r.read_varuint64()? != 0),
+        Type::Uint32 => Scalar::Unsigned(r.read_varuint64()? as u32 as u64),
+        Type::Uint64 => Scalar::Unsigned(r.read_varuint64()?),
+        Type::Int32 => Scalar::Signed(r.read_varuint64()? as i32 as i64),
+        Type::Int64 => Scalar::Signed(r.read_varuint64()? as i64),
+        Type::Sint32 => Scalar::Signed(r.read_varint32()? as i64),
+        Type::Sint64 => Scalar::Signed(r.read_varint64()?),
+        Type::Fixed32 => Scalar::Unsigned(r.read_u32()? as u64),
+        Type::Fixed64 => Scalar::Unsigned(r.read_u64()?),
+        Type::Sfixed32 => Scalar::Signed(r.read_u32()? as i32 as i64),
+        Type::Sfixed64 => Scalar::Signed(r.read_u64()? as i64),
+        Type::Float => Scalar::Float(f32::from_bits(r.read_u32()?)),
+        Type::Double => Scalar::Double(f64::from_bits(r.read_u64()?)),
+        Type::String => Scalar::Str(core::str::from_utf8(data).map_err(|_| ReadError::InvalidUtf8)?),
+        Type::Bytes | Type::FixedBytes(_) => Scalar::Bytes(data),
+        Type::Enum(e) => {
+            let value = r.read_varuint64()? as i32;
+            let name = e.values.iter().find(|(_, v)| *v == value).map(|(n, _)| *n);
+            Scalar::Enum(name, value)
+        }
+        Type::Message(_) | Type::Unknown => unreachable!(),
+    })
+}
+
+/// Copy the encoded message `data`, described by `desc`, leaving out the fields for which `drop`
+/// returns true, e.g. personal data before forwarding to a third party.
+///
+/// `drop` is called with the descriptor of the message holding the field, and the field tag.
+/// Nested messages are filtered recursively, other fields, including those without a
+/// description, are copied as-is without being decoded. Returns the number of bytes written to
+/// `buf`.
+///
+/// ```
+/// use noproto::descriptor::descriptor;
+/// use noproto::transcode::strip;
+///
+/// #[derive(noproto::Message, Default)]
+/// struct User {
+///     #[noproto(tag = 1)]
+///     id: u32,
+///     #[noproto(tag = 2)]
+///     email: heapless::String<32>,
+/// }
+///
+/// let user = User { id: 7, email: "a@example.com".try_into().unwrap() };
+/// let mut data = [0; 64];
+/// let n = noproto::write(&user, &mut data).unwrap();
+///
+/// let mut buf = [0; 64];
+/// let n = strip(descriptor::<User>().unwrap(), &data[..n], &|_, tag| tag == 2, &mut buf).unwrap();
+/// let user: User = noproto::read(&buf[..n]).unwrap();
+/// assert_eq!(user.id, 7);
+/// assert_eq!(user.email, "");
+/// ```
+pub fn strip(
+    desc: &MessageDescriptor,
+    data: &[u8],
+    drop: &impl Fn(&MessageDescriptor, u32) -> bool,
+    buf: &mut [u8],
+) -> Result<usize, TranscodeError> {
+    let mut w = ByteWriter::new(buf);
+    strip_message(desc, data, drop, &mut w)?;
+    Ok(w.pos())
+}
+
+/// Copy the encoded message `data`, described by `desc`, leaving out the fields marked
+/// `#[noproto(redact)]`. See [`strip`].
+pub fn strip_redacted(desc: &MessageDescriptor, data: &[u8], buf: &mut [u8]) -> Result<usize, TranscodeError> {
+    strip(desc, data, &|desc, tag| desc.field(tag).is_some_and(|f| f.redacted), buf)
+}
+
+fn strip_message(
+    desc: &MessageDescriptor,
+    data: &[u8],
+    drop: &impl Fn(&MessageDescriptor, u32) -> bool,
+    w: &mut ByteWriter,
+) -> Result<(), TranscodeError> {
+    let mut fields = ByteReader::new(data).fields();
+    loop {
+        let start = data.len() - fields.remaining().len();
+        let Some(field) = fields.next() else { break };
+        let field = field?;
+        let end = data.len() - fields.remaining().len();
+        if drop(desc, field.tag()) {
+            continue;
+        }
+        let header = (field.tag() << 3) | field.wire_type() as u32;
+        match (desc.field(field.tag()).map(|f| f.ty), field.wire_type()) {
+            (Some(Type::Message(m)), WireType::LengthDelimited) => {
+                w.write_varuint32(header)?;
+                length_delimited(w, |w| strip_message(m, field.data(), drop, w))?;
+            }
+            (Some(Type::Message(m)), WireType::StartGroup) => {
+                w.write_varuint32(header)?;
+                strip_message(m, field.data(), drop, w)?;
+                w.write_varuint32((field.tag() << 3) | END_GROUP)?;
+            }
+            _ => w.write(&data[start..end])?,
+        }
+    }
+    Ok(())
+}
+
+fn json_message(
+    desc: &MessageDescriptor,
+    data: &[u8],
+    opts: Options,
+    w: &mut impl Write,
+) -> Result<(), TranscodeError> {
+    // Validate once, so the per-field scans below can't fail halfway through the output.
+    for r in ByteReader::new(data).fields() {
+        r?;
+    }
+
+    w.write_char('{')?;
+    let mut first = true;
+    for field in desc.all_fields() {
+        if field.ty == Type::Unknown || (field.redacted && opts.omit_redacted) {
+            continue;
+        }
+        let present = match field.label {
+            Label::Repeated => ByteReader::new(data)
+                .fields()
+                .any(|r| r.map(|r| r.tag() == field.tag).unwrap_or(false)),
+            _ => last_value(field, data)?.is_some(),
+        };
+        if !present {
+            continue;
+        }
+        if !first {
+            w.write_char(',')?;
+        }
+        first = false;
+        json_str(field.name, w)?;
+        w.write_char(':')?;
+
+        if field.redacted {
+            json_str(REDACTED, w)?;
+        } else if field.label == Label::Repeated {
+            w.write_char('[')?;
+            let mut first = true;
+            for_each_value(field, data, |value| {
+                if !first {
+                    w.write_char(',')?;
+                }
+                first = false;
+                json_value(&field.ty, value, opts, w)
+            })?;
+            w.write_char(']')?;
+        } else if let Some(value) = last_value(field, data)? {
+            json_value(&field.ty, value, opts, w)?;
+        }
+    }
+    w.write_char('}')?;
+    Ok(())
+}
+
+fn json_value(ty: &Type, data: &[u8], opts: Options, w: &mut impl Write) -> Result<(), TranscodeError> {
+    if let Type::Message(m) = ty {
+        return json_message(m, data, opts, w);
+    }
+    match scalar(ty, data)? {
+        Scalar::Bool(v) => write_bool(v, w)?,
+        Scalar::Unsigned(v) => write_u64(v, w)?,
+        Scalar::Signed(v) => write_i64(v, w)?,
+        Scalar::Float(v) => json_float(v, v as f64, w)?,
+        Scalar::Double(v) => json_float(v, v, w)?,
+        Scalar::Str(v) => json_str(v, w)?,
+        Scalar::Bytes(v) => {
+            w.write_char('"')?;
+            base64_encode(v, w)?;
+            w.write_char('"')?;
+        }
+        Scalar::Enum(Some(name), _) => json_str(name, w)?,
+        Scalar::Enum(None, v) => write_i64(v.into(), w)?,
+    }
+    Ok(())
+}
+
+/// Write `v`, or its special value name if `class` isn't finite.
+fn json_float(v: impl Float, class: f64, w: &mut impl Write) -> fmt::Result {
+    match class {
+        c if c.is_nan() => w.write_str("\"NaN\""),
+        f64::INFINITY => w.write_str("\"Infinity\""),
+        f64::NEG_INFINITY => w.write_str("\"-Infinity\""),
+        _ => write_finite(v, w),
+    }
+}
+
+fn json_str(s: &str, w: &mut impl Write) -> fmt::Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+fn text_message(
+    desc: &MessageDescriptor,
+    data: &[u8],
+    opts: Options,
+    indent: usize,
+    w: &mut impl Write,
+) -> Result<(), TranscodeError> {
+    for r in ByteReader::new(data).fields() {
+        r?;
+    }
+
+    for field in desc.all_fields() {
+        if field.ty == Type::Unknown || (field.redacted && opts.omit_redacted) {
+            continue;
+        }
+        let mut line = |value: Option<&[u8]>| -> Result<(), TranscodeError> {
+            write!(w, "{:indent$}{}", "", field.name, indent = indent)?;
+            match (value, &field.ty) {
+                (None, _) => write!(w, ": \"{}\"", REDACTED)?,
+                (Some(value), Type::Message(m)) => {
+                    w.write_str(" {\n")?;
+                    text_message(m, value, opts, indent + 2, w)?;
+                    write!(w, "{:indent$}}}", "", indent = indent)?;
+                }
+                (Some(value), ty) => {
+                    w.write_str(": ")?;
+                    text_value(ty, value, w)?;
+                }
+            }
+            w.write_char('\n')?;
+            Ok(())
+        };
+
+        match field.label {
+            Label::Repeated => {
+                if field.redacted {
+                    let present = ByteReader::new(data)
+                        .fields()
+                        .any(|r| r.map(|r| r.tag() == field.tag).unwrap_or(false));
+                    if present {
+                        line(None)?;
+                    }
+                } else {
+                    for_each_value(field, data, |value| line(Some(value)))?;
+                }
+            }
+            _ => {
+                if let Some(value) = last_value(field, data)? {
+                    line((!field.redacted).then_some(value))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn text_value(ty: &Type, data: &[u8], w: &mut impl Write) -> Result<(), TranscodeError> {
+    match scalar(ty, data)? {
+        Scalar::Bool(v) => write_bool(v, w)?,
+        Scalar::Unsigned(v) => write_u64(v, w)?,
+        Scalar::Signed(v) => write_i64(v, w)?,
+        Scalar::Float(v) => text_float(v, v as f64, w)?,
+        Scalar::Double(v) => text_float(v, v, w)?,
+        Scalar::Str(v) => text_bytes(v.as_bytes(), w)?,
+        Scalar::Bytes(v) => text_bytes(v, w)?,
+        Scalar::Enum(Some(name), _) => w.write_str(name)?,
+        Scalar::Enum(None, v) => write_i64(v.into(), w)?,
+    }
+    Ok(())
+}
+
+/// Write `v`, or its special value name if `class` isn't finite.
+fn text_float(v: impl Float, class: f64, w: &mut impl Write) -> fmt::Result {
+    match class {
+        c if c.is_nan() => w.write_str("nan"),
+        f64::INFINITY => w.write_str("inf"),
+        f64::NEG_INFINITY => w.write_str("-inf"),
+        _ => write_finite(v, w),
+    }
+}
+
+fn write_bool(v: bool, w: &mut impl Write) -> fmt::Result {
+    w.write_str(if v { "true" } else { "false" })
+}
+
+/// Write `v` in decimal.
+fn write_u64(mut v: u64, w: &mut impl Write) -> fmt::Result {
+    let mut buf = [0; 20];
+    let mut pos = buf.len();
+    loop {
+        pos -= 1;
+        buf[pos] = b'0' + (v % 10) as u8;
+        v /= 10;
+        if v == 0 {
+            break;
+        }
+    }
+    // Only ASCII digits, can't fail.
+    w.write_str(core::str::from_utf8(&buf[pos..]).map_err(|_| fmt::Error)?)
+}
+
+/// Write `v` in decimal.
+fn write_i64(v: i64, w: &mut impl Write) -> fmt::Result {
+    if v < 0 {
+        w.write_char('-')?;
+    }
+    write_u64(v.unsigned_abs(), w)
+}
+
+/// Floating-point numbers, `f32` or `f64`.
+#[cfg(feature = "ryu")]
+trait Float: ryu::Float {}
+
+/// Floating-point numbers, `f32` or `f64`.
+#[cfg(not(feature = "ryu"))]
+trait Float: fmt::Debug {}
+
+impl Float for f32 {}
+impl Float for f64 {}
+
+/// Write the finite number `v`, in exponent notation if it's very large or small.
+#[cfg(feature = "ryu")]
+fn write_finite(v: impl Float, w: &mut impl Write) -> fmt::Result {
+    w.write_str(ryu::Buffer::new().format_finite(v))
+}
+
+/// Write the finite number `v`, in exponent notation if it's very large or small.
+#[cfg(not(feature = "ryu"))]
+fn write_finite(v: impl Float, w: &mut impl Write) -> fmt::Result {
+    // Unlike `Display`, `Debug` switches to exponent notation for very large or small values.
+    write!(w, "{:?}", v)
+}
+
+fn text_bytes(v: &[u8], w: &mut impl Write) -> fmt::Result {
+    w.write_char('"')?;
+    for &b in v {
+        match b {
+            b'"' => w.write_str("\\\"")?,
+            b'\\' => w.write_str("\\\\")?,
+            b'\n' => w.write_str("\\n")?,
+            0x20..=0x7e => w.write_char(b as char)?,
+            _ => write!(w, "\\{:03o}", b)?,
+        }
+    }
+    w.write_char('"')
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8], w: &mut impl Write) -> fmt::Result {
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                w.write_char(BASE64[(n >> (18 - 6 * i)) as usize & 0x3f] as char)?;
+            } else {
+                w.write_char('=')?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Minimal JSON parser, working directly on the input without allocating.
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn ws(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.s.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.ws();
+        self.s.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), TranscodeError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(TranscodeError::Json),
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        self.ws();
+        if self.s[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse a string, returning its contents with escapes still in place.
+    fn string(&mut self) -> Result<&'a [u8], TranscodeError> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        loop {
+            match self.s.get(self.pos) {
+                None => return Err(TranscodeError::Json),
+                Some(b'"') => break,
+                Some(b'\\') => self.pos += 2,
+                Some(_) => self.pos += 1,
+            }
+        }
+        let raw = self.s.get(start..self.pos).ok_or(TranscodeError::Json)?;
+        self.pos += 1;
+        Ok(raw)
+    }
+
+    /// Parse an integer, also accepted as a quoted string.
+    fn integer(&mut self) -> Result<(bool, u64), TranscodeError> {
+        let quoted = self.eat(b'"');
+        self.ws();
+        let negative = self.s.get(self.pos) == Some(&b'-');
+        if negative {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        let mut val: u64 = 0;
+        while let Some(&c @ b'0'..=b'9') = self.s.get(self.pos) {
+            val = val
+                .checked_mul(10)
+                .and_then(|v| v.checked_add((c - b'0') as u64))
+                .ok_or(TranscodeError::Json)?;
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(TranscodeError::Json);
+        }
+        if quoted {
+            self.expect(b'"')?;
+        }
+        Ok((negative, val))
+    }
+
+    fn unsigned(&mut self) -> Result<u64, TranscodeError> {
+        match self.integer()? {
+            (false, v) => Ok(v),
+            (true, 0) => Ok(0),
+            _ => Err(TranscodeError::Json),
+        }
+    }
+
+    /// Parse a floating-point number, also accepted as a quoted string, e.g. `"NaN"`.
+    fn float<F: core::str::FromStr>(&mut self) -> Result<F, TranscodeError> {
+        let raw = if self.peek() == Some(b'"') {
+            self.string()?
+        } else {
+            let start = self.pos;
+            while let Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') = self.s.get(self.pos) {
+                self.pos += 1;
+            }
+            &self.s[start..self.pos]
+        };
+        let raw = core::str::from_utf8(raw).map_err(|_| TranscodeError::Json)?;
+        raw.parse().map_err(|_| TranscodeError::Json)
+    }
+
+    fn signed(&mut self) -> Result<i64, TranscodeError> {
+        let (negative, v) = self.integer()?;
+        match negative {
+            false => i64::try_from(v).map_err(|_| TranscodeError::Json),
+            true if v <= i64::MAX as u64 + 1 => Ok((v as i64).wrapping_neg()),
+            true => Err(TranscodeError::Json),
+        }
+    }
+}
+
+/// Call `f` with each byte of the string `raw`, resolving escapes.
+fn unescape(raw: &[u8], mut f: impl FnMut(u8) -> Result<(), TranscodeError>) -> Result<(), TranscodeError> {
+    let mut i = 0;
+    while i < raw.len() {
+        let b = raw[i];
+        i += 1;
+        if b != b'\\' {
+            f(b)?;
+            continue;
+        }
+        let e = *raw.get(i).ok_or(TranscodeError::Json)?;
+        i += 1;
+        let c = match e {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let hex = |i: usize| -> Result<u32, TranscodeError> {
+                    let digits = raw.get(i..i + 4).ok_or(TranscodeError::Json)?;
+                    let digits = core::str::from_utf8(digits).map_err(|_| TranscodeError::Json)?;
+                    u32::from_str_radix(digits, 16).map_err(|_| TranscodeError::Json)
+                };
+                let mut code = hex(i)?;
+                i += 4;
+                if (0xd800..0xdc00).contains(&code) {
+                    // Surrogate pair.
+                    if raw.get(i..i + 2) != Some(b"\\u") {
+                        return Err(TranscodeError::Json);
+                    }
+                    let low = hex(i + 2)?;
+                    i += 6;
+                    code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                }
+                char::from_u32(code).ok_or(TranscodeError::Json)?
+            }
+            _ => return Err(TranscodeError::Json),
+        };
+        let mut utf8 = [0; 4];
+        for &b in c.encode_utf8(&mut utf8).as_bytes() {
+            f(b)?;
+        }
+    }
+    Ok(())
+}
+
+fn unescaped_eq(raw: &[u8], s: &str) -> bool {
+    let mut expected = s.as_bytes().iter();
+    let mut equal = true;
+    let res = unescape(raw, |b| {
+        equal &= expected.next() == Some(&b);
+        Ok(())
+    });
+    res.is_ok() && equal && expected.next().is_none()
+}
+
+fn base64_value(c: u8) -> Result<u32, TranscodeError> {
+    match c {
+        b'A'..=b'Z' => Ok((c - b'A') as u32),
+        b'a'..=b'z' => Ok((c - b'a' + 26) as u32),
+        b'0'..=b'9' => Ok((c - b'0' + 52) as u32),
+        b'+' | b'-' => Ok(62),
+        b'/' | b'_' => Ok(63),
+        _ => Err(TranscodeError::Json),
+    }
+}
+
+fn base64_decode(raw: &[u8], w: &mut ByteWriter) -> Result<(), TranscodeError> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for &c in raw.iter().take_while(|&&c| c != b'=') {
+        acc = acc << 6 | base64_value(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            w.write_u8((acc >> bits) as u8)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `f` inside a length-delimited section of `w`, keeping its error.
+fn length_delimited(
+    w: &mut ByteWriter,
+    f: impl FnOnce(&mut ByteWriter) -> Result<(), TranscodeError>,
+) -> Result<(), TranscodeError> {
+    let mut err = None;
+    let res = w.write_length_delimited(|w| {
+        f(w).map_err(|e| {
+            err = Some(e);
+            WriteError
+        })
+    });
+    match (err, res) {
+        (Some(e), _) => Err(e),
+        (None, res) => Ok(res?),
+    }
+}
+
+fn parse_message(p: &mut Parser, desc: &MessageDescriptor, w: &mut ByteWriter) -> Result<(), TranscodeError> {
+    p.expect(b'{')?;
+    if p.eat(b'}') {
+        return Ok(());
+    }
+    loop {
+        let key = p.string()?;
+        p.expect(b':')?;
+        let field = desc
+            .all_fields()
+            .find(|f| unescaped_eq(key, f.name))
+            .ok_or(TranscodeError::Json)?;
+
+        if p.keyword("null") {
+            // Absent.
+        } else if field.label == Label::Repeated {
+            p.expect(b'[')?;
+            if !p.eat(b']') {
+                loop {
+                    parse_value(p, field, w)?;
+                    if p.eat(b']') {
+                        break;
+                    }
+                    p.expect(b',')?;
+                }
+            }
+        } else {
+            parse_value(p, field, w)?;
+        }
+
+        if p.eat(b'}') {
+            return Ok(());
+        }
+        p.expect(b',')?;
+    }
+}
+
+fn parse_value(p: &mut Parser, field: &FieldDescriptor, w: &mut ByteWriter) -> Result<(), TranscodeError> {
+    let wire_type = field.wire_type().ok_or(TranscodeError::Json)?;
+    w.write_varuint32((field.tag << 3) | wire_type as u32)?;
+    match field.ty {
+        Type::Bool => {
+            let v = if p.keyword("true") {
+                true
+            } else if p.keyword("false") {
+                false
+            } else {
+                return Err(TranscodeError::Json);
+            };
+            w.write_varuint32(v as u32)?;
+        }
+        Type::Uint32 => w.write_varuint32(u32::try_from(p.unsigned()?).map_err(|_| TranscodeError::Json)?)?,
+        Type::Uint64 => w.write_varuint64(p.unsigned()?)?,
+        Type::Int32 => {
+            let v = i32::try_from(p.signed()?).map_err(|_| TranscodeError::Json)?;
+            w.write_varuint64(v as i64 as u64)?
+        }
+        Type::Int64 => w.write_varuint64(p.signed()? as u64)?,
+        Type::Sint32 => w.write_varint32(i32::try_from(p.signed()?).map_err(|_| TranscodeError::Json)?)?,
+        Type::Sint64 => w.write_varint64(p.signed()?)?,
+        Type::Fixed32 => w.write_u32(u32::try_from(p.unsigned()?).map_err(|_| TranscodeError::Json)?)?,
+        Type::Fixed64 => w.write_u64(p.unsigned()?)?,
+        Type::Sfixed32 => {
+            let v = i32::try_from(p.signed()?).map_err(|_| TranscodeError::Json)?;
+            w.write_u32(v as u32)?
+        }
+        Type::Sfixed64 => w.write_u64(p.signed()? as u64)?,
+        Type::Float => w.write_u32(p.float::<f32>()?.to_bits())?,
+        Type::Double => w.write_u64(p.float::<f64>()?.to_bits())?,
+        Type::String => {
+            let raw = p.string()?;
+            length_delimited(w, |w| unescape(raw, |b| Ok(w.write_u8(b)?)))?;
+        }
+        Type::Bytes => {
+            let raw = p.string()?;
+            length_delimited(w, |w| base64_decode(raw, w))?;
+        }
+        Type::FixedBytes(len) => {
+            let raw = p.string()?;
+            length_delimited(w, |w| {
+                let start = w.pos();
+                base64_decode(raw, w)?;
+                match w.pos() - start == len {
+                    true => Ok(()),
+                    false => Err(TranscodeError::Json),
+                }
+            })?;
+        }
+        Type::Enum(e) => {
+            let v = if p.peek() == Some(b'"') {
+                let raw = p.string()?;
+                let (_, v) = e
+                    .values
+                    .iter()
+                    .find(|(name, _)| unescaped_eq(raw, name))
+                    .ok_or(TranscodeError::Json)?;
+                *v
+            } else {
+                i32::try_from(p.signed()?).map_err(|_| TranscodeError::Json)?
+            };
+            w.write_varuint64(v as i64 as u64)?;
+        }
+        Type::Message(m) if field.group => {
+            parse_message(p, m, w)?;
+            w.write_varuint32((field.tag << 3) | END_GROUP)?;
+        }
+        Type::Message(m) => length_delimited(w, |w| parse_message(p, m, w))?,
+        Type::Unknown => return Err(TranscodeError::Json),
+    }
+    Ok(())
+}
@@ -0,0 +1,313 @@
+//! Wrapper types selecting a specific protobuf wire encoding.
+//!
+//! Each wrapper encodes its integer as the protobuf type it's named after, whatever the
+//! attributes of the field, so the encoding is part of the type. This also selects the encoding
+//! in generic code and containers, where there are no field attributes:
+//!
+//! ```
+//! use noproto::types::{Fixed32, Sint32, Uint32};
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Sample<T> {
+//!     #[noproto(tag = 1)]
+//!     value: T,
+//! }
+//!
+//! let mut buf = [0; 8];
+//! assert_eq!(noproto::write(&Sample { value: Uint32(1) }, &mut buf), Ok(2));
+//! assert_eq!(noproto::write(&Sample { value: Sint32(-1) }, &mut buf), Ok(2));
+//! assert_eq!(noproto::write(&Sample { value: Fixed32(1) }, &mut buf), Ok(5));
+//! ```
+
+use crate::descriptor::Type;
+use crate::fixed::FixedLe;
+use crate::read::ByteReader;
+use crate::write::ByteWriter;
+use crate::{Message, ReadError, WireType, WriteError};
+
+macro_rules! wrapper {
+    ($(#[$attr:meta])* $name:ident($ty:ty)) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub $ty);
+
+        impl From<$ty> for $name {
+            fn from(val: $ty) -> Self {
+                Self(val)
+            }
+        }
+
+        impl From<$name> for $ty {
+            fn from(val: $name) -> Self {
+                val.0
+            }
+        }
+    };
+}
+
+wrapper!(
+    /// `fixed32`: unsigned 32-bit integer, always encoded as 4 little-endian bytes.
+    Fixed32(u32)
+);
+wrapper!(
+    /// `fixed64`: unsigned 64-bit integer, always encoded as 8 little-endian bytes.
+    Fixed64(u64)
+);
+wrapper!(
+    /// `sfixed32`: signed 32-bit integer, always encoded as 4 little-endian bytes.
+    SFixed32(i32)
+);
+wrapper!(
+    /// `sfixed64`: signed 64-bit integer, always encoded as 8 little-endian bytes.
+    SFixed64(i64)
+);
+
+wrapper!(
+    /// `sint32`: signed 32-bit integer, zigzag varint encoded. Same encoding as plain `i32`.
+    Sint32(i32)
+);
+wrapper!(
+    /// `sint64`: signed 64-bit integer, zigzag varint encoded. Same encoding as plain `i64`.
+    Sint64(i64)
+);
+wrapper!(
+    /// `int32`: signed 32-bit integer, two's-complement varint encoded.
+    ///
+    /// Negative values are sign-extended to 64 bits, so they always take 10 bytes on the wire.
+    Int32(i32)
+);
+wrapper!(
+    /// `int64`: signed 64-bit integer, two's-complement varint encoded.
+    Int64(i64)
+);
+wrapper!(
+    /// `uint32`: unsigned 32-bit integer, varint encoded. Same encoding as plain `u32`.
+    Uint32(u32)
+);
+wrapper!(
+    /// `uint64`: unsigned 64-bit integer, varint encoded. Same encoding as plain `u64`.
+    Uint64(u64)
+);
+
+impl Message for Fixed32 {
+    const WIRE_TYPE: WireType = WireType::ThirtyTwoBit;
+    const MAX_ENCODED_LEN: usize = 4;
+    const TYPE: Type = Type::Fixed32;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_u32(self.0)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_u32()?;
+        Ok(())
+    }
+}
+
+impl Message for Fixed64 {
+    const WIRE_TYPE: WireType = WireType::SixtyFourBit;
+    const MAX_ENCODED_LEN: usize = 8;
+    const TYPE: Type = Type::Fixed64;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_u64(self.0)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_u64()?;
+        Ok(())
+    }
+}
+
+impl Message for SFixed32 {
+    const WIRE_TYPE: WireType = WireType::ThirtyTwoBit;
+    const MAX_ENCODED_LEN: usize = 4;
+    const TYPE: Type = Type::Sfixed32;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_u32(self.0 as u32)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_u32()? as i32;
+        Ok(())
+    }
+}
+
+impl Message for SFixed64 {
+    const WIRE_TYPE: WireType = WireType::SixtyFourBit;
+    const MAX_ENCODED_LEN: usize = 8;
+    const TYPE: Type = Type::Sfixed64;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_u64(self.0 as u64)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_u64()? as i64;
+        Ok(())
+    }
+}
+
+impl Message for Sint32 {
+    const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 5;
+    const TYPE: Type = Type::Sint32;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_varint32(self.0)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_varint32()?;
+        Ok(())
+    }
+}
+
+impl Message for Sint64 {
+    const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 10;
+    const TYPE: Type = Type::Sint64;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_varint64(self.0)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_varint64()?;
+        Ok(())
+    }
+}
+
+impl Message for Int32 {
+    const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 10;
+    const TYPE: Type = Type::Int32;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_varuint64(self.0 as i64 as u64)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        // int32 values are truncated to 32 bits, as protoc does.
+        self.0 = r.read_varuint64()? as i32;
+        Ok(())
+    }
+}
+
+impl Message for Int64 {
+    const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 10;
+    const TYPE: Type = Type::Int64;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_varuint64(self.0 as u64)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_varuint64()? as i64;
+        Ok(())
+    }
+}
+
+impl Message for Uint32 {
+    const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 5;
+    const TYPE: Type = Type::Uint32;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_varuint32(self.0)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_varuint32()?;
+        Ok(())
+    }
+}
+
+impl Message for Uint64 {
+    const WIRE_TYPE: WireType = WireType::Varint;
+    const MAX_ENCODED_LEN: usize = 10;
+    const TYPE: Type = Type::Uint64;
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_varuint64(self.0)
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        self.0 = r.read_varuint64()?;
+        Ok(())
+    }
+}
+
+/// Integer types with a `fixed32`/`fixed64` encoding, selected by `#[noproto(fixed)]`.
+pub trait AsFixed: Sized {
+    /// Wrapper type implementing the encoding.
+    type Wire: Message + Default + From<Self> + Into<Self>;
+}
+
+/// Integer types with a `sfixed32`/`sfixed64` encoding, selected by `#[noproto(sfixed)]`.
+pub trait AsSFixed: Sized {
+    /// Wrapper type implementing the encoding.
+    type Wire: Message + Default + From<Self> + Into<Self>;
+}
+
+impl AsFixed for u32 {
+    type Wire = Fixed32;
+}
+
+impl AsFixed for u64 {
+    type Wire = Fixed64;
+}
+
+impl AsSFixed for i32 {
+    type Wire = SFixed32;
+}
+
+impl AsSFixed for i64 {
+    type Wire = SFixed64;
+}
+
+/// Number stored as a `bytes` field holding its little-endian representation, selected by
+/// `#[noproto(raw_le_bytes)]`.
+///
+/// Matches the layout of the number in a packed C struct, for peers mixing raw-struct framing
+/// with protobuf. Decoding fails if the field length is not the size of `T`, which descriptors
+/// record as [`Type::FixedBytes`].
+///
+/// ```
+/// use noproto::descriptor::{descriptor, Type};
+/// use noproto::transcode::from_json;
+///
+/// #[derive(noproto::Message, Default)]
+/// struct Frame {
+///     #[noproto(tag = 1, raw_le_bytes)]
+///     voltage: u32,
+/// }
+///
+/// let desc = descriptor::<Frame>().unwrap();
+/// assert_eq!(desc.field(1).unwrap().ty, Type::FixedBytes(4));
+///
+/// let mut buf = [0; 16];
+/// assert!(from_json(desc, r#"{"voltage":"AQIDBA=="}"#, &mut buf).is_ok());
+/// assert!(from_json(desc, r#"{"voltage":"AQID"}"#, &mut buf).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RawLe<T>(pub T);
+
+macro_rules! raw_le {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for RawLe<$ty> {
+                fn from(val: $ty) -> Self {
+                    Self(val)
+                }
+            }
+
+            impl From<RawLe<$ty>> for $ty {
+                fn from(val: RawLe<$ty>) -> Self {
+                    val.0
+                }
+            }
+        )*
+    };
+}
+
+raw_le!(u8, i8, u16, i16, u32, i32, f32, u64, i64, f64);
+
+impl<T: FixedLe + Default> Message for RawLe<T> {
+    const WIRE_TYPE: WireType = WireType::LengthDelimited;
+    const MAX_ENCODED_LEN: usize = T::SIZE;
+    const TYPE: Type = Type::FixedBytes(T::SIZE);
+    fn write_raw(&self, w: &mut ByteWriter) -> Result<(), WriteError> {
+        w.write_fixed_slice(&[self.0])
+    }
+    fn read_raw(&mut self, r: &mut ByteReader) -> Result<(), ReadError> {
+        let bytes = r.read_to_end()?;
+        if bytes.len() != T::SIZE {
+            return Err(ReadError::InvalidLength);
+        }
+        self.0 = T::get_le(bytes);
+        Ok(())
+    }
+}
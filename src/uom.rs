@@ -0,0 +1,114 @@
+//! Conversions between [`uom`](::uom) quantities and plain numbers on the wire.
+//!
+//! [`uom_convert!`](crate::uom_convert) defines a module for the `convert` field attribute,
+//! encoding a quantity as a number of a given unit, multiplied by a scale. Integers are rounded
+//! to the nearest value and saturate at the bounds of the wire type.
+//!
+//! ```
+//! use uom::si::f32::ThermodynamicTemperature;
+//! use uom::si::thermodynamic_temperature::degree_celsius;
+//!
+//! // Milli-degrees Celsius in a `sint32`.
+//! noproto::uom_convert!(
+//!     mod millidegrees:
+//!     uom::si::f32::ThermodynamicTemperature,
+//!     uom::si::thermodynamic_temperature::degree_celsius,
+//!     i32,
+//!     1000.0
+//! );
+//!
+//! #[derive(noproto::Message, Default)]
+//! struct Reading {
+//!     #[noproto(tag = 1, convert(from = "i32", with = "millidegrees"))]
+//!     temperature: ThermodynamicTemperature,
+//! }
+//!
+//! let reading = Reading { temperature: ThermodynamicTemperature::new::<degree_celsius>(21.5) };
+//! assert_eq!(millidegrees::to_wire(&reading.temperature), 21500);
+//!
+//! let mut buf = [0; 8];
+//! let n = noproto::write(&reading, &mut buf).unwrap();
+//! let back: Reading = noproto::read(&buf[..n]).unwrap();
+//! assert_eq!(back.temperature.get::<degree_celsius>(), 21.5);
+//! ```
+
+/// Numbers a quantity can be stored as, on the wire or in the quantity itself.
+pub trait Number: Copy {
+    /// Convert to `f64`.
+    fn to_f64(self) -> f64;
+    /// Convert from `f64`, rounding to the nearest integer and saturating if needed.
+    fn from_f64(val: f64) -> Self;
+}
+
+macro_rules! float {
+    ($ty:ty) => {
+        impl Number for $ty {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(val: f64) -> Self {
+                val as $ty
+            }
+        }
+    };
+}
+
+macro_rules! int {
+    ($ty:ty) => {
+        impl Number for $ty {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(val: f64) -> Self {
+                // `f64::round` needs `std`. The cast saturates, and maps NaN to 0.
+                if val < 0.0 {
+                    (val - 0.5) as $ty
+                } else {
+                    (val + 0.5) as $ty
+                }
+            }
+        }
+    };
+}
+
+float!(f32);
+float!(f64);
+int!(i32);
+int!(i64);
+int!(u32);
+int!(u64);
+
+/// Scale the value `val` of a quantity to its wire representation.
+pub fn to_wire<V: Number, W: Number>(val: V, scale: f64) -> W {
+    W::from_f64(val.to_f64() * scale)
+}
+
+/// Scale the wire representation `wire` back to the value of a quantity.
+pub fn from_wire<W: Number, V: Number>(wire: W, scale: f64) -> V {
+    V::from_f64(wire.to_f64() / scale)
+}
+
+/// Define a module converting a [`uom`](::uom) quantity for the `convert` field attribute.
+///
+/// Takes the module name, the quantity type (with `f32` or `f64` storage), the unit, the wire
+/// type and the number of wire units per unit. See the [`uom`](crate::uom) module for an
+/// example.
+#[macro_export]
+macro_rules! uom_convert {
+    ($vis:vis mod $name:ident: $qty:ty, $unit:ty, $wire:ty, $scale:expr $(,)?) => {
+        $vis mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            /// Convert the quantity to its wire representation.
+            pub fn to_wire(value: &$qty) -> $wire {
+                $crate::uom::to_wire(value.get::<$unit>(), $scale)
+            }
+
+            /// Convert the wire representation back to the quantity.
+            pub fn from_wire(wire: $wire) -> $qty {
+                <$qty>::new::<$unit>($crate::uom::from_wire(wire, $scale))
+            }
+        }
+    };
+}
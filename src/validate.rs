@@ -0,0 +1,22 @@
+/// Error returned by [`Message::validate`](crate::Message::validate).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ValidationError {
+    /// A string holds invalid UTF-8.
+    Utf8,
+    /// A container holds more elements than its capacity.
+    Capacity,
+    /// An enumeration field holds a value that's not one of its variants.
+    Enum,
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValidationError::Utf8 => f.write_str("invalid UTF-8 in string"),
+            ValidationError::Capacity => f.write_str("container length over capacity"),
+            ValidationError::Enum => f.write_str("unknown enumeration value"),
+        }
+    }
+}
+
+impl core::error::Error for ValidationError {}
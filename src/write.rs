@@ -1,32 +1,425 @@
+use crate::descriptor::Type;
+use crate::fixed::FixedLe;
+use crate::read::END_GROUP;
 use crate::{Message, Oneof, OptionalMessage, RepeatedMessage, WireType};
 
 /// Error returned by [`ByteWriter`].
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct WriteError;
 
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("buffer too small")
+    }
+}
+
+impl core::error::Error for WriteError {}
+
+/// Error returned by [`write_sized`](crate::write_sized).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SizedWriteError {
+    /// Length of the encoded message, or `None` if it can't be encoded in a buffer of any size.
+    pub required: Option<usize>,
+}
+
+impl core::fmt::Display for SizedWriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.required {
+            Some(required) => write!(f, "buffer too small, {} bytes required", required),
+            None => f.write_str("message can't be encoded"),
+        }
+    }
+}
+
+impl core::error::Error for SizedWriteError {}
+
+impl From<SizedWriteError> for WriteError {
+    fn from(_: SizedWriteError) -> Self {
+        WriteError
+    }
+}
+
+/// Maximum number of bytes of a varint-encoded u32.
+pub const MAX_VARUINT32_LEN: usize = 5;
+
+/// Maximum number of bytes of a varint-encoded u64, and of any valid varint.
+pub const MAX_VARUINT64_LEN: usize = 10;
+
+/// Maximum length of length-delimited data, whose length is encoded as a u32.
+pub const MAX_LENGTH_DELIMITED: usize = u32::MAX as usize;
+
+/// Number of bytes `val` takes when varint-encoded.
+pub const fn varuint_len(val: u64) -> usize {
+    let bits = 64 - (val | 1).leading_zeros() as usize;
+    bits.div_ceil(7)
+}
+
+/// Upper bound on the encoded length of a field holding message `M`, including its header.
+pub const fn max_field_len<M: Message>(tag: u32) -> usize {
+    let header = varuint_len((tag as u64) << 3);
+    let body = match M::WIRE_TYPE {
+        WireType::LengthDelimited => M::MAX_ENCODED_LEN.saturating_add(varuint_len(M::MAX_ENCODED_LEN as u64)),
+        _ => M::MAX_ENCODED_LEN,
+    };
+    body.saturating_add(header)
+}
+
+/// Upper bound on the encoded length of a repeated field, including the field headers.
+pub const fn max_repeated_len<M: RepeatedMessage>(tag: u32) -> usize {
+    M::MAX_LEN.saturating_mul(max_field_len::<M::Message>(tag))
+}
+
+/// Upper bound on the encoded length of a repeated field written with
+/// [`write_packed`](ByteWriter::write_packed), including its header.
+pub const fn max_packed_len<M: RepeatedMessage>(tag: u32) -> usize {
+    if matches!(M::Message::WIRE_TYPE, WireType::LengthDelimited) {
+        return max_repeated_len::<M>(tag);
+    }
+    let body = M::MAX_LEN.saturating_mul(M::Message::MAX_ENCODED_LEN);
+    let header = varuint_len((tag as u64) << 3);
+    body.saturating_add(varuint_len(body as u64)).saturating_add(header)
+}
+
+/// Fail the build if `M` can't be encoded as a group: only messages made of fields can, not
+/// scalars, strings or bytes.
+pub(crate) const fn assert_group<M: Message>() {
+    let fields = matches!(M::WIRE_TYPE, WireType::LengthDelimited)
+        && !matches!(M::TYPE, Type::String | Type::Bytes | Type::FixedBytes(_));
+    assert!(fields, "group fields must hold messages");
+}
+
+/// Upper bound on the encoded length of a group holding message `M`, including its start-group
+/// and end-group headers.
+pub const fn max_group_len<M: Message>(tag: u32) -> usize {
+    let header = varuint_len((tag as u64) << 3);
+    M::MAX_ENCODED_LEN.saturating_add(2 * header)
+}
+
+/// Upper bound on the encoded length of a repeated group field, including the group headers.
+pub const fn max_repeated_group_len<M: RepeatedMessage>(tag: u32) -> usize {
+    M::MAX_LEN.saturating_mul(max_group_len::<M::Message>(tag))
+}
+
 /// Writer for protobuf messages.
 pub struct ByteWriter<'a> {
-    buf: &'a mut [u8],
+    buf: Buf<'a>,
+    pos: usize,
+}
+
+/// Position of a [`ByteWriter`], to roll back to with [`ByteWriter::restore`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Checkpoint {
     pos: usize,
 }
 
+impl Checkpoint {
+    /// Get the number of bytes written when the checkpoint was taken.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+/// Growable destination for a [`ByteWriter`], see [`ByteWriter::with_output`].
+///
+/// Implemented for `heapless::Vec<u8, N>`, and for `Vec<u8>` with the `alloc` feature. Plain
+/// `&mut [u8]` buffers are written with [`ByteWriter::new`].
+pub trait Output {
+    /// Get the bytes written so far.
+    fn bytes(&self) -> &[u8];
+    /// Get the bytes written so far, to patch them.
+    fn bytes_mut(&mut self) -> &mut [u8];
+    /// Append `bytes`, or fail if they don't fit.
+    fn push(&mut self, bytes: &[u8]) -> Result<(), WriteError>;
+    /// Append `len` zero bytes to be patched later, or fail if they don't fit.
+    fn reserve(&mut self, len: usize) -> Result<(), WriteError>;
+    /// Shorten the output to `len` bytes.
+    fn truncate(&mut self, len: usize);
+    /// Get the number of bytes written so far.
+    fn position(&self) -> usize {
+        self.bytes().len()
+    }
+}
+
+impl<const N: usize> Output for heapless::Vec<u8, N> {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+    fn push(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.extend_from_slice(bytes).map_err(|_| WriteError)
+    }
+    fn reserve(&mut self, len: usize) -> Result<(), WriteError> {
+        let len = self.len().checked_add(len).ok_or(WriteError)?;
+        self.resize(len, 0).map_err(|_| WriteError)
+    }
+    fn truncate(&mut self, len: usize) {
+        heapless::Vec::truncate(self, len)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Output for alloc::vec::Vec<u8> {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+    fn push(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+    fn reserve(&mut self, len: usize) -> Result<(), WriteError> {
+        let len = self.len().checked_add(len).ok_or(WriteError)?;
+        self.resize(len, 0);
+        Ok(())
+    }
+    fn truncate(&mut self, len: usize) {
+        alloc::vec::Vec::truncate(self, len)
+    }
+}
+
+/// Storage backing a [`ByteWriter`].
+enum Buf<'a> {
+    /// A single contiguous buffer.
+    Slice(&'a mut [u8]),
+    /// Several buffers, filled in order as if they were one.
+    Segments(&'a mut [&'a mut [u8]]),
+    /// A growable container.
+    Output(&'a mut dyn Output),
+    /// Only store the bytes at `start..start + buf.len()`, discarding the others.
+    Window { buf: &'a mut [u8], start: usize },
+    /// Only count the bytes written, without storing them.
+    Counting,
+    /// Pass the bytes to `f` as they are written, `len` of them so far.
+    Stream { f: &'a mut dyn FnMut(&[u8]) -> Result<(), WriteError>, len: usize },
+}
+
+impl Buf<'_> {
+    fn capacity(&self) -> usize {
+        match self {
+            Buf::Slice(buf) => buf.len(),
+            Buf::Segments(segments) => segments.iter().map(|s| s.len()).sum(),
+            // The output reports when it's full.
+            Buf::Output(_) | Buf::Window { .. } | Buf::Counting | Buf::Stream { .. } => usize::MAX,
+        }
+    }
+
+    /// Get a mutable reference to the byte at `pos`, failing if it isn't stored.
+    fn byte_mut(&mut self, mut pos: usize) -> Result<&mut u8, WriteError> {
+        match self {
+            Buf::Slice(buf) => buf.get_mut(pos).ok_or(WriteError),
+            Buf::Segments(segments) => {
+                for segment in segments.iter_mut() {
+                    if pos < segment.len() {
+                        return Ok(&mut segment[pos]);
+                    }
+                    pos -= segment.len();
+                }
+                Err(WriteError)
+            }
+            Buf::Output(out) => out.bytes_mut().get_mut(pos).ok_or(WriteError),
+            Buf::Window { .. } | Buf::Counting | Buf::Stream { .. } => Err(WriteError),
+        }
+    }
+
+    /// Grow the output to at least `len` bytes, zero-filling it.
+    fn grow(&mut self, len: usize) -> Result<(), WriteError> {
+        if let Buf::Output(out) = self {
+            let pos = out.position();
+            if len > pos {
+                out.reserve(len - pos)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop anything written after `len`.
+    fn truncate(&mut self, len: usize) {
+        if let Buf::Output(out) = self {
+            if out.position() > len {
+                out.truncate(len);
+            }
+        }
+    }
+
+    /// Copy `bytes` to `pos`.
+    fn put(&mut self, pos: usize, bytes: &[u8]) -> Result<(), WriteError> {
+        match self {
+            Buf::Slice(buf) => subslice(buf, pos, bytes.len())?.copy_from_slice(bytes),
+            Buf::Output(out) if out.position() == pos => out.push(bytes)?,
+            Buf::Output(_) => {
+                self.grow(pos + bytes.len())?;
+                if let Buf::Output(out) = self {
+                    subslice(out.bytes_mut(), pos, bytes.len())?.copy_from_slice(bytes);
+                }
+            }
+            Buf::Window { buf, start } => {
+                let (lo, hi) = window_overlap(*start, buf.len(), pos, bytes.len());
+                if lo < hi {
+                    buf[lo - *start..hi - *start].copy_from_slice(&bytes[lo - pos..hi - pos]);
+                }
+            }
+            Buf::Counting => {}
+            // Bytes already passed on can't be changed, and no gap can be left.
+            Buf::Stream { f, len } if *len == pos => {
+                f(bytes)?;
+                *len += bytes.len();
+            }
+            Buf::Stream { .. } => return Err(WriteError),
+            _ => {
+                for (i, &b) in bytes.iter().enumerate() {
+                    *self.byte_mut(pos + i)? = b;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the bytes in `start..end` to `dest`.
+    fn move_to(&mut self, start: usize, end: usize, dest: usize) -> Result<(), WriteError> {
+        match self {
+            Buf::Slice(buf) => move_within(buf, start, end, dest)?,
+            Buf::Output(_) => {
+                self.grow(dest + (end - start))?;
+                if let Buf::Output(out) = self {
+                    move_within(out.bytes_mut(), start, end, dest)?;
+                }
+            }
+            Buf::Window { buf, start: window } => {
+                // The bytes outside the window are gone, only moves not touching it work.
+                let (lo, hi) = window_overlap(*window, buf.len(), start.min(dest), end.max(dest + (end - start)));
+                if lo < hi {
+                    return Err(WriteError);
+                }
+            }
+            Buf::Counting => {}
+            Buf::Stream { .. } if start < end => return Err(WriteError),
+            Buf::Stream { .. } => {}
+            _ if dest > start => {
+                for i in (0..end - start).rev() {
+                    let b = *self.byte_mut(start + i)?;
+                    *self.byte_mut(dest + i)? = b;
+                }
+            }
+            _ => {
+                for i in 0..end - start {
+                    let b = *self.byte_mut(start + i)?;
+                    *self.byte_mut(dest + i)? = b;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get `buf[pos..pos + len]`, failing if it's out of bounds.
+fn subslice(buf: &mut [u8], pos: usize, len: usize) -> Result<&mut [u8], WriteError> {
+    buf.get_mut(pos..).and_then(|b| b.get_mut(..len)).ok_or(WriteError)
+}
+
+/// Move `buf[start..end]` to `dest`, failing if either range is out of bounds.
+fn move_within(buf: &mut [u8], start: usize, end: usize, dest: usize) -> Result<(), WriteError> {
+    if start > end || end > buf.len() || dest > buf.len() - (end - start) {
+        return Err(WriteError);
+    }
+    buf.copy_within(start..end, dest);
+    Ok(())
+}
+
+/// Intersection of the window `start..start + len` with `pos..pos + n`, as absolute positions.
+fn window_overlap(start: usize, len: usize, pos: usize, n: usize) -> (usize, usize) {
+    (pos.max(start), (pos + n).min(start + len))
+}
+
 impl<'a> ByteWriter<'a> {
     /// Create a new [`ByteWriter`] that writes to `buf`.
     pub fn new(buf: &'a mut [u8]) -> Self {
-        Self { buf, pos: 0 }
+        Self {
+            buf: Buf::Slice(buf),
+            pos: 0,
+        }
+    }
+
+    /// Create a new [`ByteWriter`] that writes to several buffers, filling them in order.
+    pub fn new_segmented(segments: &'a mut [&'a mut [u8]]) -> Self {
+        Self {
+            buf: Buf::Segments(segments),
+            pos: 0,
+        }
+    }
+
+    /// Create a new [`ByteWriter`] that appends to a growable `out`, e.g. a `heapless::Vec`.
+    ///
+    /// Positions include the bytes already in `out`.
+    pub fn with_output(out: &'a mut dyn Output) -> Self {
+        Self {
+            pos: out.position(),
+            buf: Buf::Output(out),
+        }
+    }
+
+    /// Create a [`ByteWriter`] that only stores the bytes written at positions
+    /// `start..start + buf.len()`, discarding the others.
+    ///
+    /// Encoding a message again with increasing `start` produces it piece by piece, see
+    /// [`ChunkedEncoder`](crate::chunked::ChunkedEncoder). Data written with
+    /// [`write_length_delimited`](Self::write_length_delimited) can't be moved to fit its
+    /// header if it touches the window, so doing so fails.
+    pub fn new_window(buf: &'a mut [u8], start: usize) -> Self {
+        Self {
+            buf: Buf::Window { buf, start },
+            pos: 0,
+        }
+    }
+
+    /// Create a [`ByteWriter`] that passes the bytes to `f` in order as they are written,
+    /// without storing them, failing the write if `f` fails.
+    ///
+    /// Nothing can be moved or patched, so lengths are computed before the data they describe.
+    pub(crate) fn streaming(f: &'a mut dyn FnMut(&[u8]) -> Result<(), WriteError>) -> Self {
+        Self {
+            buf: Buf::Stream { f, len: 0 },
+            pos: 0,
+        }
+    }
+
+    /// Check if data written with [`write_length_delimited`](Self::write_length_delimited) can
+    /// be moved to fit its header, which windows and streams can't do.
+    pub(crate) fn can_move(&self) -> bool {
+        !matches!(self.buf, Buf::Window { .. } | Buf::Stream { .. })
+    }
+
+    /// Create a [`ByteWriter`] that only counts the bytes written, without storing them.
+    ///
+    /// Writing a message to it gives its encoded length, see [`pos`](Self::pos).
+    pub fn counting() -> ByteWriter<'static> {
+        ByteWriter {
+            buf: Buf::Counting,
+            pos: 0,
+        }
     }
 
     /// Get the bytes written so far.
+    ///
+    /// Empty if the writer is backed by several buffers or only counts.
     pub fn bytes(&self) -> &[u8] {
-        &self.buf[..self.pos]
+        match &self.buf {
+            Buf::Slice(buf) => &buf[..self.pos],
+            Buf::Output(out) => out.bytes(),
+            _ => &[],
+        }
     }
 
     /// Write `bytes` to the buffer.
     pub fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
-        if self.buf.len() - self.pos < bytes.len() {
+        if self.buf.capacity() - self.pos < bytes.len() {
             return Err(WriteError);
         }
-        self.buf[self.pos..][..bytes.len()].copy_from_slice(bytes);
+        self.buf.put(self.pos, bytes)?;
         self.pos += bytes.len();
         Ok(())
     }
@@ -51,6 +444,28 @@ impl<'a> ByteWriter<'a> {
         self.write(&val.to_le_bytes())
     }
 
+    /// Write fixed-width values to the buffer, as the body of a packed repeated field.
+    ///
+    /// On little-endian targets this is a single copy.
+    pub fn write_fixed_slice<T: FixedLe>(&mut self, vals: &[T]) -> Result<(), WriteError> {
+        #[cfg(target_endian = "little")]
+        {
+            self.write(crate::fixed::as_bytes(vals))
+        }
+        #[cfg(not(target_endian = "little"))]
+        {
+            if self.buf.capacity() - self.pos < vals.len() * T::SIZE {
+                return Err(WriteError);
+            }
+            let mut bytes = [0; 8];
+            for &v in vals {
+                v.put_le(&mut bytes[..T::SIZE]);
+                self.write(&bytes[..T::SIZE])?;
+            }
+            Ok(())
+        }
+    }
+
     /// Write varint-encoded u32 to the buffer.
     pub fn write_varuint32(&mut self, mut val: u32) -> Result<(), WriteError> {
         loop {
@@ -102,26 +517,56 @@ impl<'a> ByteWriter<'a> {
         &mut self,
         f: impl FnOnce(&mut ByteWriter) -> Result<(), WriteError>,
     ) -> Result<(), WriteError> {
+        self.write_length_delimited_max(usize::MAX, f)
+    }
+
+    /// Write length-delimited data of at most `max_len` bytes to the buffer.
+    ///
+    /// Space for the length header is reserved up front based on `max_len`, so the data
+    /// only has to be moved if it ends up with a shorter header. With `max_len` below 128
+    /// it's never moved.
+    pub fn write_length_delimited_max(
+        &mut self,
+        max_len: usize,
+        f: impl FnOnce(&mut ByteWriter) -> Result<(), WriteError>,
+    ) -> Result<(), WriteError> {
+        let reserved = match max_len {
+            0..=MAX_LENGTH_DELIMITED => varuint_len(max_len as u64),
+            _ => 0,
+        };
+        if self.buf.capacity() - self.pos < reserved {
+            return Err(WriteError);
+        }
+        let header_start = self.pos;
+        self.pos += reserved;
+
         // Write the data
         let start = self.pos;
         f(self)?;
         let len = self.pos - start;
+        if len > MAX_LENGTH_DELIMITED {
+            return Err(WriteError);
+        }
 
         // Encode length header
-        let mut header = [0; 16];
+        let mut header = [0; MAX_VARUINT32_LEN];
         let mut header = ByteWriter::new(&mut header);
-        header.write_varuint32(len.try_into().unwrap())?;
+        header.write_varuint32(len as u32)?;
         let header = header.bytes();
 
-        // Move the data to make space for the header.
-        if self.buf.len() - self.pos < header.len() {
+        // Move the data to fit the header exactly.
+        let data_start = header_start + header.len();
+        if data_start > start && self.buf.capacity() - self.pos < data_start - start {
             return Err(WriteError);
         }
-        self.buf.copy_within(start..self.pos, start + header.len());
+        if data_start != start {
+            self.buf.move_to(start, self.pos, data_start)?;
+        }
 
         // Insert the header
-        self.buf[start..][..header.len()].copy_from_slice(header);
-        self.pos += header.len();
+        self.buf.put(header_start, header)?;
+        self.pos = data_start + len;
+        self.buf.truncate(self.pos);
 
         Ok(())
     }
@@ -131,11 +576,37 @@ impl<'a> ByteWriter<'a> {
         self.write_varuint32((tag << 3) | (M::WIRE_TYPE as u32))?;
 
         match M::WIRE_TYPE {
-            WireType::LengthDelimited => self.write_length_delimited(|w| msg.write_raw(w)),
+            WireType::LengthDelimited => self.write_message_delimited(msg),
             _ => msg.write_raw(self),
         }
     }
 
+    /// Write a message with its length header.
+    ///
+    /// Unless `M::MAX_ENCODED_LEN` guarantees a 1-byte header and the writer can patch it in, the
+    /// message length is computed first with a counting pass, so the header can be written before
+    /// the message and its bytes are written exactly once, instead of being moved to fit the
+    /// header.
+    fn write_message_delimited<M: Message>(&mut self, msg: &M) -> Result<(), WriteError> {
+        let short = varuint_len(M::MAX_ENCODED_LEN as u64) == 1 && !matches!(self.buf, Buf::Stream { .. });
+        if matches!(self.buf, Buf::Counting) || short {
+            return self.write_length_delimited_max(M::MAX_ENCODED_LEN, |w| msg.write_raw(w));
+        }
+
+        let len = msg.encoded_len()?;
+        if len > MAX_LENGTH_DELIMITED || self.buf.capacity() - self.pos < len {
+            return Err(WriteError);
+        }
+        self.write_varuint32(len as u32)?;
+        let start = self.pos;
+        msg.write_raw(self)?;
+        // The header is already written, the message must not change length between passes.
+        if self.pos - start != len {
+            return Err(WriteError);
+        }
+        Ok(())
+    }
+
     /// Write a repeated protobuf field to the buffer.
     pub fn write_repeated<M: RepeatedMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
         for i in msg.iter()? {
@@ -144,6 +615,40 @@ impl<'a> ByteWriter<'a> {
         Ok(())
     }
 
+    /// Write a repeated protobuf field to the buffer, packing scalar elements into a single
+    /// length-delimited field.
+    ///
+    /// Nothing is written if the field is empty. Elements of length-delimited types (strings,
+    /// bytes and messages) can't be packed and are written as with [`write_repeated`](Self::write_repeated).
+    pub fn write_packed<M: RepeatedMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        if matches!(M::Message::WIRE_TYPE, WireType::LengthDelimited) {
+            return self.write_repeated(tag, msg);
+        }
+
+        // Size the elements first, so they're written after their header without moving them.
+        let mut counter = ByteWriter::counting();
+        for i in msg.iter()? {
+            i.write_raw(&mut counter)?;
+        }
+        let len = counter.pos();
+        if len == 0 {
+            return Ok(());
+        }
+        if len > MAX_LENGTH_DELIMITED {
+            return Err(WriteError);
+        }
+        self.write_varuint32((tag << 3) | WireType::LengthDelimited as u32)?;
+        self.write_varuint32(len as u32)?;
+        let start = self.pos;
+        for i in msg.iter()? {
+            i.write_raw(self)?;
+        }
+        if self.pos - start != len {
+            return Err(WriteError);
+        }
+        Ok(())
+    }
+
     /// Write an optional protobuf field to the buffer.
     pub fn write_optional<M: OptionalMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
         if let Some(msg) = msg.get() {
@@ -152,12 +657,85 @@ impl<'a> ByteWriter<'a> {
         Ok(())
     }
 
+    /// Write a message as a group to the buffer, delimited by start-group and end-group headers
+    /// instead of a length prefix.
+    ///
+    /// Groups are deprecated in proto2, and back as the `DELIMITED` message encoding in protobuf
+    /// editions. Only messages made of fields can be groups, others fail to build:
+    ///
+    /// ```compile_fail
+    /// #[derive(noproto::Message, Default)]
+    /// struct Bad {
+    ///     #[noproto(tag = 3, group)]
+    ///     x: u32,
+    /// }
+    ///
+    /// noproto::write(&Bad::default(), &mut [0; 8]).unwrap();
+    /// ```
+    pub fn write_group<M: Message>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        const { assert_group::<M>() };
+        self.write_varuint32((tag << 3) | (WireType::StartGroup as u32))?;
+        msg.write_raw(self)?;
+        self.write_varuint32((tag << 3) | END_GROUP)
+    }
+
+    /// Write a repeated group field to the buffer.
+    pub fn write_repeated_group<M: RepeatedMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        for i in msg.iter()? {
+            self.write_group(tag, i)?;
+        }
+        Ok(())
+    }
+
+    /// Write an optional group field to the buffer.
+    pub fn write_optional_group<M: OptionalMessage>(&mut self, tag: u32, msg: &M) -> Result<(), WriteError> {
+        if let Some(msg) = msg.get() {
+            self.write_group(tag, msg)?;
+        }
+        Ok(())
+    }
+
     /// Write a oneof protobuf field to the buffer.
     pub fn write_oneof<M: Oneof>(&mut self, msg: &M) -> Result<(), WriteError> {
         msg.write_raw(self)
     }
 
-    pub(crate) fn pos(&self) -> usize {
+    /// Get the number of bytes written so far.
+    pub fn pos(&self) -> usize {
         self.pos
     }
+
+    /// Save the current position, to speculatively write data and roll it back with
+    /// [`restore`](Self::restore), e.g. if it turns out too large for the framing.
+    ///
+    /// ```
+    /// use noproto::encoding::ByteWriter;
+    ///
+    /// let mut buf = [0; 16];
+    /// let mut w = ByteWriter::new(&mut buf);
+    /// w.write_field(1, &7u32).unwrap();
+    ///
+    /// let cp = w.checkpoint();
+    /// let blob = heapless::Vec::<u8, 10>::from_slice(&[0xAA; 10]).unwrap();
+    /// let res = w.write_field(2, &blob);
+    /// if res.is_err() || w.pos() - cp.pos() > 8 {
+    ///     w.restore(cp);
+    /// }
+    /// assert_eq!(w.bytes(), [0x08, 0x07]);
+    /// ```
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { pos: self.pos }
+    }
+
+    /// Roll back to `checkpoint`, discarding everything written since, including partial
+    /// writes that failed.
+    ///
+    /// `checkpoint` must come from this writer. A checkpoint after the current position is
+    /// ignored, since the data up to it may have been rolled back already.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        if checkpoint.pos <= self.pos {
+            self.pos = checkpoint.pos;
+            self.buf.truncate(checkpoint.pos);
+        }
+    }
 }
@@ -0,0 +1,63 @@
+//! Messages covering the field kinds, each with the tests generated by `#[noproto(tests)]`.
+
+#[derive(noproto::Message, Default)]
+#[noproto(tests)]
+struct Scalars {
+    #[noproto(tag = 1)]
+    flag: bool,
+    #[noproto(tag = 2)]
+    small: u8,
+    #[noproto(tag = 3)]
+    count: u64,
+    #[noproto(tag = 4)]
+    delta: i32,
+    #[noproto(tag = 5)]
+    ratio: f32,
+    #[noproto(tag = 6, raw_le_bytes)]
+    raw: u32,
+}
+
+#[derive(noproto::Message, Default)]
+#[noproto(tests)]
+struct Texts {
+    #[noproto(tag = 1)]
+    name: heapless::String<16>,
+    #[noproto(tag = 2)]
+    data: heapless::Vec<u8, 8>,
+    #[noproto(tag = 3, compress)]
+    log: heapless::String<32>,
+}
+
+#[derive(noproto::Enumeration, Clone, Copy, PartialEq, Debug)]
+#[repr(i32)]
+enum Mode {
+    Off = 0,
+    On = 1,
+}
+
+#[derive(noproto::Oneof)]
+enum Payload {
+    #[noproto(tag = 10)]
+    Code(u32),
+    #[noproto(tag = 11)]
+    Text(heapless::String<8>),
+}
+
+#[derive(noproto::Message, Default)]
+#[noproto(tests)]
+struct Nested {
+    #[noproto(tag = 1)]
+    scalars: Scalars,
+    #[noproto(tag = 2, repeated)]
+    texts: heapless::Vec<Texts, 2>,
+    #[noproto(tag = 3, group)]
+    grouped: Scalars,
+    #[noproto(tag = 4, optional)]
+    maybe: Option<u32>,
+    #[noproto(tag = 5, packed)]
+    samples: heapless::Vec<i32, 4>,
+    #[noproto(tag = 6, enumeration = "Mode")]
+    mode: i32,
+    #[noproto(oneof, tags = "10, 11")]
+    payload: Option<Payload>,
+}